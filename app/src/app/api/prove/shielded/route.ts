@@ -9,7 +9,7 @@ import {
     writeProverToml,
     addressToField,
 } from '@/lib/proof-utils';
-import { poseidonHash } from '@/lib/poseidon';
+import { poseidonHash, randomField } from '@/lib/poseidon';
 import {
     ProofErrorCodes,
     createErrorResponse,
@@ -42,6 +42,9 @@ export async function POST(request: NextRequest) {
             recipient,
             mint,
             pool_id,
+            withdraw_amount,
+            change_secret,
+            change_nullifier,
         } = body;
 
         if (
@@ -88,6 +91,27 @@ export async function POST(request: NextRequest) {
 
         const nullifierHash = await poseidonHash([nullifier, mintField, poolField]);
 
+        // spends the whole note unless withdraw_amount is given, the rest
+        // goes into a change note (of 0 for a full spend)
+        const spendAmount = BigInt(withdraw_amount ?? amount);
+        const changeAmount = BigInt(amount) - spendAmount;
+        if (spendAmount < BigInt(0) || changeAmount < BigInt(0)) {
+            return createErrorResponse(
+                ProofErrorCodes.INVALID_AMOUNT,
+                'withdraw_amount must be between 0 and the note amount',
+                400
+            );
+        }
+        const changeSecret = change_secret ?? randomField();
+        const changeNullifier = change_nullifier ?? randomField();
+        const changeCommitment = await poseidonHash([
+            changeAmount.toString(),
+            changeSecret,
+            changeNullifier,
+            mintField,
+            poolField,
+        ]);
+
         const proverContent = `
 # Private inputs
 amount = "${amount}"
@@ -95,14 +119,18 @@ secret = "${secret}"
 nullifier = "${nullifier}"
 merkle_path = ${JSON.stringify(merkle_path.map((x: string | number) => x.toString()))}
 merkle_indices = ${JSON.stringify(merkle_indices)}
+change_amount = "${changeAmount}"
+change_secret = "${changeSecret}"
+change_nullifier = "${changeNullifier}"
 
 # Public inputs
 root = "${root}"
 nullifier_hash = "${nullifierHash}"
-amount_pub = "${amount}"
+amount_pub = "${spendAmount}"
 recipient = "${recipientField}"
 mint = "${mintField}"
 pool_id = "${poolField}"
+change_commitment = "${changeCommitment}"
 `.trim();
 
         await writeProverToml(config.circuitDir, proverContent);
@@ -120,12 +148,20 @@ pool_id = "${poolField}"
 
         try {
             const result = await generateFullProof(config, {
-                amount,
+                amount: spendAmount.toString(),
                 recipient,
                 mint,
                 pool_id,
             });
-            return NextResponse.json(result);
+            return NextResponse.json({
+                ...result,
+                changeNote: {
+                    amount: changeAmount.toString(),
+                    secret: changeSecret,
+                    nullifier: changeNullifier,
+                    commitment: changeCommitment,
+                },
+            });
         } catch (e) {
             return proofGenerationError(e instanceof Error ? e.message : String(e));
         }
@@ -140,8 +176,17 @@ export async function GET() {
         circuit: CIRCUIT_NAME,
         description: 'Shielded spend proof (note membership + nullifier + recipient binding)',
         inputs: {
-            private: ['amount', 'secret', 'nullifier', 'merkle_path', 'merkle_indices'],
-            public: ['root', 'nullifier_hash', 'amount_pub', 'recipient', 'mint', 'pool_id'],
+            private: [
+                'amount',
+                'secret',
+                'nullifier',
+                'merkle_path',
+                'merkle_indices',
+                'change_amount',
+                'change_secret',
+                'change_nullifier',
+            ],
+            public: ['root', 'nullifier_hash', 'amount_pub', 'recipient', 'mint', 'pool_id', 'change_commitment'],
         },
     });
 }
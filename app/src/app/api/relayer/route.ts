@@ -13,6 +13,11 @@ import {
     buildInstruction,
 } from './utils';
 
+// swap_private and withdraw_shielded fail up front with InsufficientComputeBudget
+// below 350k / 300k remaining units (MIN_COMPUTE_UNITS_* in shielded_pool.rs),
+// the 200k default budget is never enough
+const COMPUTE_UNIT_LIMIT = 400_000;
+
 export async function POST(req: Request) {
     try {
         const body = await req.json();
@@ -44,7 +49,7 @@ export async function POST(req: Request) {
         const instruction = buildInstruction(ixData, accounts, relayerKeypair, meta.nullifierPdaToUse);
 
         // build tx w/ extra compute
-        const computeBudgetIx = ComputeBudgetProgram.setComputeUnitLimit({ units: 400_000 });
+        const computeBudgetIx = ComputeBudgetProgram.setComputeUnitLimit({ units: COMPUTE_UNIT_LIMIT });
         const priorityIx = ComputeBudgetProgram.setComputeUnitPrice({ microLamports: 1_000 }); // ~1 lamport/cu
         const tx = new Transaction().add(computeBudgetIx, priorityIx, instruction);
         const { blockhash, lastValidBlockHeight } = await connection.getLatestBlockhash();
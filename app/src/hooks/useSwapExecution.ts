@@ -9,6 +9,7 @@ import {
     COMPUTE_UNITS,
     PRIORITY_FEE_MICROLAMPORTS,
     LAMPORTS_MULTIPLIER,
+    SWAP_DEADLINE_SECS,
 } from '@/lib/constants';
 import { SwapDirection } from '@/types/swap';
 import { addressToField, fieldToLeBytes32 } from '@/lib/proof-fields';
@@ -107,7 +108,7 @@ export function useSwapExecution({
                 console.log("Depositing funds to Shielded Pool...");
 
                 const depositIx = await program.methods
-                    .deposit(amountInLamports, commitmentBytes)
+                    .deposit(amountInLamports, commitmentBytes, null) // no opening, the note stays private
                     .accounts({
                         shieldedPool: inputShieldedPool,
                         vault: inputShieldedVault,
@@ -184,6 +185,10 @@ export function useSwapExecution({
                 const nullifierHashBytes = Array.from(
                     publicInputsBytes.slice(headerOffset + 32, headerOffset + 64)
                 );
+                // the note is spent in full, so this is a zero-value change note
+                const changeCommitmentBytes = Array.from(
+                    publicInputsBytes.slice(headerOffset + 192, headerOffset + 224)
+                );
                 const [nullifierPda] = PublicKey.findProgramAddressSync(
                     [
                         Buffer.from('nullifier'),
@@ -197,18 +202,24 @@ export function useSwapExecution({
                 console.log("Constructing private transaction for Relayer...");
 
                 const ix = await program.methods
-                    .swapPrivate(
-                        Buffer.from(proofData.proof),
-                        Buffer.from(publicInputsBytes),
-                        amountInLamports,
-                        minOutLamports,
-                        isAtoB, // Direction flag
-                        nullifierHashBytes
-                    )
+                    .swapPrivate({
+                        proof: Buffer.from(proofData.proof),
+                        publicInputs: Buffer.from(publicInputsBytes),
+                        amountIn: amountInLamports,
+                        minOut: minOutLamports,
+                        acceptAnyOutput: false, // min_out is always set from the slippage setting
+                        deadline: new BN(Math.floor(Date.now() / 1000) + SWAP_DEADLINE_SECS),
+                        isAToB: isAtoB, // Direction flag
+                        nullifierHash: nullifierHashBytes,
+                        changeCommitment: changeCommitmentBytes,
+                        rootIndex: null, // the program scans the history without a hint
+                    })
                     .accounts({
                         pool: poolConfig.poolPda,
                         inputShieldedPool: inputShieldedPool,
                         inputRootHistory: inputRootHistory,
+                        tokenAMint: poolConfig.tokenAMint,
+                        tokenBMint: poolConfig.tokenBMint,
                         verifierProgram: poolConfig.shieldedVerifierProgramId,
                         nullifierAccount: nullifierPda,
                         tokenProgram: TOKEN_PROGRAM_ID,
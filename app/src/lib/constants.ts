@@ -17,6 +17,7 @@ export const LAMPORTS_MULTIPLIER = 1e9;
 // Transaction configuration
 export const COMPUTE_UNITS = 500_000;
 export const PRIORITY_FEE_MICROLAMPORTS = 1_000;
+export const SWAP_DEADLINE_SECS = 120; // swaps still pending after this fail on-chain
 
 // UI configuration
 export const BALANCE_POLL_INTERVAL_MS = 30_000;
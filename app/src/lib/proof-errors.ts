@@ -14,6 +14,7 @@ export const ProofErrorCodes = {
     BALANCE_BELOW_THRESHOLD: 'BALANCE_BELOW_THRESHOLD',
     INSUFFICIENT_HOLDINGS: 'INSUFFICIENT_HOLDINGS',
     ADDRESS_BLACKLISTED: 'ADDRESS_BLACKLISTED',
+    INVALID_AMOUNT: 'INVALID_AMOUNT',
 
     // Setup/tool errors (5xx)
     NARGO_NOT_FOUND: 'NARGO_NOT_FOUND',
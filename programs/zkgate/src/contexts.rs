@@ -1,8 +1,11 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use anchor_spl::associated_token::AssociatedToken;
-use crate::state::Pool;
-use crate::state::shielded::{ShieldedPool, ShieldedRootHistory, Nullifier};
+use crate::errors::ErrorCode;
+use crate::state::{FeeDistribution, Pool, ProofCommitment};
+use crate::state::shielded::{
+    ShieldedPool, ShieldedRootHistory, ShieldedMerkleTree, ShieldedMintAllowlist, Nullifier, VerifiedProof,
+};
 
 #[derive(Accounts)]
 pub struct CreatePool<'info> {
@@ -14,72 +17,325 @@ pub struct CreatePool<'info> {
         bump
     )]
     pub pool: Account<'info, Pool>,
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"lp_mint", pool.key().as_ref()],
+        bump,
+        mint::decimals = 9,
+        mint::authority = pool
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+    /// pool-owned lp account holding the MINIMUM_LIQUIDITY shares, never spent
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"lp_lock", pool.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = pool
+    )]
+    pub lp_lock: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut)]
     pub user: Signer<'info>,
     pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
+/// create_pool with both reserve atas initialized in the same transaction,
+/// so a pool can't end up pointing at reserves it doesn't own
 #[derive(Accounts)]
-pub struct AddLiquidity<'info> {
+pub struct CreatePoolWithReserves<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = Pool::LEN,
+        seeds = [b"pool", token_a_mint.key().as_ref(), token_b_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"lp_mint", pool.key().as_ref()],
+        bump,
+        mint::decimals = 9,
+        mint::authority = pool
+    )]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    /// pool-owned lp account holding the MINIMUM_LIQUIDITY shares, never spent
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"lp_lock", pool.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = pool
+    )]
+    pub lp_lock: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = token_a_mint,
+        associated_token::authority = pool,
+        associated_token::token_program = token_program
+    )]
+    pub token_a_reserve: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = token_b_mint,
+        associated_token::authority = pool,
+        associated_token::token_program = token_program
+    )]
+    pub token_b_reserve: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(mut, has_one = token_a_mint, has_one = token_b_mint)]
     pub pool: Account<'info, Pool>,
     #[account(mut, constraint = user_token_a.mint == pool.token_a_mint, constraint = user_token_a.owner == user.key())]
-    pub user_token_a: Account<'info, TokenAccount>,
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
     #[account(mut, constraint = user_token_b.mint == pool.token_b_mint, constraint = user_token_b.owner == user.key())]
-    pub user_token_b: Account<'info, TokenAccount>,
-    #[account(mut, constraint = token_a_reserve.mint == pool.token_a_mint)]
-    pub token_a_reserve: Account<'info, TokenAccount>,
-    #[account(mut, constraint = token_b_reserve.mint == pool.token_b_mint)]
-    pub token_b_reserve: Account<'info, TokenAccount>,
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_a_reserve.mint == pool.token_a_mint,
+        constraint = token_a_reserve.owner == pool.key(),
+        constraint = !token_a_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_a_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_b_reserve.mint == pool.token_b_mint,
+        constraint = token_b_reserve.owner == pool.key(),
+        constraint = !token_b_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_b_reserve: InterfaceAccount<'info, TokenAccount>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, constraint = user_lp.mint == pool.lp_mint, constraint = user_lp.owner == user.key())]
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, seeds = [b"lp_lock", pool.key().as_ref()], bump)]
+    pub lp_lock: Box<InterfaceAccount<'info, TokenAccount>>,
     pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// add_liquidity from one side only. part of the deposit is swapped as a
+/// proof-gated swap, so the other side's accounts and the verifier are needed
+#[derive(Accounts)]
+#[instruction(amount_in: u64, is_token_a: bool)]
+pub struct AddLiquiditySingle<'info> {
+    #[account(mut, constraint = !pool.paused @ ErrorCode::PoolPaused)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(address = if is_token_a { pool.token_a_mint } else { pool.token_b_mint })]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = if is_token_a { pool.token_b_mint } else { pool.token_a_mint })]
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, constraint = user_token_in.mint == mint_in.key(), constraint = user_token_in.owner == user.key())]
+    pub user_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    // receives the swap output before it is deposited with the rest
+    #[account(mut, constraint = user_token_out.mint == mint_out.key(), constraint = user_token_out.owner == user.key())]
+    pub user_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = reserve_in.mint == mint_in.key(),
+        constraint = reserve_in.owner == pool.key(),
+        constraint = !reserve_in.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub reserve_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = reserve_out.mint == mint_out.key(),
+        constraint = reserve_out.owner == pool.key(),
+        constraint = !reserve_out.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub reserve_out: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, constraint = user_lp.mint == pool.lp_mint, constraint = user_lp.owner == user.key())]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub user: Signer<'info>,
+    /// CHECK: validated in verify_zk_proof
+    pub verifier_program: UncheckedAccount<'info>,
+    /// CHECK: forwarded to the verifier when pool.verifier_requires_state is set
+    pub verifier_state: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub history: Box<Account<'info, crate::state::roots::StateRootHistory>>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(mut, has_one = token_a_mint, has_one = token_b_mint)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, constraint = user_token_a.mint == pool.token_a_mint, constraint = user_token_a.owner == user.key())]
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = user_token_b.mint == pool.token_b_mint, constraint = user_token_b.owner == user.key())]
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_a_reserve.mint == pool.token_a_mint,
+        constraint = token_a_reserve.owner == pool.key(),
+        constraint = !token_a_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_a_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_b_reserve.mint == pool.token_b_mint,
+        constraint = token_b_reserve.owner == pool.key(),
+        constraint = !token_b_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_b_reserve: InterfaceAccount<'info, TokenAccount>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, constraint = user_lp.mint == pool.lp_mint, constraint = user_lp.owner == user.key())]
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
 pub struct ZKSwap<'info> {
-    #[account(mut)]
+    #[account(mut, has_one = token_a_mint, has_one = token_b_mint, constraint = !pool.paused @ ErrorCode::PoolPaused)]
     pub pool: Account<'info, Pool>,
     #[account(mut, constraint = user_token_a.mint == pool.token_a_mint, constraint = user_token_a.owner == user.key())]
-    pub user_token_a: Account<'info, TokenAccount>,
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
     #[account(mut, constraint = user_token_b.mint == pool.token_b_mint, constraint = user_token_b.owner == user.key())]
-    pub user_token_b: Account<'info, TokenAccount>,
-    #[account(mut, constraint = token_a_reserve.mint == pool.token_a_mint)]
-    pub token_a_reserve: Account<'info, TokenAccount>,
-    #[account(mut, constraint = token_b_reserve.mint == pool.token_b_mint)]
-    pub token_b_reserve: Account<'info, TokenAccount>,
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_a_reserve.mint == pool.token_a_mint,
+        constraint = token_a_reserve.owner == pool.key(),
+        constraint = !token_a_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_a_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_b_reserve.mint == pool.token_b_mint,
+        constraint = token_b_reserve.owner == pool.key(),
+        constraint = !token_b_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_b_reserve: InterfaceAccount<'info, TokenAccount>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
     pub user: Signer<'info>,
     /// CHECK: validated in verify_zk_proof
     pub verifier_program: UncheckedAccount<'info>,
-    /// CHECK: required by deployed program
+    /// CHECK: forwarded to the verifier when pool.verifier_requires_state is set
     pub verifier_state: UncheckedAccount<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     #[account(mut)]
     pub history: Box<Account<'info, crate::state::roots::StateRootHistory>>,
+    /// receives the token b output in place of user_token_b, so a relayer
+    /// can sign and pay for a swap on someone else's behalf
+    #[account(mut, constraint = recipient_token_b.mint == pool.token_b_mint @ ErrorCode::InvalidRecipient)]
+    pub recipient_token_b: Option<InterfaceAccount<'info, TokenAccount>>,
 }
 
 #[derive(Accounts)]
 pub struct ZKSwapReverse<'info> {
-    #[account(mut)]
+    #[account(mut, has_one = token_a_mint, has_one = token_b_mint, constraint = !pool.paused @ ErrorCode::PoolPaused)]
     pub pool: Account<'info, Pool>,
     #[account(mut, constraint = user_token_a.mint == pool.token_a_mint, constraint = user_token_a.owner == user.key())]
-    pub user_token_a: Account<'info, TokenAccount>,
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
     #[account(mut, constraint = user_token_b.mint == pool.token_b_mint, constraint = user_token_b.owner == user.key())]
-    pub user_token_b: Account<'info, TokenAccount>,
-    #[account(mut, constraint = token_a_reserve.mint == pool.token_a_mint)]
-    pub token_a_reserve: Account<'info, TokenAccount>,
-    #[account(mut, constraint = token_b_reserve.mint == pool.token_b_mint)]
-    pub token_b_reserve: Account<'info, TokenAccount>,
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_a_reserve.mint == pool.token_a_mint,
+        constraint = token_a_reserve.owner == pool.key(),
+        constraint = !token_a_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_a_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_b_reserve.mint == pool.token_b_mint,
+        constraint = token_b_reserve.owner == pool.key(),
+        constraint = !token_b_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_b_reserve: InterfaceAccount<'info, TokenAccount>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
     pub user: Signer<'info>,
     /// CHECK: validated in verify_zk_proof
     pub verifier_program: UncheckedAccount<'info>,
-    /// CHECK: required by deployed program
+    /// CHECK: forwarded to the verifier when pool.verifier_requires_state is set
     pub verifier_state: UncheckedAccount<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(mut)]
+    pub history: Box<Account<'info, crate::state::roots::StateRootHistory>>,
+}
+
+/// two-hop exact-in swap, a -> b on first_pool then b -> c on second_pool.
+/// the intermediate mint moves reserve to reserve and never reaches the user
+#[derive(Accounts)]
+pub struct SwapRouteTwo<'info> {
+    #[account(mut, constraint = !first_pool.paused @ ErrorCode::PoolPaused)]
+    pub first_pool: Box<Account<'info, Pool>>,
+    #[account(mut, constraint = !second_pool.paused @ ErrorCode::PoolPaused)]
+    pub second_pool: Box<Account<'info, Pool>>,
+    #[account(mut, constraint = user_token_in.mint == first_pool.token_a_mint, constraint = user_token_in.owner == user.key())]
+    pub user_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = user_token_out.mint == second_pool.token_b_mint, constraint = user_token_out.owner == user.key())]
+    pub user_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = first_reserve_in.mint == first_pool.token_a_mint,
+        constraint = first_reserve_in.owner == first_pool.key(),
+        constraint = !first_reserve_in.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub first_reserve_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = first_reserve_out.mint == first_pool.token_b_mint,
+        constraint = first_reserve_out.owner == first_pool.key(),
+        constraint = !first_reserve_out.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub first_reserve_out: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = second_reserve_in.mint == second_pool.token_a_mint,
+        constraint = second_reserve_in.owner == second_pool.key(),
+        constraint = !second_reserve_in.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub second_reserve_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = second_reserve_out.mint == second_pool.token_b_mint,
+        constraint = second_reserve_out.owner == second_pool.key(),
+        constraint = !second_reserve_out.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub second_reserve_out: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = first_pool.token_a_mint)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = first_pool.token_b_mint)]
+    pub mint_mid: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = second_pool.token_b_mint)]
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: validated in verify_zk_proof
+    pub verifier_program: UncheckedAccount<'info>,
+    /// CHECK: forwarded to the verifier when first_pool.verifier_requires_state is set
+    pub verifier_state: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub history: Box<Account<'info, crate::state::roots::StateRootHistory>>,
 }
 
@@ -88,23 +344,302 @@ pub struct GetPoolInfo<'info> {
     pub pool: Account<'info, Pool>,
 }
 
+#[derive(Accounts)]
+pub struct GetLpPosition<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct DeriveSwapAccounts {}
+
+#[derive(Accounts)]
+pub struct ComparePools<'info> {
+    pub first_pool: Account<'info, Pool>,
+    pub second_pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct CommitProof<'info> {
+    #[account(constraint = !pool.paused @ ErrorCode::PoolPaused)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = ProofCommitment::LEN,
+        seeds = [b"proof_commitment", pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub commitment: Account<'info, ProofCommitment>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: validated in verify_zk_proof
+    pub verifier_program: UncheckedAccount<'info>,
+    /// CHECK: forwarded to the verifier when pool.verifier_requires_state is set
+    pub verifier_state: UncheckedAccount<'info>,
+    pub history: Box<Account<'info, crate::state::roots::StateRootHistory>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleSwap<'info> {
+    #[account(mut, has_one = token_a_mint, has_one = token_b_mint, constraint = !pool.paused @ ErrorCode::PoolPaused)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        close = user,
+        has_one = pool,
+        has_one = user,
+        seeds = [b"proof_commitment", pool.key().as_ref(), user.key().as_ref()],
+        bump = commitment.bump
+    )]
+    pub commitment: Account<'info, ProofCommitment>,
+    #[account(mut, constraint = user_token_a.mint == pool.token_a_mint, constraint = user_token_a.owner == user.key())]
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = user_token_b.mint == pool.token_b_mint, constraint = user_token_b.owner == user.key())]
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_a_reserve.mint == pool.token_a_mint,
+        constraint = token_a_reserve.owner == pool.key(),
+        constraint = !token_a_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_a_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = token_b_reserve.mint == pool.token_b_mint,
+        constraint = token_b_reserve.owner == pool.key(),
+        constraint = !token_b_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub token_b_reserve: InterfaceAccount<'info, TokenAccount>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ManagePool<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateReserve<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        constraint = old_reserve.mint == pool.token_a_mint || old_reserve.mint == pool.token_b_mint,
+        constraint = old_reserve.owner == pool.key(),
+        constraint = !old_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub old_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = new_reserve.key() != old_reserve.key(),
+        constraint = new_reserve.mint == old_reserve.mint,
+        constraint = new_reserve.owner == pool.key(),
+        constraint = !new_reserve.is_frozen() @ ErrorCode::ReserveFrozen
+    )]
+    pub new_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = old_reserve.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// grows a shielded pool account from the original layout to the current
+/// one. it can't be an Account<ShieldedPool> until the migration has run
+#[derive(Accounts)]
+pub struct MigrateShieldedPool<'info> {
+    /// CHECK: layout, discriminator and authority are read from the raw bytes
+    #[account(mut)]
+    pub shielded_pool: UncheckedAccount<'info>,
+    /// CHECK: only inspected to see whether the pool's tree exists
+    #[account(seeds = [b"shielded_tree", shielded_pool.key().as_ref()], bump)]
+    pub merkle_tree: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// grows a shielded root history created before written_at, which can't
+/// be loaded as ShieldedRootHistory until the migration has run
+#[derive(Accounts)]
+pub struct MigrateShieldedRootHistory<'info> {
+    #[account(has_one = authority, has_one = root_history)]
+    pub shielded_pool: Account<'info, ShieldedPool>,
+    /// CHECK: layout, discriminator and pool are read from the raw bytes
+    #[account(mut)]
+    pub root_history: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// rewrites a state root history created with the fixed 100-root array
+#[derive(Accounts)]
+pub struct MigrateRootHistory<'info> {
+    /// CHECK: layout, discriminator and authority are read from the raw bytes
+    #[account(mut)]
+    pub history: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// recreates a pool's canonical reserve ata after it was closed, paid by the authority
+#[derive(Accounts)]
+pub struct RecreateReserve<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(constraint = mint.key() == pool.token_a_mint || mint.key() == pool.token_b_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = mint,
+        associated_token::authority = pool,
+        associated_token::token_program = token_program
+    )]
+    pub reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// closes an emptied pool together with its reserve atas and lp lock,
+/// all rent goes back to the authority
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
+    #[account(mut, has_one = authority, has_one = token_a_mint, has_one = token_b_mint, has_one = lp_mint, close = authority)]
+    pub pool: Account<'info, Pool>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = token_a_mint,
+        associated_token::authority = pool,
+        associated_token::token_program = token_program
+    )]
+    pub token_a_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = token_b_mint,
+        associated_token::authority = pool,
+        associated_token::token_program = token_program
+    )]
+    pub token_b_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [b"lp_lock", pool.key().as_ref()], bump)]
+    pub lp_lock: Box<InterfaceAccount<'info, TokenAccount>>,
+    // receive the dust backing the locked shares
+    #[account(mut, constraint = authority_token_a.mint == pool.token_a_mint, constraint = authority_token_a.owner == authority.key())]
+    pub authority_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = authority_token_b.mint == pool.token_b_mint, constraint = authority_token_b.owner == authority.key())]
+    pub authority_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeDistribution<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = FeeDistribution::LEN,
+        seeds = [b"fee_distribution", pool.key().as_ref()],
+        bump
+    )]
+    pub fee_distribution: Account<'info, FeeDistribution>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeFees<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        seeds = [b"fee_distribution", pool.key().as_ref()],
+        bump = fee_distribution.bump,
+        has_one = pool
+    )]
+    pub fee_distribution: Account<'info, FeeDistribution>,
+    // remaining accounts: one recipient token account per configured recipient, in order
+    #[account(
+        mut,
+        constraint = reserve.mint == pool.token_a_mint || reserve.mint == pool.token_b_mint,
+        constraint = reserve.owner == pool.key()
+    )]
+    pub reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = reserve.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CollectFees<'info> {
+    #[account(mut, has_one = authority, has_one = token_a_mint, has_one = token_b_mint)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, constraint = token_a_reserve.mint == pool.token_a_mint, constraint = token_a_reserve.owner == pool.key())]
+    pub token_a_reserve: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = token_b_reserve.mint == pool.token_b_mint, constraint = token_b_reserve.owner == pool.key())]
+    pub token_b_reserve: InterfaceAccount<'info, TokenAccount>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, constraint = authority_token_a.mint == pool.token_a_mint, constraint = authority_token_a.owner == authority.key())]
+    pub authority_token_a: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = authority_token_b.mint == pool.token_b_mint, constraint = authority_token_b.owner == authority.key())]
+    pub authority_token_b: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
 // -----------------------------------------------------------------------------
 // shielded swap context
 // -----------------------------------------------------------------------------
 
 #[derive(Accounts)]
 pub struct SwapPrivate<'info> {
-    #[account(mut)]
+    #[account(mut, has_one = token_a_mint, has_one = token_b_mint, constraint = !pool.paused @ ErrorCode::PoolPaused)]
     pub pool: Account<'info, Pool>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = !input_shielded_pool.paused @ ErrorCode::PoolPaused,
+        constraint = !input_shielded_pool.has_tree || input_merkle_tree.is_some() @ ErrorCode::MerkleTreeRequired
+    )]
     pub input_shielded_pool: Account<'info, ShieldedPool>,
     #[account(mut)]
     pub input_root_history: AccountLoader<'info, ShieldedRootHistory>,
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
     // remaining accounts:
     // 0: shielded_vault_in (writable)
     // 1: reserve_in (writable)
     // 2: reserve_out (writable)
     // 3: recipient_token (writable)
+    // 4: verified_proof cache (optional, writable, closed to the relayer when used)
+    // then the pool's swap hook program and its accounts, when it has one
     /// CHECK: validated by cpi verifier and public inputs
     pub verifier_program: UncheckedAccount<'info>,
     /// CHECK: pda derived from input shielded pool and nullifier hash
@@ -112,7 +647,29 @@ pub struct SwapPrivate<'info> {
     pub nullifier_account: UncheckedAccount<'info>,
     #[account(mut)]
     pub relayer: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    // input pools with an on-chain tree pass it so the change note is inserted here
+    #[account(mut, seeds = [b"shielded_tree", input_shielded_pool.key().as_ref()], bump)]
+    pub input_merkle_tree: Option<AccountLoader<'info, ShieldedMerkleTree>>,
+}
+
+#[derive(Accounts)]
+#[instruction(proof: Vec<u8>, public_inputs: Vec<u8>, nullifier_hash: [u8; 32])]
+pub struct CacheProofVerification<'info> {
+    pub input_shielded_pool: Account<'info, ShieldedPool>,
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = VerifiedProof::LEN,
+        seeds = [b"verified_proof", input_shielded_pool.key().as_ref(), nullifier_hash.as_ref()],
+        bump
+    )]
+    pub verified_proof: Account<'info, VerifiedProof>,
+    /// CHECK: validated by cpi verifier, swap_private only accepts the cache for the same program
+    pub verifier_program: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub relayer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
@@ -130,19 +687,54 @@ pub struct InitializeShieldedPool<'info> {
         bump
     )]
     pub shielded_pool: Account<'info, ShieldedPool>,
-    pub mint: Account<'info, Mint>,
+    pub mint: InterfaceAccount<'info, Mint>,
     #[account(
         init,
         payer = authority,
         associated_token::mint = mint,
         associated_token::authority = shielded_pool
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
     pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
+    /// CHECK: fixed PDA, may not exist yet; read in check_mint_allowed
+    #[account(seeds = [b"shielded_mint_allowlist"], bump)]
+    pub mint_allowlist: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeShieldedMintAllowlist<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = ShieldedMintAllowlist::LEN,
+        seeds = [b"shielded_mint_allowlist"],
+        bump
+    )]
+    pub mint_allowlist: Account<'info, ShieldedMintAllowlist>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    // the allow-list pda is global, only the program's upgrade authority may create it
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ ErrorCode::NotUpgradeAuthority)]
+    pub program: Program<'info, crate::program::Zkgate>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()) @ ErrorCode::NotUpgradeAuthority)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateShieldedMintAllowlist<'info> {
+    #[account(
+        mut,
+        seeds = [b"shielded_mint_allowlist"],
+        bump = mint_allowlist.bump,
+        has_one = authority
+    )]
+    pub mint_allowlist: Account<'info, ShieldedMintAllowlist>,
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
@@ -163,16 +755,65 @@ pub struct InitializeShieldedRootHistory<'info> {
 }
 
 #[derive(Accounts)]
-pub struct DepositShielded<'info> {
+pub struct InitializeShieldedTree<'info> {
+    #[account(mut, has_one = authority)]
+    pub shielded_pool: Account<'info, ShieldedPool>,
+    #[account(
+        init,
+        payer = authority,
+        space = ShieldedMerkleTree::LEN,
+        seeds = [b"shielded_tree", shielded_pool.key().as_ref()],
+        bump
+    )]
+    pub merkle_tree: AccountLoader<'info, ShieldedMerkleTree>,
     #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositShielded<'info> {
+    #[account(mut, constraint = !shielded_pool.paused @ ErrorCode::PoolPaused)]
     pub shielded_pool: Account<'info, ShieldedPool>,
     #[account(mut)]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub user_token: Account<'info, TokenAccount>,
+    pub user_token: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = shielded_pool.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
     pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    // pools with an on-chain tree pass both, the commitment is inserted and
+    // the new root recorded here instead of by update_shielded_root
+    #[account(mut, seeds = [b"shielded_tree", shielded_pool.key().as_ref()], bump)]
+    pub merkle_tree: Option<AccountLoader<'info, ShieldedMerkleTree>>,
+    #[account(mut, address = shielded_pool.root_history)]
+    pub root_history: Option<AccountLoader<'info, ShieldedRootHistory>>,
+}
+
+#[derive(Accounts)]
+pub struct DepositAndCommit<'info> {
+    #[account(
+        mut,
+        has_one = vault,
+        has_one = mint,
+        has_one = root_history,
+        constraint = !shielded_pool.paused @ ErrorCode::PoolPaused
+    )]
+    pub shielded_pool: Account<'info, ShieldedPool>,
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = user_token.mint == shielded_pool.mint)]
+    pub user_token: InterfaceAccount<'info, TokenAccount>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(mut, seeds = [b"shielded_tree", shielded_pool.key().as_ref()], bump)]
+    pub merkle_tree: AccountLoader<'info, ShieldedMerkleTree>,
+    #[account(mut)]
+    pub root_history: AccountLoader<'info, ShieldedRootHistory>,
 }
 
 #[derive(Accounts)]
@@ -184,12 +825,26 @@ pub struct UpdateShieldedRoot<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct SyncShieldedRoot<'info> {
+    #[account(mut, has_one = authority, has_one = root_history)]
+    pub shielded_pool: Account<'info, ShieldedPool>,
+    pub root_history: AccountLoader<'info, ShieldedRootHistory>,
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct WithdrawShielded<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = !shielded_pool.paused @ ErrorCode::PoolPaused,
+        constraint = !shielded_pool.has_tree || merkle_tree.is_some() @ ErrorCode::MerkleTreeRequired
+    )]
     pub shielded_pool: Account<'info, ShieldedPool>,
     #[account(mut)]
     pub root_history: AccountLoader<'info, ShieldedRootHistory>,
+    #[account(address = shielded_pool.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
     // remaining accounts:
     // 0: vault (writable)
     // 1: recipient_token (writable)
@@ -200,6 +855,111 @@ pub struct WithdrawShielded<'info> {
     pub nullifier_account: UncheckedAccount<'info>,
     #[account(mut)]
     pub relayer: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+    // pools with an on-chain tree pass it so the change note is inserted here
+    #[account(mut, seeds = [b"shielded_tree", shielded_pool.key().as_ref()], bump)]
+    pub merkle_tree: Option<AccountLoader<'info, ShieldedMerkleTree>>,
+}
+
+/// withdraw_shielded for several notes paid out in one transfer
+#[derive(Accounts)]
+pub struct WithdrawShieldedBatch<'info> {
+    #[account(
+        mut,
+        has_one = vault,
+        constraint = !shielded_pool.paused @ ErrorCode::PoolPaused,
+        constraint = !shielded_pool.has_tree || merkle_tree.is_some() @ ErrorCode::MerkleTreeRequired
+    )]
+    pub shielded_pool: Account<'info, ShieldedPool>,
+    #[account(mut)]
+    pub root_history: AccountLoader<'info, ShieldedRootHistory>,
+    #[account(address = shielded_pool.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, constraint = recipient_token.mint == shielded_pool.mint @ ErrorCode::InvalidShieldedAccount)]
+    pub recipient_token: Box<InterfaceAccount<'info, TokenAccount>>,
+    // remaining accounts: one nullifier pda per spend, in spend order (writable)
+    /// CHECK: validated by cpi verifier and public inputs
+    pub verifier_program: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    #[account(mut, seeds = [b"shielded_tree", shielded_pool.key().as_ref()], bump)]
+    pub merkle_tree: Option<AccountLoader<'info, ShieldedMerkleTree>>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawShieldedSol<'info> {
+    #[account(
+        mut,
+        has_one = vault,
+        constraint = !shielded_pool.paused @ ErrorCode::PoolPaused,
+        constraint = !shielded_pool.has_tree || merkle_tree.is_some() @ ErrorCode::MerkleTreeRequired
+    )]
+    pub shielded_pool: Account<'info, ShieldedPool>,
+    #[account(mut)]
+    pub root_history: AccountLoader<'info, ShieldedRootHistory>,
+    #[account(
+        address = shielded_pool.mint,
+        constraint = shielded_pool.check_native_mint().is_ok() @ ErrorCode::NotNativeMint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    // holds the withdrawn wsol for the length of the instruction, closed
+    // again before it returns
+    #[account(
+        init,
+        payer = relayer,
+        seeds = [b"unwrap", shielded_pool.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = shielded_pool,
+        token::token_program = token_program
+    )]
+    pub unwrap_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub recipient: SystemAccount<'info>,
+    /// CHECK: validated by cpi verifier and public inputs
+    pub verifier_program: UncheckedAccount<'info>,
+    /// CHECK: pda derived from shielded pool and nullifier hash
+    #[account(mut)]
+    pub nullifier_account: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    #[account(mut, seeds = [b"shielded_tree", shielded_pool.key().as_ref()], bump)]
+    pub merkle_tree: Option<AccountLoader<'info, ShieldedMerkleTree>>,
+}
+
+#[derive(Accounts)]
+pub struct GetShieldedPool<'info> {
+    pub shielded_pool: Account<'info, ShieldedPool>,
+}
+
+#[derive(Accounts)]
+pub struct NullifierRent {}
+
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(mut, has_one = authority, has_one = vault, has_one = mint)]
+    pub shielded_pool: Account<'info, ShieldedPool>,
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = authority_token.mint == shielded_pool.mint @ ErrorCode::InvalidShieldedAccount)]
+    pub authority_token: InterfaceAccount<'info, TokenAccount>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ManageShieldedPool<'info> {
+    #[account(mut, has_one = authority)]
+    pub shielded_pool: Account<'info, ShieldedPool>,
+    pub authority: Signer<'info>,
 }
@@ -0,0 +1,66 @@
+use anchor_lang::prelude::*;
+
+use crate::state::shielded::hash_pair;
+
+/// keccak256(amount_le || secret || recipient), the leaf of a plain
+/// (non-zk) note. deposits can only check it against a revealed opening,
+/// so it's meant for tests and the on-chain tree, not for private notes
+pub fn compute_commitment(amount: u64, secret: [u8; 32], recipient: &Pubkey) -> [u8; 32] {
+    solana_keccak_hasher::hashv(&[&amount.to_le_bytes(), &secret, recipient.as_ref()]).to_bytes()
+}
+
+/// recomputes the root of ShieldedMerkleTree from `leaf` and its siblings,
+/// bottom level first. bit `i` of `path_indices` is set when the node at
+/// level `i` is a right child, i.e. `path_indices` is the leaf index
+pub fn verify_merkle_path(leaf: [u8; 32], path_elements: &[[u8; 32]], path_indices: u64, root: [u8; 32]) -> bool {
+    if path_elements.len() < 64 && path_indices >> path_elements.len() != 0 {
+        return false;
+    }
+    let mut node = leaf;
+    for (level, sibling) in path_elements.iter().enumerate() {
+        node = if (path_indices >> level) & 1 == 0 {
+            hash_pair(&node, sibling)
+        } else {
+            hash_pair(sibling, &node)
+        };
+    }
+    node == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_commitment_binds_every_input() {
+        let recipient = Pubkey::new_unique();
+        let commitment = compute_commitment(100, [7; 32], &recipient);
+        assert_eq!(commitment, compute_commitment(100, [7; 32], &recipient));
+        assert_ne!(commitment, compute_commitment(101, [7; 32], &recipient));
+        assert_ne!(commitment, compute_commitment(100, [8; 32], &recipient));
+        assert_ne!(commitment, compute_commitment(100, [7; 32], &Pubkey::new_unique()));
+        assert_ne!(commitment, [0; 32]);
+    }
+
+    #[test]
+    fn test_verify_merkle_path_matches_tree_inserts() {
+        use crate::state::shielded::{ShieldedMerkleTree, SHIELDED_TREE_DEPTH};
+
+        let mut tree: ShieldedMerkleTree = bytemuck::Zeroable::zeroed();
+        tree.init(Pubkey::new_unique());
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        for leaf in leaves {
+            tree.insert(leaf).unwrap();
+        }
+
+        // leaf 2 sits next to an empty slot, above it is hash(leaf 0, leaf 1)
+        let mut path = tree.zeros.to_vec();
+        path[1] = hash_pair(&leaves[0], &leaves[1]);
+        assert!(verify_merkle_path(leaves[2], &path, 2, tree.root));
+
+        assert!(!verify_merkle_path(leaves[2], &path, 3, tree.root));
+        assert!(!verify_merkle_path(leaves[1], &path, 2, tree.root));
+        assert!(!verify_merkle_path(leaves[2], &path, 2 | 1 << SHIELDED_TREE_DEPTH, tree.root));
+        assert!(!verify_merkle_path(leaves[2], &path[..SHIELDED_TREE_DEPTH - 1], 2, tree.root));
+    }
+}
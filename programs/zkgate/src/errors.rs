@@ -28,4 +28,172 @@ pub enum ErrorCode {
 
     #[msg("Nullifier already spent")]
     NullifierAlreadySpent,
+
+    #[msg("Pool reserve account is frozen")]
+    ReserveFrozen,
+
+    #[msg("Pool PDA does not match its stored seeds")]
+    InvalidPoolPda,
+
+    #[msg("Fee distribution weights are invalid")]
+    InvalidFeeDistribution,
+
+    #[msg("Fee recipient account does not match the distribution")]
+    InvalidFeeRecipient,
+
+    #[msg("Mint is not on the shielded pool allow-list")]
+    MintNotAllowed,
+
+    #[msg("Shielded mint allow-list is full")]
+    AllowlistFull,
+
+    #[msg("Pool invariant violated")]
+    InvariantViolated,
+
+    #[msg("Insufficient compute budget to complete the instruction")]
+    InsufficientComputeBudget,
+
+    #[msg("Leaf index is below the last rooted index")]
+    InvalidLeafIndex,
+
+    #[msg("Execution price is worse than the limit price")]
+    LimitPriceNotMet,
+
+    #[msg("Verifier requires an initialized verifier state account")]
+    MissingVerifierState,
+
+    #[msg("Proof references too many roots")]
+    TooManyRoots,
+
+    #[msg("Total LP share supply would exceed the cap")]
+    ShareCapExceeded,
+
+    #[msg("Proof commitment has expired")]
+    ProofCommitmentExpired,
+
+    #[msg("Mint has a freeze authority")]
+    MintFreezable,
+
+    #[msg("Deposit would mint zero LP shares")]
+    ZeroLiquidityMinted,
+
+    #[msg("Pools do not trade the same mint pair")]
+    PoolPairMismatch,
+
+    #[msg("Fee rate exceeds the maximum")]
+    InvalidFeeBps,
+
+    #[msg("Route uses the same pool for both legs")]
+    SamePoolInRoute,
+
+    #[msg("Route pools do not share the intermediate mint")]
+    InvalidRoute,
+
+    #[msg("Required input exceeds the maximum input")]
+    ExcessiveInputAmount,
+
+    #[msg("Internal swap exceeds the allowed slippage")]
+    InternalSlippageExceeded,
+
+    #[msg("Shielded merkle tree is full")]
+    TreeFull,
+
+    #[msg("Pool is paused")]
+    PoolPaused,
+
+    #[msg("Received amount differs from the transferred amount")]
+    TransferAmountMismatch,
+
+    #[msg("Requested amount exceeds the accrued fees")]
+    InsufficientFees,
+
+    #[msg("Transaction deadline has passed")]
+    DeadlineExceeded,
+
+    #[msg("Pool mints must differ")]
+    IdenticalMints,
+
+    #[msg("Pool mints must be ordered token_a_mint < token_b_mint")]
+    MintsNotSorted,
+
+    #[msg("Initial deposit mints fewer shares than the locked minimum")]
+    InsufficientInitialLiquidity,
+
+    #[msg("Swap output is below the pool's minimum output")]
+    OutputBelowMinimum,
+
+    #[msg("Deposit batch is empty, mismatched or too large")]
+    BatchTooLarge,
+
+    #[msg("min_out of zero requires accept_any_output")]
+    SlippageProtectionRequired,
+
+    #[msg("Emergency withdrawal timelock has not elapsed")]
+    TimelockActive,
+
+    #[msg("Emergency delay is below the minimum")]
+    EmergencyDelayTooShort,
+
+    #[msg("Reserve token account is closed or was never created")]
+    ReserveAccountMissing,
+
+    #[msg("Pool is locked by a swap in progress")]
+    PoolLocked,
+
+    #[msg("Change commitment must be non-zero")]
+    InvalidChangeCommitment,
+
+    #[msg("Pool still holds reserves or outstanding LP shares")]
+    PoolNotEmpty,
+
+    #[msg("Proof amount does not fit a u64 token amount")]
+    AmountTooLarge,
+
+    #[msg("Commitment openings need the plain-commitments build")]
+    PlainCommitmentsDisabled,
+
+    #[msg("Commitment does not match its opening")]
+    CommitmentMismatch,
+
+    #[msg("Swap moves the price further than the allowed impact")]
+    ExcessivePriceImpact,
+
+    #[msg("Swap exceeds the pool's per-swap size cap")]
+    SwapTooLarge,
+
+    #[msg("Root history can only grow, up to MAX_ROOT_HISTORY_LEN")]
+    InvalidHistoryLength,
+
+    #[msg("Pool requires its swap hook program in the remaining accounts")]
+    SwapHookMissing,
+
+    #[msg("Recipient token account does not match the swap output")]
+    InvalidRecipient,
+
+    #[msg("Proof or public inputs are empty")]
+    EmptyProof,
+
+    #[msg("Shielded pool mint is not wrapped SOL")]
+    NotNativeMint,
+
+    #[msg("Merkle proof does not match the current root")]
+    InvalidMerkleProof,
+
+    #[msg("Slippage exceeds the maximum")]
+    InvalidSlippage,
+
+    #[msg("Signer is not the pending pool authority")]
+    NotPendingAuthority,
+
+    #[msg("Signer is not the program upgrade authority")]
+    NotUpgradeAuthority,
+
+    #[msg("This shielded pool has an on-chain tree, pass the merkle_tree account")]
+    MerkleTreeRequired,
+
+    #[msg("Account is not in the layout this migration expects")]
+    InvalidMigration,
+
+    #[msg("No emergency withdrawal request is pending")]
+    NoEmergencyRequest,
 }
@@ -0,0 +1,452 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::token_interface::{self, Burn, CloseAccount, TokenAccount, TransferChecked};
+
+use crate::contexts::{
+    ClosePool, CollectFees, DistributeFees, MigrateReserve, MigrateRootHistory, MigrateShieldedPool,
+    MigrateShieldedRootHistory, RecreateReserve, SetFeeDistribution,
+};
+use crate::errors::ErrorCode;
+use crate::math::{check_pool_empty, split_by_weights};
+use crate::state::{Pool, ReserveUpdateEvent, ReserveUpdateReason, FEE_WEIGHT_DENOMINATOR, MAX_FEE_RECIPIENTS};
+use crate::state::roots::{StateRootHistory, LEGACY_ROOT_HISTORY_LEN};
+use crate::state::shielded::{ShieldedPool, ShieldedRootHistory, DEFAULT_EMERGENCY_DELAY};
+
+/// moves the full balance of a pool reserve into a fresh pool-owned account,
+/// used to recover a pool once a frozen reserve has been thawed
+pub fn migrate_reserve(ctx: Context<MigrateReserve>) -> Result<()> {
+    let pool = &ctx.accounts.pool;
+    let amount = ctx.accounts.old_reserve.amount;
+    pool.validate_pda(&pool.key())?;
+
+    let seeds = &[
+        b"pool".as_ref(),
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &[pool.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.old_reserve.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.new_reserve.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    msg!(
+        "Reserve migrated: {} -> {} ({})",
+        ctx.accounts.old_reserve.key(),
+        ctx.accounts.new_reserve.key(),
+        amount
+    );
+    Ok(())
+}
+
+/// the new ata starts empty while the pool still tracks its old reserve,
+/// so the authority has to refund it before swaps out of that side work
+pub fn recreate_reserve(ctx: Context<RecreateReserve>) -> Result<()> {
+    let pool = &ctx.accounts.pool;
+    pool.validate_pda(&pool.key())?;
+    let tracked = if ctx.accounts.mint.key() == pool.token_a_mint {
+        pool.token_a_reserve
+    } else {
+        pool.token_b_reserve
+    };
+    msg!(
+        "Reserve recreated: {} (tracked reserve {})",
+        ctx.accounts.reserve.key(),
+        tracked
+    );
+    Ok(())
+}
+
+/// sweeps what is left in the reserves to the authority, burns the locked
+/// shares and closes the reserve atas and lp lock. the pool account itself
+/// is closed by the context. the lp mint stays behind, classic spl mints
+/// can't be closed
+pub fn close_pool(ctx: Context<ClosePool>) -> Result<()> {
+    let pool = &ctx.accounts.pool;
+    pool.validate_pda(&pool.key())?;
+    // the locked floor keeps a dust balance in both reserves for good, so
+    // that is all an empty pool can get down to
+    check_pool_empty(ctx.accounts.lp_mint.supply, ctx.accounts.lp_lock.amount)?;
+
+    let seeds = &[
+        b"pool".as_ref(),
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &[pool.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    // the live balances, so donations on top of the tracked reserves go too
+    for (reserve, mint, to) in [
+        (&ctx.accounts.token_a_reserve, &ctx.accounts.token_a_mint, &ctx.accounts.authority_token_a),
+        (&ctx.accounts.token_b_reserve, &ctx.accounts.token_b_mint, &ctx.accounts.authority_token_b),
+    ] {
+        if reserve.amount == 0 {
+            continue;
+        }
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: reserve.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            reserve.amount,
+            mint.decimals,
+        )?;
+    }
+
+    let locked = ctx.accounts.lp_lock.amount;
+    if locked > 0 {
+        token_interface::burn(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.lp_lock.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            locked,
+        )?;
+    }
+
+    for account in [
+        ctx.accounts.token_a_reserve.to_account_info(),
+        ctx.accounts.token_b_reserve.to_account_info(),
+        ctx.accounts.lp_lock.to_account_info(),
+    ] {
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account,
+                destination: ctx.accounts.authority.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+    }
+
+    msg!(
+        "Pool closed: {}, swept A={}, B={}",
+        pool.key(),
+        ctx.accounts.token_a_reserve.amount,
+        ctx.accounts.token_b_reserve.amount
+    );
+    Ok(())
+}
+
+pub fn set_fee_distribution(
+    ctx: Context<SetFeeDistribution>,
+    recipients: Vec<Pubkey>,
+    weights: Vec<u16>,
+) -> Result<()> {
+    require!(
+        !recipients.is_empty() && recipients.len() <= MAX_FEE_RECIPIENTS,
+        ErrorCode::InvalidFeeDistribution
+    );
+    require!(recipients.len() == weights.len(), ErrorCode::InvalidFeeDistribution);
+    let total: u32 = weights.iter().map(|w| *w as u32).sum();
+    require!(total == FEE_WEIGHT_DENOMINATOR as u32, ErrorCode::InvalidFeeDistribution);
+
+    let distribution = &mut ctx.accounts.fee_distribution;
+    distribution.pool = ctx.accounts.pool.key();
+    distribution.recipients = [Pubkey::default(); MAX_FEE_RECIPIENTS];
+    distribution.weights = [0; MAX_FEE_RECIPIENTS];
+    distribution.recipients[..recipients.len()].copy_from_slice(&recipients);
+    distribution.weights[..weights.len()].copy_from_slice(&weights);
+    distribution.count = recipients.len() as u8;
+    distribution.bump = ctx.bumps.fee_distribution;
+    Ok(())
+}
+
+/// sweeps the accrued fees on the reserve's side of the pool to the
+/// configured recipients in a single instruction
+pub fn distribute_fees<'info>(
+    ctx: Context<'_, '_, '_, 'info, DistributeFees<'info>>,
+) -> Result<()> {
+    let distribution = &ctx.accounts.fee_distribution;
+    let count = distribution.count as usize;
+    require!(ctx.remaining_accounts.len() == count, ErrorCode::InvalidFeeRecipient);
+
+    let pool = &mut ctx.accounts.pool;
+    pool.validate_pda(&pool.key())?;
+    let reserve_mint = ctx.accounts.reserve.mint;
+    let is_token_a = reserve_mint == pool.token_a_mint;
+    let amount = if is_token_a { pool.total_fees_a } else { pool.total_fees_b };
+    require!(amount > 0, ErrorCode::ZeroAmount);
+
+    let shares = split_by_weights(amount, &distribution.weights[..count])?;
+
+    let seeds = &[
+        b"pool".as_ref(),
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &[pool.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    let recipient_infos = ctx.remaining_accounts.to_vec();
+    for (i, recipient_info) in recipient_infos.iter().enumerate() {
+        require_keys_eq!(*recipient_info.owner, ctx.accounts.token_program.key(), ErrorCode::InvalidFeeRecipient);
+        let recipient_account = TokenAccount::try_deserialize(&mut &recipient_info.try_borrow_data()?[..])?;
+        require!(recipient_account.mint == reserve_mint, ErrorCode::InvalidFeeRecipient);
+        require!(
+            recipient_account.owner == distribution.recipients[i],
+            ErrorCode::InvalidFeeRecipient
+        );
+        if shares[i] == 0 {
+            continue;
+        }
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reserve.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: recipient_info.clone(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            shares[i],
+            ctx.accounts.mint.decimals,
+        )?;
+    }
+
+    let reserve_a_before = pool.token_a_reserve;
+    let reserve_b_before = pool.token_b_reserve;
+    if is_token_a {
+        pool.token_a_reserve = pool.token_a_reserve.checked_sub(amount).ok_or(ErrorCode::InsufficientLiquidity)?;
+        pool.total_fees_a = 0;
+    } else {
+        pool.token_b_reserve = pool.token_b_reserve.checked_sub(amount).ok_or(ErrorCode::InsufficientLiquidity)?;
+        pool.total_fees_b = 0;
+    }
+    pool.k = (pool.token_a_reserve as u128).checked_mul(pool.token_b_reserve as u128).ok_or(ErrorCode::MathOverflow)?;
+
+    emit!(ReserveUpdateEvent {
+        pool: pool.key(),
+        reserve_a_before,
+        reserve_a_after: pool.token_a_reserve,
+        reserve_b_before,
+        reserve_b_after: pool.token_b_reserve,
+        reason: ReserveUpdateReason::FeeDistribution,
+    });
+
+    msg!("Fees distributed: {} to {} recipients", amount, count);
+    Ok(())
+}
+
+/// pays accrued protocol fees out of the reserves to the pool authority.
+/// the fees are part of the tracked reserves, so both shrink together
+pub fn collect_fees(ctx: Context<CollectFees>, amount_a: u64, amount_b: u64) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    pool.validate_pda(&pool.key())?;
+    require!(amount_a > 0 || amount_b > 0, ErrorCode::ZeroAmount);
+    require!(amount_a <= pool.total_fees_a && amount_b <= pool.total_fees_b, ErrorCode::InsufficientFees);
+
+    let seeds = &[
+        b"pool".as_ref(),
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &[pool.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    if amount_a > 0 {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.token_a_reserve.to_account_info(),
+                    mint: ctx.accounts.token_a_mint.to_account_info(),
+                    to: ctx.accounts.authority_token_a.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount_a,
+            pool.token_a_decimals,
+        )?;
+    }
+    if amount_b > 0 {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.token_b_reserve.to_account_info(),
+                    mint: ctx.accounts.token_b_mint.to_account_info(),
+                    to: ctx.accounts.authority_token_b.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount_b,
+            pool.token_b_decimals,
+        )?;
+    }
+
+    let reserve_a_before = pool.token_a_reserve;
+    let reserve_b_before = pool.token_b_reserve;
+    pool.token_a_reserve = pool.token_a_reserve.checked_sub(amount_a).ok_or(ErrorCode::InsufficientLiquidity)?;
+    pool.token_b_reserve = pool.token_b_reserve.checked_sub(amount_b).ok_or(ErrorCode::InsufficientLiquidity)?;
+    pool.total_fees_a -= amount_a;
+    pool.total_fees_b -= amount_b;
+    pool.k = (pool.token_a_reserve as u128).checked_mul(pool.token_b_reserve as u128).ok_or(ErrorCode::MathOverflow)?;
+
+    emit!(ReserveUpdateEvent {
+        pool: pool.key(),
+        reserve_a_before,
+        reserve_a_after: pool.token_a_reserve,
+        reserve_b_before,
+        reserve_b_after: pool.token_b_reserve,
+        reason: ReserveUpdateReason::FeeCollection,
+    });
+
+    msg!("Fees collected: A={}, B={}", amount_a, amount_b);
+    Ok(())
+}
+
+/// checks an account still has the layout a migration starts from: owned by
+/// this program, `legacy_len` bytes and the discriminator of `T`
+fn check_legacy_layout<T: Discriminator>(account: &AccountInfo, legacy_len: usize) -> Result<()> {
+    require_keys_eq!(*account.owner, crate::ID, ErrorCode::InvalidMigration);
+    let data = account.try_borrow_data()?;
+    require!(data.len() == legacy_len, ErrorCode::InvalidMigration);
+    require!(data[..8] == *T::DISCRIMINATOR, ErrorCode::InvalidMigration);
+    Ok(())
+}
+
+/// the pubkey stored at `offset`, read straight from the account bytes
+fn stored_key(account: &AccountInfo, offset: usize) -> Result<Pubkey> {
+    let data = account.try_borrow_data()?;
+    let bytes: [u8; 32] = data
+        .get(offset..offset + 32)
+        .and_then(|b| b.try_into().ok())
+        .ok_or(ErrorCode::InvalidMigration)?;
+    Ok(Pubkey::new_from_array(bytes))
+}
+
+/// grows a program account in place to `new_len`, the payer tops its rent
+/// up first. new bytes are zeroed
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    new_len: usize,
+) -> Result<()> {
+    let shortfall = Rent::get()?.minimum_balance(new_len).saturating_sub(account.lamports());
+    if shortfall > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+    account.resize(new_len)?;
+    Ok(())
+}
+
+/// grows a shielded pool created with the original seven-field layout and
+/// fills in every field appended since. leaves already taken count as
+/// rooted, so set_next_index can't move the counter back over them
+pub fn migrate_shielded_pool(ctx: Context<MigrateShieldedPool>, expected_verifier: Pubkey) -> Result<()> {
+    let pool_info = ctx.accounts.shielded_pool.to_account_info();
+    check_legacy_layout::<ShieldedPool>(&pool_info, ShieldedPool::LEGACY_LEN)?;
+    // discriminator, mint and vault come before the authority
+    require_keys_eq!(
+        stored_key(&pool_info, 8 + 32 + 32)?,
+        ctx.accounts.authority.key(),
+        ErrorCode::InvalidShieldedAccount
+    );
+
+    grow_account(
+        &pool_info,
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+        ShieldedPool::LEN,
+    )?;
+    let mut pool = ShieldedPool::try_deserialize(&mut &pool_info.try_borrow_data()?[..])?;
+    let tree = &ctx.accounts.merkle_tree;
+    pool.last_rooted_index = pool.next_index;
+    pool.paused = false;
+    pool.emergency_delay = DEFAULT_EMERGENCY_DELAY;
+    pool.emergency_requested_at = 0;
+    pool.expected_verifier = expected_verifier;
+    pool.strict_root = false;
+    pool.has_tree = *tree.owner == crate::ID && !tree.data_is_empty();
+    pool.try_serialize(&mut &mut pool_info.try_borrow_mut_data()?[..])?;
+
+    msg!("Shielded pool migrated, has_tree: {}", pool.has_tree);
+    Ok(())
+}
+
+/// grows a shielded pool's root history created before written_at existed
+/// and marks its live window, so the roots it already holds keep verifying
+pub fn migrate_shielded_root_history(ctx: Context<MigrateShieldedRootHistory>) -> Result<()> {
+    let history_info = ctx.accounts.root_history.to_account_info();
+    check_legacy_layout::<ShieldedRootHistory>(&history_info, ShieldedRootHistory::LEGACY_LEN)?;
+    // discriminator and current_index come before the pool
+    require_keys_eq!(
+        stored_key(&history_info, 8 + 8)?,
+        ctx.accounts.shielded_pool.key(),
+        ErrorCode::InvalidMigration
+    );
+
+    grow_account(
+        &history_info,
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+        ShieldedRootHistory::LEN,
+    )?;
+    let mut data = history_info.try_borrow_mut_data()?;
+    let history = bytemuck::from_bytes_mut::<ShieldedRootHistory>(&mut data[8..]);
+    history.mark_live_window();
+
+    msg!("Shielded root history migrated at index {}", history.current_index);
+    Ok(())
+}
+
+/// rewrites a state root history from the fixed-array layout into the
+/// resizable one in place, so the account keeps its address and roots
+pub fn migrate_root_history(ctx: Context<MigrateRootHistory>) -> Result<()> {
+    let history_info = ctx.accounts.history.to_account_info();
+    check_legacy_layout::<StateRootHistory>(&history_info, LEGACY_ROOT_HISTORY_LEN)?;
+    let history = StateRootHistory::from_legacy(&history_info.try_borrow_data()?[8..])?;
+    require_keys_eq!(history.authority, ctx.accounts.authority.key(), ErrorCode::InvalidMigration);
+
+    grow_account(
+        &history_info,
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+        StateRootHistory::LEN,
+    )?;
+    history.try_serialize(&mut &mut history_info.try_borrow_mut_data()?[..])?;
+
+    msg!("Root history migrated at index {}", history.current_index);
+    Ok(())
+}
@@ -1,4 +1,5 @@
 pub mod swap;
 pub mod shielded_pool;
+pub mod admin;
 
 pub use swap::*;
@@ -1,36 +1,109 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::{program::invoke_signed, program_pack::Pack, system_instruction};
-use anchor_spl::token::{self, Transfer};
-use anchor_spl::token::spl_token;
+use anchor_lang::solana_program::{program::invoke_signed, system_instruction};
+use anchor_lang::system_program;
+use solana_program::compute_units::sol_remaining_compute_units;
+use solana_program::hash::hash;
+use anchor_spl::token_interface::spl_token_2022::extension::StateWithExtensions;
+use anchor_spl::token_interface::{self, spl_token_2022, CloseAccount, Mint, TransferChecked};
 
+use crate::crypto::{compute_commitment, verify_merkle_path};
 use crate::errors::ErrorCode;
-use crate::math::{get_amount_out, verify_zk_proof};
-use crate::state::Pool;
-use crate::state::shielded::{DepositEvent, Nullifier, ROOT_HISTORY_BYTES};
+use crate::instructions::swap::{
+    call_swap_hook, check_deadline, check_min_out, ensure_reserve_open, lock_pool, mint_charges_transfer_fee, token_balance,
+    SwapDirection,
+};
+use crate::math::{check_swap_invariant, check_swap_size, get_amount_out, received_amount, verify_zk_proof};
+use crate::state::{
+    Pool, ReserveUpdateEvent, ReserveUpdateReason, SwapEvent, SWAP_DIRECTION_A_TO_B, SWAP_DIRECTION_B_TO_A,
+};
+use crate::state::shielded::{
+    CommitmentOpening, CurrentRootSynced, DepositEvent, EmergencyCancelled, EmergencyRequested, MerkleProof, NextIndexReset, Nullifier, NullifierSpent, PrivateSwapEvent, ShieldedMerkleTree, ShieldedPool,
+    ShieldedMintAllowlist, ShieldedRootHistory, ShieldedSpend, SwapPrivateArgs, VerifiedProof, DEFAULT_EMERGENCY_DELAY, MAX_ALLOWED_MINTS, MAX_DEPOSIT_BATCH, MIN_EMERGENCY_DELAY, ROOT_HISTORY_BYTES,
+    ROOT_HISTORY_SIZE, SHIELDED_TREE_DEPTH,
+};
 
-const PUBLIC_INPUTS_LEN: usize = 6; // root, nullifier, amount, recipient, mint, pool
+const PUBLIC_INPUTS_LEN: usize = 7; // root, nullifier, amount, recipient, mint, pool, change commitment
+
+// compute units the handlers need including the verifier cpi, checked up front
+// so the relayer doesn't pay for a proof verification that was going to run out.
+// both are above the 200k default, clients must add a SetComputeUnitLimit
+// instruction (the relayer requests 400k)
+const MIN_COMPUTE_UNITS_WITHDRAW: u64 = 300_000;
+const MIN_COMPUTE_UNITS_SWAP_PRIVATE: u64 = 350_000;
+
+fn ensure_compute_budget(min_units: u64) -> Result<()> {
+    let remaining = sol_remaining_compute_units();
+    if remaining < min_units {
+        msg!("Compute remaining: {}, required: {}", remaining, min_units);
+        return Err(ErrorCode::InsufficientComputeBudget.into());
+    }
+    Ok(())
+}
+
+/// layout of a public witness buffer. both carry PUBLIC_INPUTS_LEN big-endian
+/// field elements, the noir/gnark witness file prefixes them with a 12-byte
+/// header (public count, secret count, vector length)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WitnessFormat {
+    Raw,
+    NoirHeader,
+}
+
+impl WitnessFormat {
+    pub const HEADER_LEN: usize = 12;
+
+    pub fn header_len(self) -> usize {
+        match self {
+            WitnessFormat::Raw => 0,
+            WitnessFormat::NoirHeader => Self::HEADER_LEN,
+        }
+    }
+}
+
+/// tags a public inputs buffer by its length. any other length means a
+/// different field count and is rejected outright, as is a header whose
+/// counts don't describe PUBLIC_INPUTS_LEN public fields
+pub fn detect_witness_format(public_inputs: &[u8]) -> Result<WitnessFormat> {
+    match public_inputs.len() {
+        len if len == PUBLIC_INPUTS_LEN * 32 => Ok(WitnessFormat::Raw),
+        len if len == PUBLIC_INPUTS_LEN * 32 + WitnessFormat::HEADER_LEN => {
+            let count = |i: usize| u32::from_be_bytes(public_inputs[i..i + 4].try_into().unwrap()) as usize;
+            require!(
+                count(0) == PUBLIC_INPUTS_LEN && count(4) == 0 && count(8) == PUBLIC_INPUTS_LEN,
+                ErrorCode::InvalidProof
+            );
+            Ok(WitnessFormat::NoirHeader)
+        }
+        _ => Err(ErrorCode::InvalidProof.into()),
+    }
+}
 
 fn parse_field(public_inputs: &[u8], index: usize) -> Result<[u8; 32]> {
-    // noir public witness files include a 12-byte header, strip it if present
-    let header = if public_inputs.len() == PUBLIC_INPUTS_LEN * 32 + 12 { 12 } else { 0 };
-    let start = header + index * 32;
-    let end = start + 32;
-    if public_inputs.len() < end {
+    let format = detect_witness_format(public_inputs)?;
+    if index >= PUBLIC_INPUTS_LEN {
         return Err(ErrorCode::InvalidProof.into());
     }
+    let start = format.header_len() + index * 32;
+    let end = start + 32;
     let mut out = [0u8; 32];
     out.copy_from_slice(&public_inputs[start..end]);
     Ok(out)
 }
 
-fn field_to_u64(field_bytes: &[u8; 32]) -> Result<u64> {
-    // public witness entries are big-endian, u64 is in the last 8 bytes
-    if field_bytes[..24].iter().any(|b| *b != 0) {
+/// a field with any of the top 16 bytes set isn't an amount at all
+fn field_to_u128(field_bytes: &[u8; 32]) -> Result<u128> {
+    // public witness entries are big-endian, amounts use the last 16 bytes
+    if field_bytes[..16].iter().any(|b| *b != 0) {
         return Err(ErrorCode::InvalidProof.into());
     }
-    let mut buf = [0u8; 8];
-    buf.copy_from_slice(&field_bytes[24..]);
-    Ok(u64::from_be_bytes(buf))
+    let mut buf = [0u8; 16];
+    buf.copy_from_slice(&field_bytes[16..]);
+    Ok(u128::from_be_bytes(buf))
+}
+
+/// a well-formed amount that still doesn't fit the u64 token amount
+fn field_to_u64(field_bytes: &[u8; 32]) -> Result<u64> {
+    u64::try_from(field_to_u128(field_bytes)?).map_err(|_| ErrorCode::AmountTooLarge.into())
 }
 
 fn pubkey_to_field_bytes(key: &Pubkey) -> [u8; 32] {
@@ -41,11 +114,79 @@ fn pubkey_to_field_bytes(key: &Pubkey) -> [u8; 32] {
     out
 }
 
-fn parse_token_account(account: &AccountInfo) -> Result<spl_token::state::Account> {
+fn parse_token_account(account: &AccountInfo) -> Result<spl_token_2022::state::Account> {
     let data = account.try_borrow_data()?;
-    spl_token::state::Account::unpack(&data).map_err(|_| ErrorCode::InvalidShieldedAccount.into())
+    StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)
+        .map(|state| state.base)
+        .map_err(|_| ErrorCode::InvalidShieldedAccount.into())
+}
+
+/// looks up a verified-proof cache entry passed to swap_private. returns
+/// true when it covers these exact inputs and hasn't expired, the entry is
+/// closed to the relayer either way since the nullifier is about to be spent
+fn consume_verified_proof<'info>(
+    cache_info: &AccountInfo<'info>,
+    relayer_info: &AccountInfo<'info>,
+    shielded_pool_key: &Pubkey,
+    verifier_program: &Pubkey,
+    nullifier_hash: &[u8; 32],
+    public_inputs: &[u8],
+) -> Result<bool> {
+    let (expected_pda, _) = Pubkey::find_program_address(
+        &[b"verified_proof", shielded_pool_key.as_ref(), nullifier_hash],
+        &crate::ID,
+    );
+    require_keys_eq!(cache_info.key(), expected_pda, ErrorCode::InvalidShieldedAccount);
+    if cache_info.owner != &crate::ID {
+        return Ok(false);
+    }
+
+    let usable = {
+        let data = cache_info.try_borrow_data()?;
+        let mut cursor: &[u8] = &data;
+        let cached = VerifiedProof::try_deserialize(&mut cursor)?;
+        cached.is_usable(verifier_program, &hash(public_inputs).to_bytes(), Clock::get()?.slot)
+    };
+
+    let lamports = cache_info.lamports();
+    **relayer_info.try_borrow_mut_lamports()? = relayer_info
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ErrorCode::MathOverflow)?;
+    **cache_info.try_borrow_mut_lamports()? = 0;
+    cache_info.assign(&system_program::ID);
+    cache_info.resize(0)?;
+    Ok(usable)
 }
 
+pub fn cache_proof_verification(
+    ctx: Context<crate::contexts::CacheProofVerification>,
+    proof: Vec<u8>,
+    public_inputs: Vec<u8>,
+    nullifier_hash: [u8; 32],
+) -> Result<()> {
+    require!(parse_field(&public_inputs, 1)? == nullifier_hash, ErrorCode::InvalidProof);
+    let expected_pool = pubkey_to_field_bytes(&ctx.accounts.input_shielded_pool.key());
+    require!(parse_field(&public_inputs, 5)? == expected_pool, ErrorCode::InvalidProof);
+
+    ctx.accounts.input_shielded_pool.check_verifier(&ctx.accounts.verifier_program.key())?;
+    verify_zk_proof(&ctx.accounts.verifier_program, None, &proof, &public_inputs)?;
+
+    let cache = &mut ctx.accounts.verified_proof;
+    cache.verifier_program = ctx.accounts.verifier_program.key();
+    cache.inputs_hash = hash(&public_inputs).to_bytes();
+    cache.verified_slot = Clock::get()?.slot;
+    cache.bump = ctx.bumps.verified_proof;
+    Ok(())
+}
+
+pub fn nullifier_rent() -> Result<u64> {
+    Ok(Rent::get()?.minimum_balance(Nullifier::LEN))
+}
+
+/// marks the nullifier spent, creating its pda on first use. a nullifier
+/// that is already spent fails with NullifierAlreadySpent, so `Ok` always
+/// means this call did the unspent -> spent transition
 fn ensure_nullifier_account<'info>(
     nullifier_info: &AccountInfo<'info>,
     payer_info: &AccountInfo<'info>,
@@ -71,7 +212,7 @@ fn ensure_nullifier_account<'info>(
             return Err(ErrorCode::NullifierAlreadySpent.into());
         }
         nullifier.spent = true;
-        nullifier.serialize(&mut &mut data[..])?;
+        nullifier.try_serialize(&mut &mut data[..])?;
         return Ok(());
     }
 
@@ -79,8 +220,7 @@ fn ensure_nullifier_account<'info>(
         return Err(ErrorCode::InvalidShieldedAccount.into());
     }
 
-    let rent = Rent::get()?;
-    let lamports = rent.minimum_balance(Nullifier::LEN);
+    let lamports = nullifier_rent()?;
     let ix = system_instruction::create_account(
         payer_info.key,
         &expected_pda,
@@ -96,11 +236,30 @@ fn ensure_nullifier_account<'info>(
 
     let mut data = nullifier_info.try_borrow_mut_data()?;
     let nullifier = Nullifier { spent: true };
-    nullifier.serialize(&mut &mut data[..])?;
+    // try_serialize writes the discriminator too, so a replay deserializes
+    // the account and fails on `spent` rather than on a missing discriminator
+    nullifier.try_serialize(&mut &mut data[..])?;
     Ok(())
 }
 
-pub fn initialize_shielded_pool(ctx: Context<crate::contexts::InitializeShieldedPool>) -> Result<()> {
+/// pool creation stays permissionless until the allow-list PDA is initialized;
+/// after that only listed mints get a shielded pool
+fn check_mint_allowed(allowlist: &AccountInfo, mint: &Pubkey) -> Result<()> {
+    if allowlist.owner != &crate::ID || allowlist.data_is_empty() {
+        return Ok(());
+    }
+    let data = allowlist.try_borrow_data()?;
+    let allowlist = ShieldedMintAllowlist::try_deserialize(&mut &data[..])?;
+    require!(allowlist.mints.contains(mint), ErrorCode::MintNotAllowed);
+    Ok(())
+}
+
+pub fn initialize_shielded_pool(
+    ctx: Context<crate::contexts::InitializeShieldedPool>,
+    expected_verifier: Pubkey,
+) -> Result<()> {
+    check_mint_allowed(&ctx.accounts.mint_allowlist, &ctx.accounts.mint.key())?;
+
     let pool = &mut ctx.accounts.shielded_pool;
     pool.mint = ctx.accounts.mint.key();
     pool.vault = ctx.accounts.vault.key();
@@ -113,6 +272,45 @@ pub fn initialize_shielded_pool(ctx: Context<crate::contexts::InitializeShielded
     pool.root_history = root_history;
     pool.next_index = 0;
     pool.bump = ctx.bumps.shielded_pool;
+    pool.last_rooted_index = 0;
+    pool.paused = false;
+    pool.emergency_delay = DEFAULT_EMERGENCY_DELAY;
+    pool.emergency_requested_at = 0;
+    pool.expected_verifier = expected_verifier;
+    pool.strict_root = false;
+    pool.has_tree = false;
+    Ok(())
+}
+
+pub fn initialize_shielded_mint_allowlist(
+    ctx: Context<crate::contexts::InitializeShieldedMintAllowlist>,
+) -> Result<()> {
+    let allowlist = &mut ctx.accounts.mint_allowlist;
+    allowlist.authority = ctx.accounts.authority.key();
+    allowlist.mints = Vec::new();
+    allowlist.bump = ctx.bumps.mint_allowlist;
+    Ok(())
+}
+
+pub fn add_shielded_mint(
+    ctx: Context<crate::contexts::UpdateShieldedMintAllowlist>,
+    mint: Pubkey,
+) -> Result<()> {
+    let allowlist = &mut ctx.accounts.mint_allowlist;
+    if allowlist.mints.contains(&mint) {
+        return Ok(());
+    }
+    require!(allowlist.mints.len() < MAX_ALLOWED_MINTS, ErrorCode::AllowlistFull);
+    allowlist.mints.push(mint);
+    Ok(())
+}
+
+pub fn remove_shielded_mint(
+    ctx: Context<crate::contexts::UpdateShieldedMintAllowlist>,
+    mint: Pubkey,
+) -> Result<()> {
+    let allowlist = &mut ctx.accounts.mint_allowlist;
+    allowlist.mints.retain(|m| *m != mint);
     Ok(())
 }
 
@@ -126,38 +324,154 @@ pub fn initialize_shielded_root_history(
     require!(pool.root_history == ctx.accounts.root_history.key(), ErrorCode::InvalidShieldedAccount);
 
     history.roots = [0u8; ROOT_HISTORY_BYTES];
+    history.written_at = [0; ROOT_HISTORY_SIZE];
     history.current_index = 0;
     history.pool = pool.key();
     Ok(())
 }
 
+pub fn initialize_shielded_tree(ctx: Context<crate::contexts::InitializeShieldedTree>) -> Result<()> {
+    // leaf indices are shared with the pool, so the tree has to start with it
+    require!(ctx.accounts.shielded_pool.next_index == 0, ErrorCode::InvalidLeafIndex);
+    let mut tree = ctx.accounts.merkle_tree.load_init()?;
+    tree.init(ctx.accounts.shielded_pool.key());
+    ctx.accounts.shielded_pool.has_tree = true;
+    Ok(())
+}
+
+/// pulls a deposit into the vault. notes are worth their nominal amount, so
+/// a fee-bearing mint that delivers short would leave the vault undercollateralized
+fn transfer_into_vault<'info>(
+    token_program: AccountInfo<'info>,
+    user_token: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: AccountInfo<'info>,
+    user: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let vault_before = token_balance(&vault)?;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program,
+            TransferChecked {
+                from: user_token,
+                mint: mint.to_account_info(),
+                to: vault.clone(),
+                authority: user,
+            },
+        ),
+        amount,
+        mint.decimals,
+    )?;
+    received_amount(vault_before, token_balance(&vault)?, amount, false)?;
+    Ok(())
+}
+
+/// appends a deposit's commitment at `index` and publishes the resulting root
+fn insert_commitment(
+    pool: &mut ShieldedPool,
+    pool_key: Pubkey,
+    tree: &mut ShieldedMerkleTree,
+    history: &mut ShieldedRootHistory,
+    index: u64,
+    commitment: [u8; 32],
+) -> Result<[u8; 32]> {
+    require!(tree.next_index == index, ErrorCode::InvalidLeafIndex);
+    require!(history.pool == pool_key, ErrorCode::InvalidShieldedAccount);
+
+    let root = tree.insert(commitment)?;
+    history.append_root(root);
+    pool.current_root = root;
+    pool.last_rooted_index = pool.next_index;
+    Ok(root)
+}
+
+/// gives the unspent remainder of a note a leaf of its own. the change
+/// amount stays private, so the event reports 0 and only the commitment
+/// binds the value
+fn insert_change_commitment(
+    pool: &mut ShieldedPool,
+    pool_key: Pubkey,
+    merkle_tree: Option<&AccountLoader<ShieldedMerkleTree>>,
+    root_history: &AccountLoader<ShieldedRootHistory>,
+    commitment: [u8; 32],
+) -> Result<()> {
+    require!(commitment != [0u8; 32], ErrorCode::InvalidChangeCommitment);
+    pool.check_tree_passed(merkle_tree.is_some())?;
+    let index = pool.next_index;
+    pool.next_index = pool.next_index.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+    if let Some(tree_loader) = merkle_tree {
+        let mut tree = tree_loader.load_mut()?;
+        let mut history = root_history.load_mut()?;
+        insert_commitment(pool, pool_key, &mut tree, &mut history, index, commitment)?;
+    }
+
+    emit!(DepositEvent {
+        pool: pool_key,
+        index,
+        commitment,
+        amount: 0,
+    });
+    Ok(())
+}
+
+/// a zero deposit would still take a leaf index and emit a DepositEvent
+fn check_deposit_amount(amount: u64) -> Result<()> {
+    require!(amount > 0, ErrorCode::ZeroAmount);
+    Ok(())
+}
+
+/// checks a revealed opening against the commitment. openings give the
+/// note away, so only builds with the `plain-commitments` feature take them
+fn check_commitment_opening(amount: u64, commitment: &[u8; 32], opening: Option<&CommitmentOpening>) -> Result<()> {
+    let Some(opening) = opening else {
+        return Ok(());
+    };
+    require!(cfg!(feature = "plain-commitments"), ErrorCode::PlainCommitmentsDisabled);
+    require!(
+        compute_commitment(amount, opening.secret, &opening.recipient) == *commitment,
+        ErrorCode::CommitmentMismatch
+    );
+    Ok(())
+}
+
 pub fn deposit_shielded(
     ctx: Context<crate::contexts::DepositShielded>,
     amount: u64,
     commitment: [u8; 32],
+    opening: Option<CommitmentOpening>,
 ) -> Result<()> {
+    check_deposit_amount(amount)?;
+    check_commitment_opening(amount, &commitment, opening.as_ref())?;
+    let pool_key = ctx.accounts.shielded_pool.key();
     let pool = &mut ctx.accounts.shielded_pool;
     require!(ctx.accounts.vault.key() == pool.vault, ErrorCode::InvalidShieldedAccount);
     require!(ctx.accounts.vault.mint == pool.mint, ErrorCode::InvalidShieldedAccount);
     require!(ctx.accounts.user_token.mint == pool.mint, ErrorCode::InvalidShieldedAccount);
+    pool.check_tree_passed(ctx.accounts.merkle_tree.is_some())?;
 
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.user_token.to_account_info(),
-                to: ctx.accounts.vault.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
-            },
-        ),
+    transfer_into_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.user_token.to_account_info(),
+        &ctx.accounts.mint,
+        ctx.accounts.vault.to_account_info(),
+        ctx.accounts.user.to_account_info(),
         amount,
     )?;
 
     let index = pool.next_index;
     pool.next_index = pool.next_index.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
 
+    if let Some(tree_loader) = &ctx.accounts.merkle_tree {
+        let history_loader = ctx.accounts.root_history.as_ref().ok_or(ErrorCode::InvalidShieldedAccount)?;
+        let mut tree = tree_loader.load_mut()?;
+        let mut history = history_loader.load_mut()?;
+        insert_commitment(pool, pool_key, &mut tree, &mut history, index, commitment)?;
+    }
+
     emit!(DepositEvent {
-        pool: ctx.accounts.shielded_pool.key(),
+        pool: pool_key,
         index,
         commitment,
         amount,
@@ -166,6 +480,106 @@ pub fn deposit_shielded(
     Ok(())
 }
 
+/// validates a deposit batch and returns the single transfer amount covering it
+fn batch_total(amounts: &[u64], commitment_count: usize) -> Result<u64> {
+    require!(
+        !amounts.is_empty() && amounts.len() == commitment_count && amounts.len() <= MAX_DEPOSIT_BATCH,
+        ErrorCode::BatchTooLarge
+    );
+    amounts.iter().try_fold(0u64, |total, amount| {
+        check_deposit_amount(*amount)?;
+        total.checked_add(*amount).ok_or(ErrorCode::MathOverflow.into())
+    })
+}
+
+/// deposits several notes with one token transfer. leaves take sequential
+/// indices and each gets its own DepositEvent
+pub fn deposit_shielded_batch(
+    ctx: Context<crate::contexts::DepositShielded>,
+    amounts: Vec<u64>,
+    commitments: Vec<[u8; 32]>,
+) -> Result<()> {
+    let total = batch_total(&amounts, commitments.len())?;
+    let pool_key = ctx.accounts.shielded_pool.key();
+    let pool = &mut ctx.accounts.shielded_pool;
+    require!(ctx.accounts.vault.key() == pool.vault, ErrorCode::InvalidShieldedAccount);
+    require!(ctx.accounts.vault.mint == pool.mint, ErrorCode::InvalidShieldedAccount);
+    require!(ctx.accounts.user_token.mint == pool.mint, ErrorCode::InvalidShieldedAccount);
+    pool.check_tree_passed(ctx.accounts.merkle_tree.is_some())?;
+
+    transfer_into_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.user_token.to_account_info(),
+        &ctx.accounts.mint,
+        ctx.accounts.vault.to_account_info(),
+        ctx.accounts.user.to_account_info(),
+        total,
+    )?;
+
+    let mut tree_accounts = match &ctx.accounts.merkle_tree {
+        Some(tree_loader) => {
+            let history_loader = ctx.accounts.root_history.as_ref().ok_or(ErrorCode::InvalidShieldedAccount)?;
+            Some((tree_loader.load_mut()?, history_loader.load_mut()?))
+        }
+        None => None,
+    };
+
+    for (amount, commitment) in amounts.into_iter().zip(commitments) {
+        let index = pool.next_index;
+        pool.next_index = pool.next_index.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        if let Some((tree, history)) = tree_accounts.as_mut() {
+            insert_commitment(pool, pool_key, tree, history, index, commitment)?;
+        }
+
+        emit!(DepositEvent {
+            pool: pool_key,
+            index,
+            commitment,
+            amount,
+        });
+    }
+
+    Ok(())
+}
+
+/// single-step shielding for pools with an on-chain tree: the deposit is
+/// inserted and rooted in the same instruction, so the note can be spent by
+/// the next transaction. returns the new root for the client's proof
+pub fn deposit_and_commit(
+    ctx: Context<crate::contexts::DepositAndCommit>,
+    amount: u64,
+    commitment: [u8; 32],
+) -> Result<[u8; 32]> {
+    check_deposit_amount(amount)?;
+    let pool_key = ctx.accounts.shielded_pool.key();
+
+    transfer_into_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.user_token.to_account_info(),
+        &ctx.accounts.mint,
+        ctx.accounts.vault.to_account_info(),
+        ctx.accounts.user.to_account_info(),
+        amount,
+    )?;
+
+    let pool = &mut ctx.accounts.shielded_pool;
+    let index = pool.next_index;
+    pool.next_index = pool.next_index.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+    let mut tree = ctx.accounts.merkle_tree.load_mut()?;
+    let mut history = ctx.accounts.root_history.load_mut()?;
+    let root = insert_commitment(pool, pool_key, &mut tree, &mut history, index, commitment)?;
+
+    emit!(DepositEvent {
+        pool: pool_key,
+        index,
+        commitment,
+        amount,
+    });
+
+    Ok(root)
+}
+
 pub fn update_shielded_root(
     ctx: Context<crate::contexts::UpdateShieldedRoot>,
     new_root: [u8; 32],
@@ -178,59 +592,265 @@ pub fn update_shielded_root(
     require!(history.pool == pool.key(), ErrorCode::InvalidShieldedAccount);
     require!(included_leaves == pool.next_index, ErrorCode::InvalidStateRoot);
     pool.current_root = new_root;
+    pool.last_rooted_index = included_leaves;
     history.append_root(new_root);
     Ok(())
 }
 
+/// recovery tool: points current_root back at the newest root in the history
+pub fn sync_current_root(ctx: Context<crate::contexts::SyncShieldedRoot>) -> Result<()> {
+    let pool = &mut ctx.accounts.shielded_pool;
+    let history = ctx.accounts.root_history.load()?;
+    require!(history.pool == pool.key(), ErrorCode::InvalidShieldedAccount);
+    let new_root = history.latest_root().ok_or(ErrorCode::InvalidStateRoot)?;
+
+    let old_root = pool.current_root;
+    pool.current_root = new_root;
+    emit!(CurrentRootSynced {
+        pool: pool.key(),
+        old_root,
+        new_root,
+    });
+    Ok(())
+}
+
+/// recovery only: overrides the on-chain leaf counter to match a rebuilt tree.
+/// a wrong value makes every later deposit land at the wrong leaf index, so this
+/// must only be used once the off-chain tree has been reconstructed from events
+pub fn set_next_index(
+    ctx: Context<crate::contexts::ManageShieldedPool>,
+    new_index: u64,
+) -> Result<()> {
+    let pool = &mut ctx.accounts.shielded_pool;
+    require!(new_index >= pool.last_rooted_index, ErrorCode::InvalidLeafIndex);
+
+    let old_index = pool.next_index;
+    pool.next_index = new_index;
+    msg!("WARNING: next_index overridden {} -> {}", old_index, new_index);
+
+    emit!(NextIndexReset {
+        pool: pool.key(),
+        old_index,
+        new_index,
+    });
+    Ok(())
+}
+
+/// starts the emergency timelock. the request is public so depositors can
+/// exit through withdraw_shielded before the vault can be swept
+pub fn request_emergency(ctx: Context<crate::contexts::ManageShieldedPool>) -> Result<()> {
+    let pool = &mut ctx.accounts.shielded_pool;
+    let now = Clock::get()?.unix_timestamp;
+    pool.emergency_requested_at = now;
+    let unlocks_at = now.checked_add(pool.emergency_delay).ok_or(ErrorCode::MathOverflow)?;
+    msg!("WARNING: emergency withdrawal requested, unlocks at {}", unlocks_at);
+
+    emit!(EmergencyRequested {
+        pool: pool.key(),
+        requested_at: now,
+        unlocks_at,
+    });
+    Ok(())
+}
+
+/// withdraws a pending emergency request before it is used
+pub fn cancel_emergency(ctx: Context<crate::contexts::ManageShieldedPool>) -> Result<()> {
+    let pool = &mut ctx.accounts.shielded_pool;
+    let requested_at = pool.emergency_requested_at;
+    pool.cancel_emergency()?;
+    msg!("Emergency request from {} cancelled", requested_at);
+
+    emit!(EmergencyCancelled {
+        pool: pool.key(),
+        requested_at,
+    });
+    Ok(())
+}
+
+/// the delay can't drop below MIN_EMERGENCY_DELAY or change while a request
+/// is pending, otherwise the authority could shorten its own timelock
+pub fn set_emergency_delay(ctx: Context<crate::contexts::ManageShieldedPool>, delay: i64) -> Result<()> {
+    let pool = &mut ctx.accounts.shielded_pool;
+    require!(delay >= MIN_EMERGENCY_DELAY, ErrorCode::EmergencyDelayTooShort);
+    require!(pool.emergency_requested_at == 0, ErrorCode::TimelockActive);
+    pool.emergency_delay = delay;
+    Ok(())
+}
+
+/// sweeps `amount` from the vault to the authority once the emergency
+/// timelock has run out, for when the sequencer is gone and roots stall
+pub fn emergency_withdraw(ctx: Context<crate::contexts::EmergencyWithdraw>, amount: u64) -> Result<()> {
+    require!(amount > 0, ErrorCode::ZeroAmount);
+    ctx.accounts.shielded_pool.consume_emergency(Clock::get()?.unix_timestamp)?;
+    let pool = &ctx.accounts.shielded_pool;
+
+    let seeds = &[
+        b"shielded_pool".as_ref(),
+        pool.mint.as_ref(),
+        &[pool.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.authority_token.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    msg!("WARNING: emergency withdrawal of {}", amount);
+    Ok(())
+}
+
+/// proof and public-input checks shared by the withdraw paths. `recipient` is
+/// whatever account the proof's recipient field binds, a token account for
+/// withdraw_shielded and a system account for withdraw_shielded_sol
+fn check_withdraw_proof(
+    pool: &Account<ShieldedPool>,
+    root_history: &AccountLoader<ShieldedRootHistory>,
+    verifier_program: &AccountInfo,
+    recipient: &Pubkey,
+    spend: &ShieldedSpend,
+    root_index: Option<u64>,
+    merkle_proof: Option<&MerkleProof>,
+) -> Result<()> {
+    verify_zk_proof(verifier_program, None, &spend.proof, &spend.public_inputs)?;
+
+    let root_bytes = parse_field(&spend.public_inputs, 0)?;
+
+    let history = root_history.load()?;
+    require!(pool.root_history == root_history.key(), ErrorCode::InvalidShieldedAccount);
+    require!(history.pool == pool.key(), ErrorCode::InvalidShieldedAccount);
+
+    match merkle_proof {
+        // a light client proves its leaf against current_root itself, the
+        // proof then has to be built on that root rather than any recent one.
+        // the leaf is no public input, so this is inclusion only and doesn't
+        // tie the path to the spent note
+        Some(merkle_proof) => {
+            require!(
+                merkle_proof.path_elements.len() == SHIELDED_TREE_DEPTH,
+                ErrorCode::InvalidMerkleProof
+            );
+            require!(
+                verify_merkle_path(
+                    merkle_proof.leaf,
+                    &merkle_proof.path_elements,
+                    merkle_proof.path_indices,
+                    pool.current_root,
+                ),
+                ErrorCode::InvalidMerkleProof
+            );
+            require!(root_bytes == pool.current_root, ErrorCode::InvalidStateRoot);
+        }
+        None => history.check_pool_root(&root_bytes, root_index, pool.strict_root)?,
+    }
+    drop(history);
+    check_withdraw_fields(
+        pool,
+        &pool.key(),
+        recipient,
+        spend.amount,
+        &spend.nullifier_hash,
+        &spend.change_commitment,
+        &spend.public_inputs,
+    )
+}
+
+/// binds the proof's public fields other than the root to the withdrawal
+fn check_withdraw_fields(
+    pool: &ShieldedPool,
+    pool_key: &Pubkey,
+    recipient: &Pubkey,
+    amount: u64,
+    nullifier_hash: &[u8; 32],
+    change_commitment: &[u8; 32],
+    public_inputs: &[u8],
+) -> Result<()> {
+    require!(parse_field(public_inputs, 1)? == *nullifier_hash, ErrorCode::InvalidProof);
+    require!(parse_field(public_inputs, 6)? == *change_commitment, ErrorCode::InvalidProof);
+
+    let proof_amount = field_to_u64(&parse_field(public_inputs, 2)?)?;
+    require!(proof_amount == amount, ErrorCode::InvalidProof);
+
+    let expected_recipient = pubkey_to_field_bytes(recipient);
+    require!(parse_field(public_inputs, 3)? == expected_recipient, ErrorCode::InvalidProof);
+
+    let expected_mint = pubkey_to_field_bytes(&pool.mint);
+    require!(parse_field(public_inputs, 4)? == expected_mint, ErrorCode::InvalidProof);
+
+    let expected_pool = pubkey_to_field_bytes(pool_key);
+    require!(parse_field(public_inputs, 5)? == expected_pool, ErrorCode::InvalidProof);
+    Ok(())
+}
+
 pub fn withdraw_shielded<'info>(
     ctx: Context<'_, '_, '_, 'info, crate::contexts::WithdrawShielded<'info>>,
-    amount: u64,
-    nullifier_hash: [u8; 32],
-    proof: Vec<u8>,
-    public_inputs: Vec<u8>,
+    spend: ShieldedSpend,
+    root_index: Option<u64>,
+    merkle_proof: Option<MerkleProof>,
 ) -> Result<()> {
-    if public_inputs.len() < PUBLIC_INPUTS_LEN * 32 {
-        return Err(ErrorCode::InvalidProof.into());
-    }
+    ensure_compute_budget(MIN_COMPUTE_UNITS_WITHDRAW)?;
+    detect_witness_format(&spend.public_inputs)?;
     require!(ctx.remaining_accounts.len() >= 2, ErrorCode::InvalidShieldedAccount);
     let vault_info = ctx.remaining_accounts[0].clone();
     let recipient_info = ctx.remaining_accounts[1].clone();
-
-    verify_zk_proof(&ctx.accounts.verifier_program, &proof, &public_inputs)?;
-
-    let root_bytes = parse_field(&public_inputs, 0)?;
-    let nullifier_hash_bytes = parse_field(&public_inputs, 1)?;
-    let amount_field = parse_field(&public_inputs, 2)?;
-    let recipient_field = parse_field(&public_inputs, 3)?;
-    let mint_field = parse_field(&public_inputs, 4)?;
-    let pool_field = parse_field(&public_inputs, 5)?;
+    ctx.accounts.shielded_pool.validate_pda(&ctx.accounts.shielded_pool.key())?;
+    ctx.accounts.shielded_pool.check_verifier(&ctx.accounts.verifier_program.key())?;
 
     let pool = &ctx.accounts.shielded_pool;
-    let history = ctx.accounts.root_history.load()?;
     require!(vault_info.key() == pool.vault, ErrorCode::InvalidShieldedAccount);
 
     let vault_account = parse_token_account(&vault_info)?;
     let recipient_account = parse_token_account(&recipient_info)?;
     require!(vault_account.mint == pool.mint, ErrorCode::InvalidShieldedAccount);
     require!(recipient_account.mint == pool.mint, ErrorCode::InvalidShieldedAccount);
-    require!(pool.root_history == ctx.accounts.root_history.key(), ErrorCode::InvalidShieldedAccount);
-    require!(history.pool == pool.key(), ErrorCode::InvalidShieldedAccount);
-
-    require!(history.contains_root(&root_bytes), ErrorCode::InvalidStateRoot);
-    require!(nullifier_hash_bytes == nullifier_hash, ErrorCode::InvalidProof);
-
-    let proof_amount = field_to_u64(&amount_field)?;
-    require!(proof_amount == amount, ErrorCode::InvalidProof);
 
-    let expected_recipient = pubkey_to_field_bytes(&recipient_info.key());
-    require!(recipient_field == expected_recipient, ErrorCode::InvalidProof);
+    check_withdraw_proof(
+        pool,
+        &ctx.accounts.root_history,
+        &ctx.accounts.verifier_program,
+        &recipient_info.key(),
+        &spend,
+        root_index,
+        merkle_proof.as_ref(),
+    )?;
 
-    let expected_mint = pubkey_to_field_bytes(&pool.mint);
-    require!(mint_field == expected_mint, ErrorCode::InvalidProof);
+    // spend the nullifier before paying out, so the note is marked spent
+    // before any tokens leave the vault
+    ensure_nullifier_account(
+        &ctx.accounts.nullifier_account.to_account_info(),
+        &ctx.accounts.relayer.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+        &ctx.accounts.shielded_pool.key(),
+        &spend.nullifier_hash,
+    )?;
+    emit!(NullifierSpent {
+        pool: ctx.accounts.shielded_pool.key(),
+        nullifier_hash: spend.nullifier_hash,
+        amount: spend.amount,
+        recipient: recipient_info.key(),
+    });
 
-    let expected_pool = pubkey_to_field_bytes(&ctx.accounts.shielded_pool.key());
-    require!(pool_field == expected_pool, ErrorCode::InvalidProof);
+    // the proof binds note amount = amount + change, the remainder stays shielded
+    let pool_key = ctx.accounts.shielded_pool.key();
+    insert_change_commitment(
+        &mut ctx.accounts.shielded_pool,
+        pool_key,
+        ctx.accounts.merkle_tree.as_ref(),
+        &ctx.accounts.root_history,
+        spend.change_commitment,
+    )?;
 
+    let pool = &ctx.accounts.shielded_pool;
     let seeds = &[
         b"shielded_pool".as_ref(),
         pool.mint.as_ref(),
@@ -238,17 +858,148 @@ pub fn withdraw_shielded<'info>(
     ];
     let signer_seeds = &[&seeds[..]];
 
-    token::transfer(
+    token_interface::transfer_checked(
         CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: vault_info.clone(),
+                mint: ctx.accounts.mint.to_account_info(),
                 to: recipient_info.clone(),
                 authority: ctx.accounts.shielded_pool.to_account_info(),
             },
             signer_seeds,
         ),
-        amount,
+        spend.amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    Ok(())
+}
+
+/// the root each spend's proof was built on, in spend order
+fn spend_roots(spends: &[ShieldedSpend]) -> Result<Vec<[u8; 32]>> {
+    spends.iter().map(|spend| parse_field(&spend.public_inputs, 0)).collect()
+}
+
+/// withdraws several notes to one recipient with a single transfer. each
+/// note has its own proof, built on whichever root it was committed under,
+/// so the roots are checked together and bounded by MAX_PROOF_ROOTS
+pub fn withdraw_shielded_batch<'info>(
+    ctx: Context<'_, '_, '_, 'info, crate::contexts::WithdrawShieldedBatch<'info>>,
+    spends: Vec<ShieldedSpend>,
+) -> Result<()> {
+    let pool_key = ctx.accounts.shielded_pool.key();
+    ctx.accounts.shielded_pool.validate_pda(&pool_key)?;
+    ctx.accounts.shielded_pool.check_verifier(&ctx.accounts.verifier_program.key())?;
+    require!(ctx.remaining_accounts.len() == spends.len(), ErrorCode::InvalidShieldedAccount);
+
+    let roots = spend_roots(&spends)?;
+    {
+        let history = ctx.accounts.root_history.load()?;
+        require!(ctx.accounts.shielded_pool.root_history == ctx.accounts.root_history.key(), ErrorCode::InvalidShieldedAccount);
+        require!(history.pool == pool_key, ErrorCode::InvalidShieldedAccount);
+        history.contains_roots(&roots)?;
+        if ctx.accounts.shielded_pool.strict_root {
+            for root in &roots {
+                history.check_pool_root(root, None, true)?;
+            }
+        }
+    }
+    ensure_compute_budget(MIN_COMPUTE_UNITS_WITHDRAW * spends.len() as u64)?;
+
+    let recipient = ctx.accounts.recipient_token.key();
+    let mut total: u64 = 0;
+    for (spend, nullifier_info) in spends.iter().zip(ctx.remaining_accounts) {
+        detect_witness_format(&spend.public_inputs)?;
+        verify_zk_proof(&ctx.accounts.verifier_program, None, &spend.proof, &spend.public_inputs)?;
+        check_withdraw_fields(
+            &ctx.accounts.shielded_pool,
+            &pool_key,
+            &recipient,
+            spend.amount,
+            &spend.nullifier_hash,
+            &spend.change_commitment,
+            &spend.public_inputs,
+        )?;
+
+        // a nullifier repeated within the batch fails here on its second spend
+        ensure_nullifier_account(
+            nullifier_info,
+            &ctx.accounts.relayer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &pool_key,
+            &spend.nullifier_hash,
+        )?;
+        emit!(NullifierSpent {
+            pool: pool_key,
+            nullifier_hash: spend.nullifier_hash,
+            amount: spend.amount,
+            recipient,
+        });
+        insert_change_commitment(
+            &mut ctx.accounts.shielded_pool,
+            pool_key,
+            ctx.accounts.merkle_tree.as_ref(),
+            &ctx.accounts.root_history,
+            spend.change_commitment,
+        )?;
+        total = total.checked_add(spend.amount).ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    let pool = &ctx.accounts.shielded_pool;
+    let seeds = &[
+        b"shielded_pool".as_ref(),
+        pool.mint.as_ref(),
+        &[pool.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.recipient_token.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        total,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    msg!("Shielded batch withdrawal: {} notes, {} total", spends.len(), total);
+    Ok(())
+}
+
+/// withdraw_shielded for a wrapped sol pool that pays out native sol. the
+/// proof's recipient is the recipient's system account. the wsol moves into
+/// a pool-owned unwrap account which is closed to the relayer, and the
+/// relayer forwards `amount` lamports to the recipient, keeping the rent it
+/// put up for the unwrap account
+pub fn withdraw_shielded_sol(
+    ctx: Context<crate::contexts::WithdrawShieldedSol>,
+    amount: u64,
+    nullifier_hash: [u8; 32],
+    change_commitment: [u8; 32],
+    proof: Vec<u8>,
+    public_inputs: Vec<u8>,
+    root_index: Option<u64>,
+) -> Result<()> {
+    ensure_compute_budget(MIN_COMPUTE_UNITS_WITHDRAW)?;
+    detect_witness_format(&public_inputs)?;
+    ctx.accounts.shielded_pool.validate_pda(&ctx.accounts.shielded_pool.key())?;
+    ctx.accounts.shielded_pool.check_verifier(&ctx.accounts.verifier_program.key())?;
+
+    check_withdraw_proof(
+        &ctx.accounts.shielded_pool,
+        &ctx.accounts.root_history,
+        &ctx.accounts.verifier_program,
+        &ctx.accounts.recipient.key(),
+        &ShieldedSpend { amount, nullifier_hash, change_commitment, proof, public_inputs },
+        root_index,
+        None,
     )?;
 
     ensure_nullifier_account(
@@ -258,7 +1009,68 @@ pub fn withdraw_shielded<'info>(
         &ctx.accounts.shielded_pool.key(),
         &nullifier_hash,
     )?;
+    emit!(NullifierSpent {
+        pool: ctx.accounts.shielded_pool.key(),
+        nullifier_hash,
+        amount,
+        recipient: ctx.accounts.recipient.key(),
+    });
 
+    let pool_key = ctx.accounts.shielded_pool.key();
+    insert_change_commitment(
+        &mut ctx.accounts.shielded_pool,
+        pool_key,
+        ctx.accounts.merkle_tree.as_ref(),
+        &ctx.accounts.root_history,
+        change_commitment,
+    )?;
+
+    let pool = &ctx.accounts.shielded_pool;
+    let seeds = &[
+        b"shielded_pool".as_ref(),
+        pool.mint.as_ref(),
+        &[pool.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.unwrap_account.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    // closing a native account releases the wrapped amount and its rent together
+    token_interface::close_account(CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.unwrap_account.to_account_info(),
+            destination: ctx.accounts.relayer.to_account_info(),
+            authority: pool.to_account_info(),
+        },
+        signer_seeds,
+    ))?;
+
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.relayer.to_account_info(),
+                to: ctx.accounts.recipient.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    msg!("Shielded withdrawal unwrapped: {} lamports", amount);
     Ok(())
 }
 
@@ -266,26 +1078,70 @@ pub fn withdraw_shielded<'info>(
 // shielded swap, uses shielded deposits as private input
 // -----------------------------------------------------------------------------
 
+/// splits the remaining accounts after swap_private's fixed four into the
+/// optional verified_proof cache and the swap hook accounts. a cache is a
+/// pda of this program and never the hook program, so an extra account that
+/// is the hook program starts the hook accounts
+fn split_swap_extras<'a, 'info>(
+    extras: &'a [AccountInfo<'info>],
+    hook_program: Option<Pubkey>,
+) -> (Option<&'a AccountInfo<'info>>, &'a [AccountInfo<'info>]) {
+    match extras.split_first() {
+        Some((first, rest)) if Some(*first.key) != hook_program => (Some(first), rest),
+        _ => (None, extras),
+    }
+}
+
 pub fn swap_private<'info>(
     ctx: Context<'_, '_, '_, 'info, crate::contexts::SwapPrivate<'info>>,
-    proof: Vec<u8>,
-    public_inputs: Vec<u8>,
-    amount_in: u64,
-    min_out: u64,
-    is_a_to_b: bool,
-    nullifier_hash: [u8; 32],
+    args: SwapPrivateArgs,
 ) -> Result<()> {
-    if public_inputs.len() < PUBLIC_INPUTS_LEN * 32 {
-        return Err(ErrorCode::InvalidProof.into());
-    }
+    let SwapPrivateArgs {
+        proof,
+        public_inputs,
+        amount_in,
+        min_out,
+        accept_any_output,
+        deadline,
+        is_a_to_b,
+        nullifier_hash,
+        change_commitment,
+        root_index,
+    } = args;
+    ensure_compute_budget(MIN_COMPUTE_UNITS_SWAP_PRIVATE)?;
+    require!(!ctx.accounts.pool.paused, ErrorCode::PoolPaused);
+    check_deadline(deadline)?;
+    check_min_out(min_out, accept_any_output)?;
+    detect_witness_format(&public_inputs)?;
     require!(ctx.remaining_accounts.len() >= 4, ErrorCode::InvalidShieldedAccount);
     let shielded_vault_info = ctx.remaining_accounts[0].clone();
     let reserve_in_info = ctx.remaining_accounts[1].clone();
     let reserve_out_info = ctx.remaining_accounts[2].clone();
     let recipient_info = ctx.remaining_accounts[3].clone();
+    let (cache_info, hook_accounts) = split_swap_extras(&ctx.remaining_accounts[4..], ctx.accounts.pool.hook_program);
+    ctx.accounts.pool.validate_pda(&ctx.accounts.pool.key())?;
+    ctx.accounts.input_shielded_pool.validate_pda(&ctx.accounts.input_shielded_pool.key())?;
+    ctx.accounts.input_shielded_pool.check_verifier(&ctx.accounts.verifier_program.key())?;
 
-    // 1) verify zk proof for note ownership
-    verify_zk_proof(&ctx.accounts.verifier_program, &proof, &public_inputs)?;
+    lock_pool(&mut ctx.accounts.pool)?;
+
+    // 1) verify zk proof for note ownership, unless a retry brought a cached verification
+    let cached = match cache_info {
+        Some(cache_info) => consume_verified_proof(
+            cache_info,
+            &ctx.accounts.relayer.to_account_info(),
+            &ctx.accounts.input_shielded_pool.key(),
+            &ctx.accounts.verifier_program.key(),
+            &nullifier_hash,
+            &public_inputs,
+        )?,
+        None => false,
+    };
+    if cached {
+        debug_msg!("Using cached proof verification");
+    } else {
+        verify_zk_proof(&ctx.accounts.verifier_program, None, &proof, &public_inputs)?;
+    }
 
     let root_bytes = parse_field(&public_inputs, 0)?;
     let nullifier_hash_bytes = parse_field(&public_inputs, 1)?;
@@ -293,6 +1149,8 @@ pub fn swap_private<'info>(
     let recipient_field = parse_field(&public_inputs, 3)?;
     let mint_field = parse_field(&public_inputs, 4)?;
     let pool_field = parse_field(&public_inputs, 5)?;
+    let change_field = parse_field(&public_inputs, 6)?;
+    require!(change_field == change_commitment, ErrorCode::InvalidProof);
 
     let pool = &mut ctx.accounts.pool;
 
@@ -314,9 +1172,15 @@ pub fn swap_private<'info>(
     };
     require!(mint_field == expected_mint_in, ErrorCode::InvalidProof);
 
+    ensure_reserve_open(&reserve_in_info)?;
+    ensure_reserve_open(&reserve_out_info)?;
     let reserve_in_account = parse_token_account(&reserve_in_info)?;
     let reserve_out_account = parse_token_account(&reserve_out_info)?;
     let recipient_account = parse_token_account(&recipient_info)?;
+    require!(
+        !reserve_in_account.is_frozen() && !reserve_out_account.is_frozen(),
+        ErrorCode::ReserveFrozen
+    );
     let reserve_in_mint = reserve_in_account.mint;
     let reserve_out_mint = reserve_out_account.mint;
     let expected_in_mint = if is_a_to_b { pool.token_a_mint } else { pool.token_b_mint };
@@ -332,8 +1196,27 @@ pub fn swap_private<'info>(
     require!(input_history.pool == input_pool.key(), ErrorCode::InvalidProof);
     require!(reserve_in_mint == expected_in_mint, ErrorCode::InvalidProof);
     require!(reserve_out_mint == expected_out_mint, ErrorCode::InvalidProof);
+    // matching mints alone would accept reserves of another pool for the same pair
+    let pool_key = pool.key();
+    require!(
+        *reserve_in_info.owner == ctx.accounts.token_program.key()
+            && *reserve_out_info.owner == ctx.accounts.token_program.key(),
+        ErrorCode::InvalidShieldedAccount
+    );
+    require!(
+        reserve_in_account.owner == pool_key && reserve_out_account.owner == pool_key,
+        ErrorCode::InvalidShieldedAccount
+    );
+    // remaining accounts are unchecked, so rule out aliasing: the same reserve
+    // on both sides, or a reserve as recipient, would net the transfers out
+    require!(reserve_in_info.key() != reserve_out_info.key(), ErrorCode::InvalidShieldedAccount);
+    require!(
+        recipient_info.key() != reserve_in_info.key() && recipient_info.key() != reserve_out_info.key(),
+        ErrorCode::InvalidShieldedAccount
+    );
 
-    require!(input_history.contains_root(&root_bytes), ErrorCode::InvalidStateRoot);
+    input_history.check_pool_root(&root_bytes, root_index, input_pool.strict_root)?;
+    drop(input_history);
 
     ensure_nullifier_account(
         &ctx.accounts.nullifier_account.to_account_info(),
@@ -342,6 +1225,12 @@ pub fn swap_private<'info>(
         &ctx.accounts.input_shielded_pool.key(),
         &nullifier_hash,
     )?;
+    emit!(NullifierSpent {
+        pool: ctx.accounts.input_shielded_pool.key(),
+        nullifier_hash,
+        amount: amount_in,
+        recipient: recipient_key,
+    });
 
     // 2) move amount_in from shielded vault to amm reserve
     let input_vault_seeds = &[
@@ -351,18 +1240,29 @@ pub fn swap_private<'info>(
     ];
     let input_vault_signer = &[&input_vault_seeds[..]];
 
-    token::transfer(
+    let (mint_in, mint_out, decimals_in, decimals_out) = if is_a_to_b {
+        (&ctx.accounts.token_a_mint, &ctx.accounts.token_b_mint, pool.token_a_decimals, pool.token_b_decimals)
+    } else {
+        (&ctx.accounts.token_b_mint, &ctx.accounts.token_a_mint, pool.token_b_decimals, pool.token_a_decimals)
+    };
+    let charges_fee = mint_charges_transfer_fee(&mint_in.to_account_info())?;
+    let balance_before = token_balance(&reserve_in_info)?;
+    token_interface::transfer_checked(
         CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: shielded_vault_info.clone(),
+                mint: mint_in.to_account_info(),
                 to: reserve_in_info.clone(),
                 authority: ctx.accounts.input_shielded_pool.to_account_info(),
             },
             input_vault_signer,
         ),
         amount_in,
+        decimals_in,
     )?;
+    // the note is spent in full, but only what reached the reserve is traded
+    let amount_in = received_amount(balance_before, token_balance(&reserve_in_info)?, amount_in, charges_fee)?;
 
     // 3) execute amm swap and send output to recipient
     let (reserve_in_amount, reserve_out_amount) = if is_a_to_b {
@@ -371,8 +1271,11 @@ pub fn swap_private<'info>(
         (pool.token_b_reserve, pool.token_a_reserve)
     };
 
-    let amount_out = get_amount_out(amount_in, reserve_in_amount, reserve_out_amount)?;
+    check_swap_size(amount_in, reserve_in_amount, pool.max_swap_bps)?;
+    let fee_bps = pool.swap_fee_bps(amount_in, reserve_in_amount);
+    let amount_out = get_amount_out(amount_in, reserve_in_amount, reserve_out_amount, fee_bps)?;
     require!(amount_out >= min_out, ErrorCode::SlippageExceeded);
+    pool.check_min_output(amount_out)?;
 
     let pool_seeds = &[
         b"pool".as_ref(),
@@ -382,28 +1285,402 @@ pub fn swap_private<'info>(
     ];
     let pool_signer = &[&pool_seeds[..]];
 
-    token::transfer(
+    token_interface::transfer_checked(
         CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: reserve_out_info.clone(),
+                mint: mint_out.to_account_info(),
                 to: recipient_info.clone(),
                 authority: pool.to_account_info(),
             },
             pool_signer,
         ),
         amount_out,
+        decimals_out,
     )?;
 
     // update amm reserves
-    if is_a_to_b {
-        pool.token_a_reserve = pool.token_a_reserve.checked_add(amount_in).ok_or(ErrorCode::MathOverflow)?;
-        pool.token_b_reserve = pool.token_b_reserve.checked_sub(amount_out).ok_or(ErrorCode::MathOverflow)?;
-    } else {
-        pool.token_b_reserve = pool.token_b_reserve.checked_add(amount_in).ok_or(ErrorCode::MathOverflow)?;
-        pool.token_a_reserve = pool.token_a_reserve.checked_sub(amount_out).ok_or(ErrorCode::MathOverflow)?;
-    }
+    let now = Clock::get()?.unix_timestamp;
+    pool.accumulate_price(now);
+    let reserve_a_before = pool.token_a_reserve;
+    let reserve_b_before = pool.token_b_reserve;
+    // the fee stays in the input reserve like on the public swaps, booked
+    // so collect_fees and the fee views see private swap income too
+    let fee = pool.book_swap(is_a_to_b, amount_in, amount_out, fee_bps)?;
+    pool.last_swap_at = now;
 
-    msg!("Shielded swap executed. Out: {}", amount_out);
+    emit!(ReserveUpdateEvent {
+        pool: pool.key(),
+        reserve_a_before,
+        reserve_a_after: pool.token_a_reserve,
+        reserve_b_before,
+        reserve_b_after: pool.token_b_reserve,
+        reason: ReserveUpdateReason::PrivateSwap,
+    });
+    let k_before = (reserve_a_before as u128)
+        .checked_mul(reserve_b_before as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let k_after = (pool.token_a_reserve as u128)
+        .checked_mul(pool.token_b_reserve as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    check_swap_invariant(k_before, k_after)?;
+    emit!(SwapEvent {
+        pool: pool.key(),
+        user: Pubkey::default(),
+        amount_in,
+        amount_out,
+        direction: if is_a_to_b { SWAP_DIRECTION_A_TO_B } else { SWAP_DIRECTION_B_TO_A },
+        fee,
+        new_reserve_a: pool.token_a_reserve,
+        new_reserve_b: pool.token_b_reserve,
+        k_before,
+        k_after,
+    });
+    emit!(PrivateSwapEvent {
+        pool: pool.key(),
+        is_a_to_b,
+        amount_in,
+        amount_out,
+        reserve_a_after: pool.token_a_reserve,
+        reserve_b_after: pool.token_b_reserve,
+    });
+
+    let direction = SwapDirection::from_a_to_b(is_a_to_b);
+    call_swap_hook(pool, hook_accounts, amount_in, amount_out, direction)?;
+    pool.locked = false;
+
+    // the rest of the input note goes back into the input shielded pool
+    let input_pool_key = ctx.accounts.input_shielded_pool.key();
+    insert_change_commitment(
+        &mut ctx.accounts.input_shielded_pool,
+        input_pool_key,
+        ctx.accounts.input_merkle_tree.as_ref(),
+        &ctx.accounts.input_root_history,
+        change_commitment,
+    )?;
+
+    debug_msg!("Shielded swap executed. Out: {}", amount_out);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn witness(header: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        if header {
+            for count in [PUBLIC_INPUTS_LEN as u32, 0, PUBLIC_INPUTS_LEN as u32] {
+                buf.extend_from_slice(&count.to_be_bytes());
+            }
+        }
+        for i in 0..PUBLIC_INPUTS_LEN {
+            buf.extend_from_slice(&[i as u8 + 1; 32]);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_parse_field_both_formats() {
+        let raw = witness(false);
+        let with_header = witness(true);
+        assert_eq!(raw.len(), PUBLIC_INPUTS_LEN * 32);
+        assert_eq!(with_header.len(), PUBLIC_INPUTS_LEN * 32 + 12);
+
+        for i in 0..PUBLIC_INPUTS_LEN {
+            let a = parse_field(&raw, i).unwrap();
+            let b = parse_field(&with_header, i).unwrap();
+            assert_eq!(a, b);
+            assert_eq!(a, [i as u8 + 1; 32]);
+        }
+        assert!(parse_field(&raw, PUBLIC_INPUTS_LEN).is_err());
+    }
+
+    #[test]
+    fn test_parse_field_rejects_wrong_count() {
+        let mut buf = witness(false);
+        buf.extend_from_slice(&[9u8; 32]);
+        assert_eq!(buf.len(), (PUBLIC_INPUTS_LEN + 1) * 32);
+        for i in 0..PUBLIC_INPUTS_LEN + 1 {
+            assert!(parse_field(&buf, i).is_err());
+        }
+        assert!(parse_field(&witness(false)[..5 * 32], 0).is_err());
+    }
+
+    #[test]
+    fn test_detect_witness_format() {
+        assert_eq!(detect_witness_format(&witness(false)).unwrap(), WitnessFormat::Raw);
+        assert_eq!(detect_witness_format(&witness(true)).unwrap(), WitnessFormat::NoirHeader);
+        assert_eq!(WitnessFormat::Raw.header_len(), 0);
+        assert_eq!(WitnessFormat::NoirHeader.header_len(), 12);
+        assert!(detect_witness_format(&witness(false)[..5 * 32]).is_err());
+        assert!(detect_witness_format(&witness(true)[1..]).is_err());
+        assert!(detect_witness_format(&[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_field_strict_lengths() {
+        let truncated = &witness(false)[..PUBLIC_INPUTS_LEN * 32 - 32];
+        assert!(parse_field(truncated, 0).is_err());
+
+        let mut off_by_one = witness(false);
+        off_by_one.push(0);
+        assert!(parse_field(&off_by_one, 0).is_err());
+        assert!(parse_field(&witness(true)[..PUBLIC_INPUTS_LEN * 32 + 11], 0).is_err());
+
+        let headered = witness(true);
+        assert_eq!(parse_field(&headered, PUBLIC_INPUTS_LEN - 1).unwrap(), [PUBLIC_INPUTS_LEN as u8; 32]);
+    }
+
+    #[test]
+    fn test_parse_field_rejects_malformed_header() {
+        for byte in [3, 7, 11] {
+            let mut buf = witness(true);
+            buf[byte] ^= 1;
+            assert!(detect_witness_format(&buf).is_err());
+            assert!(parse_field(&buf, 0).is_err());
+        }
+    }
+
+    #[test]
+    fn test_batch_total() {
+        assert_eq!(batch_total(&[1, 2, 3], 3).unwrap(), 6);
+        assert_eq!(batch_total(&[1; MAX_DEPOSIT_BATCH], MAX_DEPOSIT_BATCH).unwrap(), MAX_DEPOSIT_BATCH as u64);
+        assert!(batch_total(&[], 0).is_err());
+        assert!(batch_total(&[1, 2], 3).is_err());
+        assert!(batch_total(&[1; MAX_DEPOSIT_BATCH + 1], MAX_DEPOSIT_BATCH + 1).is_err());
+        assert!(batch_total(&[1, 0], 2).is_err());
+        assert!(batch_total(&[u64::MAX, 1], 2).is_err());
+    }
+
+    #[test]
+    fn test_nullifier_spent_before_second_withdraw() {
+        let pool_key = Pubkey::new_unique();
+        let nullifier_hash = [9u8; 32];
+        let (key, _) = Pubkey::find_program_address(&[b"nullifier", pool_key.as_ref(), &nullifier_hash], &crate::ID);
+        let owner = crate::ID;
+        let mut lamports = 1_000_000;
+        let mut data = Vec::new();
+        Nullifier { spent: false }.try_serialize(&mut data).unwrap();
+        let nullifier_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+
+        let payer_key = Pubkey::new_unique();
+        let mut payer_lamports = 0;
+        let mut payer_data = Vec::new();
+        let payer_info = AccountInfo::new(
+            &payer_key, true, true, &mut payer_lamports, &mut payer_data, &system_program::ID, false, 0,
+        );
+        let system_key = system_program::ID;
+        let mut system_lamports = 0;
+        let mut system_data = Vec::new();
+        let system_info = AccountInfo::new(
+            &system_key, false, false, &mut system_lamports, &mut system_data, &system_program::ID, true, 0,
+        );
+
+        ensure_nullifier_account(&nullifier_info, &payer_info, &system_info, &pool_key, &nullifier_hash).unwrap();
+        let replay = ensure_nullifier_account(&nullifier_info, &payer_info, &system_info, &pool_key, &nullifier_hash);
+        assert_eq!(replay.unwrap_err(), ErrorCode::NullifierAlreadySpent.into());
+    }
+
+    #[test]
+    fn test_change_commitment_takes_next_leaf() {
+        let mut pool = shielded_pool(Pubkey::default());
+        pool.next_index = 5;
+        let history_key = Pubkey::new_unique();
+        let owner = crate::ID;
+        let mut lamports = 1_000_000;
+        let mut data = vec![0u8; ShieldedRootHistory::LEN];
+        data[..8].copy_from_slice(ShieldedRootHistory::DISCRIMINATOR);
+        let history_info = AccountInfo::new(&history_key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        let history = AccountLoader::<ShieldedRootHistory>::try_from(&history_info).unwrap();
+
+        let pool_key = Pubkey::new_unique();
+        let zero = insert_change_commitment(&mut pool, pool_key, None, &history, [0; 32]);
+        assert_eq!(zero.unwrap_err(), ErrorCode::InvalidChangeCommitment.into());
+        assert_eq!(pool.next_index, 5);
+
+        insert_change_commitment(&mut pool, pool_key, None, &history, [1; 32]).unwrap();
+        assert_eq!(pool.next_index, 6);
+    }
+
+    #[test]
+    fn test_field_amount_bounds() {
+        let field = |value: u128| {
+            let mut out = [0u8; 32];
+            out[16..].copy_from_slice(&value.to_be_bytes());
+            out
+        };
+        assert_eq!(field_to_u64(&field(u64::MAX as u128)).unwrap(), u64::MAX);
+        assert_eq!(field_to_u128(&field(u64::MAX as u128 + 1)).unwrap(), u64::MAX as u128 + 1);
+        assert_eq!(
+            field_to_u64(&field(u64::MAX as u128 + 1)).unwrap_err(),
+            ErrorCode::AmountTooLarge.into()
+        );
+
+        let mut malformed = field(1);
+        malformed[0] = 1;
+        assert_eq!(field_to_u128(&malformed).unwrap_err(), ErrorCode::InvalidProof.into());
+        assert_eq!(field_to_u64(&malformed).unwrap_err(), ErrorCode::InvalidProof.into());
+    }
+
+    #[test]
+    fn test_check_commitment_opening() {
+        let opening = CommitmentOpening { secret: [3; 32], recipient: Pubkey::new_unique() };
+        let commitment = compute_commitment(50, opening.secret, &opening.recipient);
+        assert!(check_commitment_opening(50, &[9; 32], None).is_ok());
+        if cfg!(feature = "plain-commitments") {
+            assert!(check_commitment_opening(50, &commitment, Some(&opening)).is_ok());
+            assert_eq!(
+                check_commitment_opening(51, &commitment, Some(&opening)).unwrap_err(),
+                ErrorCode::CommitmentMismatch.into()
+            );
+        } else {
+            assert_eq!(
+                check_commitment_opening(50, &commitment, Some(&opening)).unwrap_err(),
+                ErrorCode::PlainCommitmentsDisabled.into()
+            );
+        }
+    }
+
+    #[test]
+    fn test_zero_deposit_rejected() {
+        assert_eq!(check_deposit_amount(0).unwrap_err(), ErrorCode::ZeroAmount.into());
+        assert!(check_deposit_amount(1).is_ok());
+    }
+
+    #[test]
+    fn test_split_swap_extras() {
+        let hook = Pubkey::new_unique();
+        let (cache_key, hook_account_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = crate::ID;
+        let (mut l1, mut l2, mut l3) = (0u64, 0u64, 0u64);
+        let (mut d1, mut d2, mut d3) = (vec![], vec![], vec![]);
+        let cache = AccountInfo::new(&cache_key, false, true, &mut l1, &mut d1, &owner, false, 0);
+        let hook_info = AccountInfo::new(&hook, false, false, &mut l2, &mut d2, &owner, true, 0);
+        let hook_account = AccountInfo::new(&hook_account_key, false, true, &mut l3, &mut d3, &owner, false, 0);
+
+        let extras = [cache.clone(), hook_info.clone(), hook_account.clone()];
+        let (found, hooks) = split_swap_extras(&extras, Some(hook));
+        assert_eq!(found.map(|c| *c.key), Some(cache_key));
+        assert_eq!(hooks.len(), 2);
+        assert_eq!(*hooks[0].key, hook);
+
+        // no cache, the hook program comes first
+        let extras = [hook_info.clone(), hook_account.clone()];
+        let (found, hooks) = split_swap_extras(&extras, Some(hook));
+        assert!(found.is_none());
+        assert_eq!(hooks.len(), 2);
+
+        // without a hook the lone extra is the cache
+        let extras = [cache];
+        let (found, hooks) = split_swap_extras(&extras, None);
+        assert_eq!(found.map(|c| *c.key), Some(cache_key));
+        assert!(hooks.is_empty());
+        assert!(split_swap_extras(&[], Some(hook)).0.is_none());
+    }
+
+    fn shielded_pool(mint: Pubkey) -> ShieldedPool {
+        ShieldedPool {
+            mint,
+            vault: Pubkey::default(),
+            authority: Pubkey::default(),
+            current_root: [0; 32],
+            root_history: Pubkey::default(),
+            next_index: 0,
+            bump: 0,
+            last_rooted_index: 0,
+            paused: false,
+            emergency_delay: DEFAULT_EMERGENCY_DELAY,
+            emergency_requested_at: 0,
+            expected_verifier: Pubkey::default(),
+            strict_root: false,
+            has_tree: false,
+        }
+    }
+
+    #[test]
+    fn test_withdraw_sol_rejects_a_non_native_pool() {
+        let pool = shielded_pool(Pubkey::new_unique());
+        assert_eq!(pool.check_native_mint().unwrap_err(), ErrorCode::NotNativeMint.into());
+    }
+
+    #[test]
+    fn test_withdraw_sol_native_pool_binds_a_system_recipient() {
+        let pool = shielded_pool(anchor_spl::token::spl_token::native_mint::ID);
+        pool.check_native_mint().unwrap();
+
+        // the public fields a wsol withdrawal proof carries, paid to a
+        // system account rather than a token account
+        let (pool_key, recipient) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (nullifier_hash, change_commitment) = ([3u8; 32], [4u8; 32]);
+        let amount = 1_500_000_000u64;
+        let mut amount_field = [0u8; 32];
+        amount_field[24..].copy_from_slice(&amount.to_be_bytes());
+        let fields = [
+            [1u8; 32],
+            nullifier_hash,
+            amount_field,
+            pubkey_to_field_bytes(&recipient),
+            pubkey_to_field_bytes(&pool.mint),
+            pubkey_to_field_bytes(&pool_key),
+            change_commitment,
+        ];
+        let public_inputs = fields.concat();
+
+        check_withdraw_fields(&pool, &pool_key, &recipient, amount, &nullifier_hash, &change_commitment, &public_inputs)
+            .unwrap();
+        // the proof is bound to its recipient and amount
+        let other = Pubkey::new_unique();
+        assert!(check_withdraw_fields(&pool, &pool_key, &other, amount, &nullifier_hash, &change_commitment, &public_inputs).is_err());
+        assert!(check_withdraw_fields(&pool, &pool_key, &recipient, amount - 1, &nullifier_hash, &change_commitment, &public_inputs).is_err());
+    }
+
+    #[test]
+    fn test_spend_roots() {
+        let spend = |root: u8| ShieldedSpend {
+            amount: 1,
+            nullifier_hash: [root; 32],
+            change_commitment: [1u8; 32],
+            proof: vec![1u8; 256],
+            public_inputs: [[root; 32], [0u8; 32], [0u8; 32], [0u8; 32], [0u8; 32], [0u8; 32], [0u8; 32]].concat(),
+        };
+        let roots = spend_roots(&[spend(7), spend(9), spend(7)]).unwrap();
+        assert_eq!(roots, vec![[7u8; 32], [9u8; 32], [7u8; 32]]);
+
+        // a malformed witness in any spend fails the whole batch
+        let mut bad = spend(3);
+        bad.public_inputs.pop();
+        assert!(spend_roots(&[spend(1), bad]).is_err());
+
+        // every spend counts towards the cap, repeated roots included
+        let mut history: ShieldedRootHistory = bytemuck::Zeroable::zeroed();
+        history.append_root([7u8; 32]);
+        history.append_root([9u8; 32]);
+        history.contains_roots(&roots).unwrap();
+        let too_many = spend_roots(&vec![spend(7); crate::state::shielded::MAX_PROOF_ROOTS + 1]).unwrap();
+        assert_eq!(history.contains_roots(&too_many).unwrap_err(), ErrorCode::TooManyRoots.into());
+    }
+
+    #[test]
+    fn test_mint_allowlist_enforced_once_initialized() {
+        let (key, _) = Pubkey::find_program_address(&[b"shielded_mint_allowlist"], &crate::ID);
+        let listed = Pubkey::new_unique();
+        let unlisted = Pubkey::new_unique();
+
+        let mut lamports = 0;
+        let mut data = Vec::new();
+        let missing = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &system_program::ID, false, 0);
+        check_mint_allowed(&missing, &unlisted).unwrap();
+
+        let owner = crate::ID;
+        let mut lamports = 1_000_000;
+        let mut data = Vec::new();
+        ShieldedMintAllowlist { authority: Pubkey::default(), mints: vec![listed], bump: 0 }
+            .try_serialize(&mut data)
+            .unwrap();
+        let allowlist = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        check_mint_allowed(&allowlist, &listed).unwrap();
+        assert_eq!(check_mint_allowed(&allowlist, &unlisted).unwrap_err(), ErrorCode::MintNotAllowed.into());
+    }
+}
@@ -1,8 +1,21 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, Transfer};
-use crate::state::Pool;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token;
+use anchor_spl::token_interface::spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use anchor_spl::token_interface::spl_token_2022::extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions};
+use anchor_spl::token_interface::{self, spl_token_2022, TokenAccount, TransferChecked};
+use crate::contexts::{CommitProof, SettleSwap, SwapRouteTwo};
+use crate::state::{
+    Pool, ReserveUpdateEvent, ReserveUpdateReason, RouteBounds, SwapAccounts, SwapBounds, SwapEvent, SwapResult, SWAP_DIRECTION_A_TO_B,
+    SWAP_DIRECTION_B_TO_A, SWAP_HOOK_DISCRIMINATOR,
+};
 use crate::errors::ErrorCode;
-use crate::math::{get_amount_out, calculate_fee, verify_zk_proof};
+use crate::math::{
+    check_price_impact, check_swap_invariant, check_swap_size, execution_price, get_amount_in_dynamic, get_amount_out, received_amount, slippage_floor,
+    verify_zk_proof,
+};
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum SwapDirection {
@@ -10,37 +23,218 @@ pub enum SwapDirection {
     BToA,
 }
 
+impl SwapDirection {
+    pub fn from_a_to_b(is_a_to_b: bool) -> Self {
+        if is_a_to_b {
+            SwapDirection::AToB
+        } else {
+            SwapDirection::BToA
+        }
+    }
+}
+
+/// the accounts a public swap moves tokens through, on the pool's a and b
+/// sides. execute_swap takes the in and out side from the swap direction
+pub struct PoolSwapAccounts<'a, 'info> {
+    pub token_program: AccountInfo<'info>,
+    pub user: AccountInfo<'info>,
+    pub user_token_a: AccountInfo<'info>,
+    pub user_token_b: AccountInfo<'info>,
+    pub token_a_reserve: AccountInfo<'info>,
+    pub token_b_reserve: AccountInfo<'info>,
+    pub token_a_mint: AccountInfo<'info>,
+    pub token_b_mint: AccountInfo<'info>,
+    /// the instruction's remaining accounts, handed to the swap hook
+    pub hook_accounts: &'a [AccountInfo<'info>],
+}
+
+impl<'info> PoolSwapAccounts<'_, 'info> {
+    /// the mint the user pays with in a swap in `direction`
+    pub fn mint_in(&self, direction: SwapDirection) -> &AccountInfo<'info> {
+        match direction {
+            SwapDirection::AToB => &self.token_a_mint,
+            SwapDirection::BToA => &self.token_b_mint,
+        }
+    }
+}
+
+/// a public swap proof and the verifier program it is checked by
+pub struct SwapProof<'a, 'info> {
+    pub verifier_program: AccountInfo<'info>,
+    /// set when the pool's verifier_requires_state is
+    pub verifier_state: Option<AccountInfo<'info>>,
+    pub proof: &'a [u8],
+    pub public_inputs: &'a [u8],
+}
+
+/// true when the mint carries a token-2022 transfer fee, so a transfer into
+/// the pool can land short of its nominal amount
+pub fn mint_charges_transfer_fee(mint: &AccountInfo) -> Result<bool> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(false);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(mint.get_extension_types()?.contains(&ExtensionType::TransferFeeConfig))
+}
+
+/// what a transfer of `amount` leaves in the destination once the mint's
+/// transfer fee for `epoch` is taken, `amount` itself for any other mint
+pub fn amount_after_transfer_fee(mint: &AccountInfo, amount: u64, epoch: u64) -> Result<u64> {
+    if !mint_charges_transfer_fee(mint)? {
+        return Ok(amount);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    let fee = mint
+        .get_extension::<TransferFeeConfig>()?
+        .calculate_epoch_fee(epoch, amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(amount.checked_sub(fee).ok_or(ErrorCode::MathOverflow)?)
+}
+
+/// what has to be sent so that `amount` lands in the destination after the
+/// mint's transfer fee for `epoch`, `amount` itself for any other mint
+pub fn amount_before_transfer_fee(mint: &AccountInfo, amount: u64, epoch: u64) -> Result<u64> {
+    if !mint_charges_transfer_fee(mint)? {
+        return Ok(amount);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    let fee = mint
+        .get_extension::<TransferFeeConfig>()?
+        .calculate_inverse_epoch_fee(epoch, amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?)
+}
+
+/// a reserve closed between quote and swap is left with no lamports or data
+/// and back under the system program, caught here before it fails to parse
+pub fn ensure_reserve_open(reserve: &AccountInfo) -> Result<()> {
+    require!(
+        reserve.lamports() > 0
+            && !reserve.data_is_empty()
+            && *reserve.owner != anchor_lang::solana_program::system_program::ID,
+        ErrorCode::ReserveAccountMissing
+    );
+    Ok(())
+}
+
+/// live balance of a classic or token-2022 token account
+pub fn token_balance(account: &AccountInfo) -> Result<u64> {
+    Ok(TokenAccount::try_deserialize(&mut &account.try_borrow_data()?[..])?.amount)
+}
+
+/// books an executed swap against the pool: moves the tracked reserves,
+/// accrues the fee on the input side and emits the swap events
+fn record_swap(
+    pool: &mut Account<Pool>,
+    user: Pubkey,
+    amount_in: u64,
+    amount_out: u64,
+    fee_bps: u16,
+    direction: SwapDirection,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    pool.accumulate_price(now);
+
+    let reserve_a_before = pool.token_a_reserve;
+    let reserve_b_before = pool.token_b_reserve;
+    let k_before = (pool.token_a_reserve as u128)
+        .checked_mul(pool.token_b_reserve as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    let fee = pool.book_swap(direction == SwapDirection::AToB, amount_in, amount_out, fee_bps)?;
+    pool.last_swap_at = now;
+
+    let k_after = (pool.token_a_reserve as u128)
+        .checked_mul(pool.token_b_reserve as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    check_swap_invariant(k_before, k_after)?;
+
+    emit!(SwapEvent {
+        pool: pool.key(),
+        user,
+        amount_in,
+        amount_out,
+        direction: match direction {
+            SwapDirection::AToB => SWAP_DIRECTION_A_TO_B,
+            SwapDirection::BToA => SWAP_DIRECTION_B_TO_A,
+        },
+        fee,
+        new_reserve_a: pool.token_a_reserve,
+        new_reserve_b: pool.token_b_reserve,
+        k_before,
+        k_after,
+    });
+    emit!(ReserveUpdateEvent {
+        pool: pool.key(),
+        reserve_a_before,
+        reserve_a_after: pool.token_a_reserve,
+        reserve_b_before,
+        reserve_b_after: pool.token_b_reserve,
+        reason: ReserveUpdateReason::Swap,
+    });
+
+    Ok(())
+}
+
 pub fn execute_swap<'info>(
     pool: &mut Account<'info, Pool>,
-    token_program: &Program<'info, Token>,
-    user_token_in: AccountInfo<'info>,
-    user_token_out: AccountInfo<'info>,
-    reserve_in: AccountInfo<'info>,
-    reserve_out: AccountInfo<'info>,
-    user: &Signer<'info>,
+    accounts: PoolSwapAccounts<'_, 'info>,
     amount_in: u64,
     min_out: u64,
+    max_impact_bps: Option<u16>,
     direction: SwapDirection,
 ) -> Result<u64> {
-    let (reserve_in_amount, reserve_out_amount) = match direction {
-        SwapDirection::AToB => (pool.token_a_reserve, pool.token_b_reserve),
-        SwapDirection::BToA => (pool.token_b_reserve, pool.token_a_reserve),
+    require!(!pool.paused, ErrorCode::PoolPaused);
+    pool.validate_pda(&pool.key())?;
+
+    let PoolSwapAccounts {
+        token_program,
+        user,
+        user_token_a,
+        user_token_b,
+        token_a_reserve,
+        token_b_reserve,
+        token_a_mint,
+        token_b_mint,
+        hook_accounts,
+    } = accounts;
+    let (user_token_in, user_token_out, reserve_in, reserve_out, mint_in, mint_out) = match direction {
+        SwapDirection::AToB => (user_token_a, user_token_b, token_a_reserve, token_b_reserve, token_a_mint, token_b_mint),
+        SwapDirection::BToA => (user_token_b, user_token_a, token_b_reserve, token_a_reserve, token_b_mint, token_a_mint),
     };
 
-    let amount_out = get_amount_out(amount_in, reserve_in_amount, reserve_out_amount)?;
-    require!(amount_out >= min_out, ErrorCode::SlippageExceeded);
+    let (reserve_in_amount, reserve_out_amount, decimals_in, decimals_out) = match direction {
+        SwapDirection::AToB => (pool.token_a_reserve, pool.token_b_reserve, pool.token_a_decimals, pool.token_b_decimals),
+        SwapDirection::BToA => (pool.token_b_reserve, pool.token_a_reserve, pool.token_b_decimals, pool.token_a_decimals),
+    };
 
-    token::transfer(
+    // a fee-bearing mint delivers less than amount_in, so the trade is
+    // priced and credited on what the reserve actually received
+    let charges_fee = mint_charges_transfer_fee(&mint_in)?;
+    let balance_before = token_balance(&reserve_in)?;
+    token_interface::transfer_checked(
         CpiContext::new(
-            token_program.to_account_info(),
-            Transfer {
+            token_program.clone(),
+            TransferChecked {
                 from: user_token_in,
-                to: reserve_in,
-                authority: user.to_account_info(),
+                mint: mint_in,
+                to: reserve_in.clone(),
+                authority: user.clone(),
             },
         ),
         amount_in,
+        decimals_in,
     )?;
+    let amount_in = received_amount(balance_before, token_balance(&reserve_in)?, amount_in, charges_fee)?;
+
+    check_swap_size(amount_in, reserve_in_amount, pool.max_swap_bps)?;
+    let fee_bps = pool.swap_fee_bps(amount_in, reserve_in_amount);
+    let amount_out = get_amount_out(amount_in, reserve_in_amount, reserve_out_amount, fee_bps)?;
+    require!(amount_out >= min_out, ErrorCode::SlippageExceeded);
+    check_price_impact(amount_in, amount_out, reserve_in_amount, reserve_out_amount, max_impact_bps)?;
 
     let seeds = &[
         b"pool".as_ref(),
@@ -50,99 +244,810 @@ pub fn execute_swap<'info>(
     ];
     let signer_seeds = &[&seeds[..]];
 
-    token::transfer(
+    token_interface::transfer_checked(
         CpiContext::new_with_signer(
-            token_program.to_account_info(),
-            Transfer {
+            token_program,
+            TransferChecked {
                 from: reserve_out,
+                mint: mint_out,
                 to: user_token_out,
                 authority: pool.to_account_info(),
             },
             signer_seeds,
         ),
         amount_out,
+        decimals_out,
     )?;
 
-    let fee = calculate_fee(amount_in)?;
+    record_swap(pool, user.key(), amount_in, amount_out, fee_bps, direction)?;
+    call_swap_hook(pool, hook_accounts, amount_in, amount_out, direction)?;
 
-    match direction {
-        SwapDirection::AToB => {
-            pool.token_a_reserve = pool.token_a_reserve.checked_add(amount_in).ok_or(ErrorCode::MathOverflow)?;
-            pool.token_b_reserve = pool.token_b_reserve.checked_sub(amount_out).ok_or(ErrorCode::MathOverflow)?;
-            pool.total_fees_a = pool.total_fees_a.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
-        }
-        SwapDirection::BToA => {
-            pool.token_b_reserve = pool.token_b_reserve.checked_add(amount_in).ok_or(ErrorCode::MathOverflow)?;
-            pool.token_a_reserve = pool.token_a_reserve.checked_sub(amount_out).ok_or(ErrorCode::MathOverflow)?;
-            pool.total_fees_b = pool.total_fees_b.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
-        }
+    Ok(amount_out)
+}
+
+/// instruction data of the post-swap hook cpi:
+/// discriminator || amount_in (u64 le) || amount_out (u64 le) || direction (u8)
+pub fn swap_hook_data(amount_in: u64, amount_out: u64, direction: SwapDirection) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 + 8 + 8 + 1);
+    data.extend_from_slice(&SWAP_HOOK_DISCRIMINATOR);
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&amount_out.to_le_bytes());
+    data.push(match direction {
+        SwapDirection::AToB => SWAP_DIRECTION_A_TO_B,
+        SwapDirection::BToA => SWAP_DIRECTION_B_TO_A,
+    });
+    data
+}
+
+/// notifies the pool's hook program of a swap. `hook_accounts` are the
+/// instruction's remaining accounts, the hook program first and then
+/// whatever the hook needs. the pool is written back first and passed
+/// read-only, still locked, so the hook sees the post-swap reserves but
+/// can't re-enter. a failing cpi always aborts the transaction, so
+/// `hook_required` only decides whether a swap may skip a missing hook
+pub fn call_swap_hook<'info>(
+    pool: &mut Account<'info, Pool>,
+    hook_accounts: &[AccountInfo<'info>],
+    amount_in: u64,
+    amount_out: u64,
+    direction: SwapDirection,
+) -> Result<()> {
+    let Some(hook_program) = pool.hook_program else {
+        return Ok(());
+    };
+    let Some((program, accounts)) = hook_accounts.split_first().filter(|(program, _)| *program.key == hook_program) else {
+        require!(!pool.hook_required, ErrorCode::SwapHookMissing);
+        msg!("Swap hook {} not passed, skipping", hook_program);
+        return Ok(());
+    };
+    pool.exit(&crate::ID)?;
+
+    let pool_info = pool.to_account_info();
+    let mut metas = vec![AccountMeta::new_readonly(*pool_info.key, false)];
+    let mut infos = vec![program.clone(), pool_info];
+    for account in accounts {
+        metas.push(if account.is_writable {
+            AccountMeta::new(*account.key, false)
+        } else {
+            AccountMeta::new_readonly(*account.key, false)
+        });
+        infos.push(account.clone());
     }
+    let ix = Instruction {
+        program_id: hook_program,
+        accounts: metas,
+        data: swap_hook_data(amount_in, amount_out, direction),
+    };
+    invoke(&ix, &infos)?;
+    debug_msg!("Swap hook called: {}", hook_program);
+    Ok(())
+}
 
+pub fn derive_swap_accounts(token_a_mint: &Pubkey, token_b_mint: &Pubkey, user: &Pubkey) -> SwapAccounts {
+    let (pool, _) = Pubkey::find_program_address(
+        &[b"pool", token_a_mint.as_ref(), token_b_mint.as_ref()],
+        &crate::ID,
+    );
+    let (lp_mint, _) = Pubkey::find_program_address(&[b"lp_mint", pool.as_ref()], &crate::ID);
+    SwapAccounts {
+        pool,
+        lp_mint,
+        token_a_reserve: get_associated_token_address(&pool, token_a_mint),
+        token_b_reserve: get_associated_token_address(&pool, token_b_mint),
+        user_token_a: get_associated_token_address(user, token_a_mint),
+        user_token_b: get_associated_token_address(user, token_b_mint),
+        token_program: token::ID,
+    }
+}
+
+/// checks a two-leg route before any reserves are touched: the legs must be
+/// distinct pools (one account can't be borrowed mutably for both) and the
+/// first pool's output mint must be the second pool's input mint.
+/// returns the intermediate mint
+pub fn validate_route(first_key: &Pubkey, first: &Pool, second_key: &Pubkey, second: &Pool) -> Result<Pubkey> {
+    require_keys_neq!(*first_key, *second_key, ErrorCode::SamePoolInRoute);
+    require_keys_eq!(first.token_b_mint, second.token_a_mint, ErrorCode::InvalidRoute);
+    require_keys_neq!(first.token_a_mint, second.token_b_mint, ErrorCode::InvalidRoute);
+    Ok(first.token_b_mint)
+}
+
+/// a zero min_out leaves the trade open to sandwiching, so it has to be
+/// asked for explicitly rather than reached by a front-end default
+pub fn check_min_out(min_out: u64, accept_any_output: bool) -> Result<()> {
+    require!(min_out > 0 || accept_any_output, ErrorCode::SlippageProtectionRequired);
+    Ok(())
+}
+
+/// rejects a swap that sat unconfirmed past the caller's unix-time deadline,
+/// checked before the verifier cpi so a stale transaction fails cheaply
+pub fn check_deadline(deadline: i64) -> Result<()> {
+    require!(Clock::get()?.unix_timestamp <= deadline, ErrorCode::DeadlineExceeded);
+    Ok(())
+}
+
+/// the swap output alongside the pool's reserves on each side after it
+fn swap_result(pool: &Pool, amount_out: u64, direction: SwapDirection) -> SwapResult {
+    let (reserve_in, reserve_out) = match direction {
+        SwapDirection::AToB => (pool.token_a_reserve, pool.token_b_reserve),
+        SwapDirection::BToA => (pool.token_b_reserve, pool.token_a_reserve),
+    };
+    SwapResult { amount_out, reserve_in, reserve_out }
+}
+
+/// marks the pool busy for the rest of the swap and writes the flag to the
+/// account before the verifier cpi, so a verifier that re-enters a swap on
+/// this pool finds it locked. a failing swap reverts the write with
+/// everything else, so only the success path has to clear it
+pub fn lock_pool(pool: &mut Account<Pool>) -> Result<()> {
+    require!(!pool.locked, ErrorCode::PoolLocked);
+    pool.locked = true;
+    pool.exit(&crate::ID)
+}
+
+/// verifies a public swap proof. with the `test-mode` feature and the pool's
+/// test_mode flag set, passing the system program as verifier skips the cpi
+fn verify_swap_proof(pool: &Pool, proof: &SwapProof) -> Result<()> {
+    require!(!pool.paused, ErrorCode::PoolPaused);
+    if skips_verification(pool, &proof.verifier_program) {
+        msg!("test-mode: skipping proof verification");
+        return Ok(());
+    }
+    pool.check_verifier(proof.verifier_program.key)?;
+    verify_zk_proof(&proof.verifier_program, proof.verifier_state.as_ref(), proof.proof, proof.public_inputs)
+}
+
+/// true when the pool's test-mode bypass lets this swap skip the verifier
+fn skips_verification(pool: &Pool, verifier_program: &AccountInfo) -> bool {
+    pool.verifier_bypass_enabled() && *verifier_program.key == anchor_lang::solana_program::system_program::ID
+}
+
+/// the internal swap of a single-sided deposit. it moves the user's tokens
+/// like any swap, so it needs a proof and runs through execute_swap with the
+/// size cap, invariant check, volume and hook. the deposit's min_lp is its
+/// slippage bound
+pub fn swap_for_deposit<'info>(
+    pool: &mut Account<'info, Pool>,
+    accounts: PoolSwapAccounts<'_, 'info>,
+    proof: &SwapProof<'_, 'info>,
+    swap_amount: u64,
+    direction: SwapDirection,
+) -> Result<u64> {
+    lock_pool(pool)?;
+    verify_swap_proof(pool, proof)?;
+
+    let amount_out = execute_swap(pool, accounts, swap_amount, 0, None, direction)?;
+    pool.locked = false;
     Ok(amount_out)
 }
 
 pub fn zk_swap<'info>(
     pool: &mut Account<'info, Pool>,
-    token_program: &Program<'info, Token>,
-    user_token_a: AccountInfo<'info>,
-    user_token_b: AccountInfo<'info>,
-    token_a_reserve: AccountInfo<'info>,
-    token_b_reserve: AccountInfo<'info>,
-    user: &Signer<'info>,
-    verifier_program: &AccountInfo<'info>,
+    accounts: PoolSwapAccounts<'_, 'info>,
+    proof: &SwapProof<'_, 'info>,
+    amount_in: u64,
+    bounds: SwapBounds,
+) -> Result<SwapResult> {
+    check_min_out(bounds.min_out, bounds.accept_any_output)?;
+    check_deadline(bounds.deadline)?;
+    lock_pool(pool)?;
+    verify_swap_proof(pool, proof)?;
+
+    let amount_out = execute_swap(pool, accounts, amount_in, bounds.min_out, bounds.max_impact_bps, SwapDirection::AToB)?;
+    pool.locked = false;
+
+    debug_msg!("Swap: {} A -> {} B", amount_in, amount_out);
+    Ok(swap_result(pool, amount_out, SwapDirection::AToB))
+}
+
+pub fn zk_swap_reverse<'info>(
+    pool: &mut Account<'info, Pool>,
+    accounts: PoolSwapAccounts<'_, 'info>,
+    proof: &SwapProof<'_, 'info>,
     amount_in: u64,
     min_out: u64,
-    proof: &[u8],
-    public_inputs: &[u8],
+    accept_any_output: bool,
+    deadline: i64,
+) -> Result<SwapResult> {
+    check_min_out(min_out, accept_any_output)?;
+    check_deadline(deadline)?;
+    lock_pool(pool)?;
+    verify_swap_proof(pool, proof)?;
+
+    let amount_out = execute_swap(pool, accounts, amount_in, min_out, None, SwapDirection::BToA)?;
+    pool.locked = false;
+
+    debug_msg!("Swap: {} B -> {} A", amount_in, amount_out);
+    Ok(swap_result(pool, amount_out, SwapDirection::BToA))
+}
+
+/// direction-agnostic exact-in swap for aggregator routing, returns the
+/// amount out and the new reserves as transaction return data
+pub fn swap_exact_in<'info>(
+    pool: &mut Account<'info, Pool>,
+    accounts: PoolSwapAccounts<'_, 'info>,
+    proof: &SwapProof<'_, 'info>,
+    amount_in: u64,
+    min_out: u64,
+    accept_any_output: bool,
+    is_a_to_b: bool,
+) -> Result<SwapResult> {
+    check_min_out(min_out, accept_any_output)?;
+    lock_pool(pool)?;
+    verify_swap_proof(pool, proof)?;
+
+    let direction = SwapDirection::from_a_to_b(is_a_to_b);
+    let amount_out = execute_swap(pool, accounts, amount_in, min_out, None, direction)?;
+    pool.locked = false;
+
+    debug_msg!("Swap: {} in -> {} out", amount_in, amount_out);
+    Ok(swap_result(pool, amount_out, direction))
+}
+
+/// exact-output swap: solves for the input and rejects it above `max_in`.
+/// input is rounded up, so the user receives at least `amount_out`
+pub fn swap_exact_out<'info>(
+    pool: &mut Account<'info, Pool>,
+    accounts: PoolSwapAccounts<'_, 'info>,
+    proof: &SwapProof<'_, 'info>,
+    amount_out: u64,
+    max_in: u64,
+    is_a_to_b: bool,
 ) -> Result<()> {
-    verify_zk_proof(verifier_program, proof, public_inputs)?;
+    lock_pool(pool)?;
+    verify_swap_proof(pool, proof)?;
 
-    let amount_out = execute_swap(
-        pool,
-        token_program,
-        user_token_a,
-        user_token_b,
-        token_a_reserve,
-        token_b_reserve,
-        user,
-        amount_in,
-        min_out,
-        SwapDirection::AToB,
-    )?;
+    let direction = SwapDirection::from_a_to_b(is_a_to_b);
+    let (reserve_in_amount, reserve_out_amount) = match direction {
+        SwapDirection::AToB => (pool.token_a_reserve, pool.token_b_reserve),
+        SwapDirection::BToA => (pool.token_b_reserve, pool.token_a_reserve),
+    };
+    // the solved input is what the reserve has to receive, so a fee-bearing
+    // mint is grossed up by its transfer fee before checking against max_in
+    let received = get_amount_in_dynamic(amount_out, reserve_in_amount, reserve_out_amount, pool.fee_bps, pool.max_fee_bps)?;
+    let amount_in = amount_before_transfer_fee(accounts.mint_in(direction), received, Clock::get()?.epoch)?;
+    require!(amount_in <= max_in, ErrorCode::ExcessiveInputAmount);
+
+    let received = execute_swap(pool, accounts, amount_in, amount_out, None, direction)?;
+    pool.locked = false;
 
-    msg!("Swap: {} A -> {} B", amount_in, amount_out);
+    debug_msg!("Exact out swap: {} in -> {} out", amount_in, received);
     Ok(())
 }
 
-pub fn zk_swap_reverse<'info>(
+pub fn swap_with_limit<'info>(
+    pool: &mut Account<'info, Pool>,
+    accounts: PoolSwapAccounts<'_, 'info>,
+    proof: &SwapProof<'_, 'info>,
+    amount_in: u64,
+    limit_price: u128,
+    is_a_to_b: bool,
+) -> Result<()> {
+    lock_pool(pool)?;
+    verify_swap_proof(pool, proof)?;
+
+    let direction = SwapDirection::from_a_to_b(is_a_to_b);
+
+    // price the trade against live reserves so the limit is checked before any transfer
+    let (reserve_in_amount, reserve_out_amount) = match direction {
+        SwapDirection::AToB => (pool.token_a_reserve, pool.token_b_reserve),
+        SwapDirection::BToA => (pool.token_b_reserve, pool.token_a_reserve),
+    };
+    // the reserve only receives amount_in less the mint's transfer fee, so
+    // the quote is taken on that net amount while the price stays per unit
+    // the user actually sends
+    let received = amount_after_transfer_fee(accounts.mint_in(direction), amount_in, Clock::get()?.epoch)?;
+    let fee_bps = pool.swap_fee_bps(received, reserve_in_amount);
+    let quoted_out = get_amount_out(received, reserve_in_amount, reserve_out_amount, fee_bps)?;
+    require!(execution_price(amount_in, quoted_out)? >= limit_price, ErrorCode::LimitPriceNotMet);
+
+    let amount_out = execute_swap(pool, accounts, amount_in, quoted_out, None, direction)?;
+    pool.locked = false;
+
+    debug_msg!("Limit swap: {} in -> {} out", amount_in, amount_out);
+    Ok(())
+}
+
+/// swap with min_out derived on chain from the live quote, `slippage_bps`
+/// below what get_amount_out returns for the current reserves
+pub fn swap_with_slippage<'info>(
     pool: &mut Account<'info, Pool>,
-    token_program: &Program<'info, Token>,
-    user_token_a: AccountInfo<'info>,
-    user_token_b: AccountInfo<'info>,
-    token_a_reserve: AccountInfo<'info>,
-    token_b_reserve: AccountInfo<'info>,
-    user: &Signer<'info>,
-    verifier_program: &AccountInfo<'info>,
+    accounts: PoolSwapAccounts<'_, 'info>,
+    proof: &SwapProof<'_, 'info>,
+    amount_in: u64,
+    slippage_bps: u16,
+    is_a_to_b: bool,
+) -> Result<()> {
+    lock_pool(pool)?;
+    verify_swap_proof(pool, proof)?;
+
+    let direction = SwapDirection::from_a_to_b(is_a_to_b);
+    let (reserve_in_amount, reserve_out_amount) = match direction {
+        SwapDirection::AToB => (pool.token_a_reserve, pool.token_b_reserve),
+        SwapDirection::BToA => (pool.token_b_reserve, pool.token_a_reserve),
+    };
+    // execute_swap prices the amount the reserve receives, so the floor is
+    // quoted off the same net amount and not the gross amount_in
+    let received = amount_after_transfer_fee(accounts.mint_in(direction), amount_in, Clock::get()?.epoch)?;
+    let fee_bps = pool.swap_fee_bps(received, reserve_in_amount);
+    let expected_out = get_amount_out(received, reserve_in_amount, reserve_out_amount, fee_bps)?;
+    let min_out = slippage_floor(expected_out, slippage_bps)?;
+
+    let amount_out = execute_swap(pool, accounts, amount_in, min_out, None, direction)?;
+    pool.locked = false;
+
+    debug_msg!("Slippage swap: {} in -> {} out (min {})", amount_in, amount_out, min_out);
+    Ok(())
+}
+
+/// first half of a two-phase swap: verifies the proof now and records the
+/// swap terms it authorizes, settle_swap executes them later without re-verifying
+pub fn commit_proof<'info>(
+    ctx: Context<'_, '_, '_, 'info, CommitProof<'info>>,
+    proof: &SwapProof<'_, 'info>,
     amount_in: u64,
     min_out: u64,
-    proof: &[u8],
-    public_inputs: &[u8],
+    accept_any_output: bool,
+    is_a_to_b: bool,
 ) -> Result<()> {
-    verify_zk_proof(verifier_program, proof, public_inputs)?;
+    check_min_out(min_out, accept_any_output)?;
+    require!(amount_in > 0, ErrorCode::ZeroAmount);
+    verify_swap_proof(&ctx.accounts.pool, proof)?;
 
-    let amount_out = execute_swap(
-        pool,
-        token_program,
-        user_token_b,
-        user_token_a,
-        token_b_reserve,
-        token_a_reserve,
-        user,
+    let commitment = &mut ctx.accounts.commitment;
+    commitment.pool = ctx.accounts.pool.key();
+    commitment.user = ctx.accounts.user.key();
+    commitment.amount_in = amount_in;
+    commitment.min_out = min_out;
+    commitment.is_a_to_b = is_a_to_b;
+    commitment.committed_slot = Clock::get()?.slot;
+    commitment.bump = ctx.bumps.commitment;
+
+    debug_msg!("Proof committed at slot {}", commitment.committed_slot);
+    Ok(())
+}
+
+/// second half of a two-phase swap, the commitment account is closed on
+/// success so a proof can only ever settle once
+pub fn settle_swap<'info>(ctx: Context<'_, '_, '_, 'info, SettleSwap<'info>>) -> Result<()> {
+    let commitment = &ctx.accounts.commitment;
+    require!(!commitment.is_expired(Clock::get()?.slot), ErrorCode::ProofCommitmentExpired);
+
+    let accounts = PoolSwapAccounts {
+        token_program: ctx.accounts.token_program.to_account_info(),
+        user: ctx.accounts.user.to_account_info(),
+        user_token_a: ctx.accounts.user_token_a.to_account_info(),
+        user_token_b: ctx.accounts.user_token_b.to_account_info(),
+        token_a_reserve: ctx.accounts.token_a_reserve.to_account_info(),
+        token_b_reserve: ctx.accounts.token_b_reserve.to_account_info(),
+        token_a_mint: ctx.accounts.token_a_mint.to_account_info(),
+        token_b_mint: ctx.accounts.token_b_mint.to_account_info(),
+        hook_accounts: ctx.remaining_accounts,
+    };
+    let direction = SwapDirection::from_a_to_b(commitment.is_a_to_b);
+    let (amount_in, min_out) = (commitment.amount_in, commitment.min_out);
+    lock_pool(&mut ctx.accounts.pool)?;
+
+    let amount_out = execute_swap(&mut ctx.accounts.pool, accounts, amount_in, min_out, None, direction)?;
+    ctx.accounts.pool.locked = false;
+
+    debug_msg!("Settled swap: {} in -> {} out", amount_in, amount_out);
+    Ok(())
+}
+
+/// routes a -> b -> c through two pools in one instruction. the first hop's
+/// output goes straight from the first pool's reserve into the second's.
+/// each leg gets the size cap, price impact bound, output floor and hook a
+/// single swap gets, with `min_mid_out` as the first leg's floor. the first
+/// `first_hook_accounts` remaining accounts go to the first pool's hook,
+/// the rest to the second's
+pub fn swap_route_two<'info>(
+    ctx: Context<'_, '_, '_, 'info, SwapRouteTwo<'info>>,
+    proof: &SwapProof<'_, 'info>,
+    amount_in: u64,
+    bounds: RouteBounds,
+    first_hook_accounts: u8,
+) -> Result<u64> {
+    let RouteBounds { min_mid_out, min_out, accept_any_output, max_impact_bps } = bounds;
+    check_min_out(min_out, accept_any_output)?;
+    require!(amount_in > 0, ErrorCode::ZeroAmount);
+    require!(
+        first_hook_accounts as usize <= ctx.remaining_accounts.len(),
+        ErrorCode::SwapHookMissing
+    );
+    let (first_hook, second_hook) = ctx.remaining_accounts.split_at(first_hook_accounts as usize);
+    validate_route(
+        &ctx.accounts.first_pool.key(),
+        &ctx.accounts.first_pool,
+        &ctx.accounts.second_pool.key(),
+        &ctx.accounts.second_pool,
+    )?;
+    ctx.accounts.first_pool.validate_pda(&ctx.accounts.first_pool.key())?;
+    ctx.accounts.second_pool.validate_pda(&ctx.accounts.second_pool.key())?;
+    lock_pool(&mut ctx.accounts.first_pool)?;
+    lock_pool(&mut ctx.accounts.second_pool)?;
+    verify_swap_proof(&ctx.accounts.first_pool, proof)?;
+    // one proof covers both legs, so it has to come from the verifier the
+    // second pool expects as well
+    if !skips_verification(&ctx.accounts.second_pool, &ctx.accounts.verifier_program) {
+        ctx.accounts.second_pool.check_verifier(ctx.accounts.verifier_program.key)?;
+    }
+
+    let accounts = ctx.accounts;
+    let token_program = accounts.token_program.to_account_info();
+
+    // hop 1: user -> first pool, priced on what the reserve received
+    let first_reserve_in = accounts.first_reserve_in.to_account_info();
+    let charges_fee = mint_charges_transfer_fee(&accounts.mint_in.to_account_info())?;
+    let balance_before = token_balance(&first_reserve_in)?;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program.clone(),
+            TransferChecked {
+                from: accounts.user_token_in.to_account_info(),
+                mint: accounts.mint_in.to_account_info(),
+                to: first_reserve_in.clone(),
+                authority: accounts.user.to_account_info(),
+            },
+        ),
         amount_in,
-        min_out,
-        SwapDirection::BToA,
+        accounts.mint_in.decimals,
     )?;
+    let first_in = received_amount(balance_before, token_balance(&first_reserve_in)?, amount_in, charges_fee)?;
+    let first = &accounts.first_pool;
+    check_swap_size(first_in, first.token_a_reserve, first.max_swap_bps)?;
+    let first_fee_bps = first.swap_fee_bps(first_in, first.token_a_reserve);
+    let first_out = get_amount_out(first_in, first.token_a_reserve, first.token_b_reserve, first_fee_bps)?;
+    require!(first_out >= min_mid_out, ErrorCode::SlippageExceeded);
+    check_price_impact(first_in, first_out, first.token_a_reserve, first.token_b_reserve, max_impact_bps)?;
 
-    msg!("Swap: {} B -> {} A", amount_in, amount_out);
-    Ok(())
+    // hop 2: first pool reserve -> second pool reserve
+    let second_reserve_in = accounts.second_reserve_in.to_account_info();
+    let charges_fee = mint_charges_transfer_fee(&accounts.mint_mid.to_account_info())?;
+    let balance_before = token_balance(&second_reserve_in)?;
+    let first_seeds = &[
+        b"pool".as_ref(),
+        first.token_a_mint.as_ref(),
+        first.token_b_mint.as_ref(),
+        &[first.bump],
+    ];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            TransferChecked {
+                from: accounts.first_reserve_out.to_account_info(),
+                mint: accounts.mint_mid.to_account_info(),
+                to: second_reserve_in.clone(),
+                authority: first.to_account_info(),
+            },
+            &[&first_seeds[..]],
+        ),
+        first_out,
+        accounts.mint_mid.decimals,
+    )?;
+    let second_in = received_amount(balance_before, token_balance(&second_reserve_in)?, first_out, charges_fee)?;
+    let second = &accounts.second_pool;
+    check_swap_size(second_in, second.token_a_reserve, second.max_swap_bps)?;
+    let second_fee_bps = second.swap_fee_bps(second_in, second.token_a_reserve);
+    let amount_out = get_amount_out(second_in, second.token_a_reserve, second.token_b_reserve, second_fee_bps)?;
+    require!(amount_out >= min_out, ErrorCode::SlippageExceeded);
+    check_price_impact(second_in, amount_out, second.token_a_reserve, second.token_b_reserve, max_impact_bps)?;
+
+    let second_seeds = &[
+        b"pool".as_ref(),
+        second.token_a_mint.as_ref(),
+        second.token_b_mint.as_ref(),
+        &[second.bump],
+    ];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program,
+            TransferChecked {
+                from: accounts.second_reserve_out.to_account_info(),
+                mint: accounts.mint_out.to_account_info(),
+                to: accounts.user_token_out.to_account_info(),
+                authority: second.to_account_info(),
+            },
+            &[&second_seeds[..]],
+        ),
+        amount_out,
+        accounts.mint_out.decimals,
+    )?;
+
+    let user = accounts.user.key();
+    record_swap(&mut accounts.first_pool, user, first_in, first_out, first_fee_bps, SwapDirection::AToB)?;
+    record_swap(&mut accounts.second_pool, user, second_in, amount_out, second_fee_bps, SwapDirection::AToB)?;
+    call_swap_hook(&mut accounts.first_pool, first_hook, first_in, first_out, SwapDirection::AToB)?;
+    call_swap_hook(&mut accounts.second_pool, second_hook, second_in, amount_out, SwapDirection::AToB)?;
+    accounts.first_pool.locked = false;
+    accounts.second_pool.locked = false;
+
+    debug_msg!("Route swap: {} in -> {} mid -> {} out", amount_in, first_out, amount_out);
+    Ok(amount_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(token_a_mint: Pubkey, token_b_mint: Pubkey) -> Pool {
+        Pool {
+            token_a_mint,
+            token_b_mint,
+            token_a_reserve: 0,
+            token_b_reserve: 0,
+            k: 0,
+            bump: 0,
+            authority: Pubkey::default(),
+            total_fees_a: 0,
+            total_fees_b: 0,
+            created_at: 0,
+            last_swap_at: 0,
+            verifier_requires_state: false,
+            test_mode: false,
+            lp_mint: Pubkey::default(),
+            token_a_decimals: 0,
+            token_b_decimals: 0,
+            fee_bps: 0,
+            paused: false,
+            min_output: 0,
+            price_cumulative_a: 0,
+            price_cumulative_b: 0,
+            last_update_ts: 0,
+            lifetime_fees_a: 0,
+            lifetime_fees_b: 0,
+            max_fee_bps: 0,
+            locked: false,
+            expected_verifier: Pubkey::default(),
+            max_swap_bps: 0,
+            volume_a: 0,
+            volume_b: 0,
+            hook_program: None,
+            hook_required: false,
+            pending_authority: Pubkey::default(),
+        }
+    }
+
+    #[test]
+    fn test_validate_route() {
+        let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (first_key, second_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let mid = validate_route(&first_key, &pool(a, b), &second_key, &pool(b, c)).unwrap();
+        assert_eq!(mid, b);
+        // same account for both legs
+        assert!(validate_route(&first_key, &pool(a, b), &first_key, &pool(b, c)).is_err());
+        // middle mint mismatch
+        assert!(validate_route(&first_key, &pool(a, b), &second_key, &pool(c, a)).is_err());
+        // round trip back to the input mint
+        assert!(validate_route(&first_key, &pool(a, b), &second_key, &pool(b, a)).is_err());
+    }
+
+    #[test]
+    fn test_derive_swap_accounts() {
+        let (a, b, user) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = derive_swap_accounts(&a, &b, &user);
+        let (pool_pda, _) = Pubkey::find_program_address(&[b"pool", a.as_ref(), b.as_ref()], &crate::ID);
+        assert_eq!(accounts.pool, pool_pda);
+        assert_eq!(accounts.token_a_reserve, get_associated_token_address(&pool_pda, &a));
+        assert_eq!(accounts.user_token_b, get_associated_token_address(&user, &b));
+        assert_ne!(accounts.token_a_reserve, accounts.token_b_reserve);
+    }
+
+    #[test]
+    fn test_validate_mint_order() {
+        let low = Pubkey::new_from_array([1; 32]);
+        let high = Pubkey::new_from_array([2; 32]);
+        assert!(Pool::validate_mint_order(&low, &high).is_ok());
+        assert!(Pool::validate_mint_order(&low, &low).is_err());
+        assert!(Pool::validate_mint_order(&high, &low).is_err());
+    }
+
+    #[test]
+    fn test_swap_result_orders_reserves_by_direction() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        p.token_a_reserve = 1_000;
+        p.token_b_reserve = 2_000;
+
+        let a_to_b = swap_result(&p, 10, SwapDirection::AToB);
+        assert_eq!(a_to_b, SwapResult { amount_out: 10, reserve_in: 1_000, reserve_out: 2_000 });
+        let b_to_a = swap_result(&p, 10, SwapDirection::BToA);
+        assert_eq!(b_to_a, SwapResult { amount_out: 10, reserve_in: 2_000, reserve_out: 1_000 });
+    }
+
+    #[test]
+    fn test_min_output_floor() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(p.check_min_output(0).is_ok());
+        p.min_output = 1_000;
+        assert!(p.check_min_output(999).is_err());
+        assert!(p.check_min_output(1_000).is_ok());
+    }
+
+    #[test]
+    fn test_accumulate_price() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        p.accumulate_price(100);
+        assert_eq!((p.price_cumulative_a, p.price_cumulative_b, p.last_update_ts), (0, 0, 100));
+
+        p.token_a_reserve = 1_000;
+        p.token_b_reserve = 4_000;
+        p.accumulate_price(110);
+        assert_eq!(p.price_cumulative_a, 4 * crate::math::PRICE_PRECISION * 10);
+        assert_eq!(p.price_cumulative_b, crate::math::PRICE_PRECISION / 4 * 10);
+
+        // a second swap in the same second adds nothing
+        p.accumulate_price(110);
+        assert_eq!(p.price_cumulative_a, 4 * crate::math::PRICE_PRECISION * 10);
+
+        p.price_cumulative_a = u128::MAX;
+        p.accumulate_price(111);
+        assert_eq!(p.price_cumulative_a, 4 * crate::math::PRICE_PRECISION - 1);
+    }
+
+    #[test]
+    fn test_claimable_fees_capped_by_reserves() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        p.token_a_reserve = 1_000;
+        p.token_b_reserve = 50;
+        p.total_fees_a = 30;
+        p.total_fees_b = 80;
+        assert_eq!(p.claimable_fees(), (30, 50));
+    }
+
+    #[test]
+    fn test_zero_min_out_needs_explicit_flag() {
+        assert!(check_min_out(0, false).is_err());
+        assert!(check_min_out(0, true).is_ok());
+        assert!(check_min_out(1, false).is_ok());
+    }
+
+    #[test]
+    fn test_closed_reserve_is_missing() {
+        let key = Pubkey::new_unique();
+        let system = anchor_lang::solana_program::system_program::ID;
+        let token = spl_token_2022::ID;
+
+        let (mut lamports, mut data) = (0, Vec::new());
+        let closed = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &system, false, 0);
+        assert!(ensure_reserve_open(&closed).is_err());
+
+        let (mut lamports, mut data) = (1_000, vec![0u8; 165]);
+        let open = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token, false, 0);
+        assert!(ensure_reserve_open(&open).is_ok());
+
+        let (mut lamports, mut data) = (1_000, Vec::new());
+        let drained = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token, false, 0);
+        assert!(ensure_reserve_open(&drained).is_err());
+    }
+
+    #[test]
+    fn test_pool_len_matches_serialized_size() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        // options are sized for their some case
+        p.hook_program = Some(Pubkey::new_unique());
+        assert_eq!(Pool::LEN, 8 + p.try_to_vec().unwrap().len());
+    }
+
+    #[test]
+    fn test_locked_pool_rejects_reentry() {
+        let key = Pubkey::new_unique();
+        let owner = crate::ID;
+        let mut lamports = 1_000_000;
+        let mut data = Vec::new();
+        pool(Pubkey::new_unique(), Pubkey::new_unique()).try_serialize(&mut data).unwrap();
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+
+        let mut outer: Account<Pool> = Account::try_from(&info).unwrap();
+        lock_pool(&mut outer).unwrap();
+        // a re-entrant swap deserializes the pool from the written account data
+        let mut inner: Account<Pool> = Account::try_from(&info).unwrap();
+        assert!(inner.locked);
+        assert!(lock_pool(&mut inner).is_err());
+    }
+
+    #[test]
+    fn test_check_verifier() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        let verifier = Pubkey::new_unique();
+        p.expected_verifier = verifier;
+        assert!(p.check_verifier(&verifier).is_ok());
+        assert!(p.check_verifier(&Pubkey::new_unique()).is_err());
+        assert!(p.check_verifier(&anchor_lang::solana_program::system_program::ID).is_err());
+    }
+
+    #[test]
+    fn test_record_volume() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        p.record_volume(true, 1_000).unwrap();
+        p.record_volume(true, 500).unwrap();
+        p.record_volume(false, 2_000).unwrap();
+        assert_eq!((p.volume_a, p.volume_b), (1_500, 2_000));
+
+        p.volume_b = u128::MAX;
+        assert!(p.record_volume(false, 1).is_err());
+        assert_eq!(p.volume_b, u128::MAX);
+    }
+
+    #[test]
+    fn test_swap_hook_data_layout() {
+        let data = swap_hook_data(1_000, 997, SwapDirection::BToA);
+        assert_eq!(data.len(), 25);
+        assert_eq!(data[..8], SWAP_HOOK_DISCRIMINATOR);
+        assert_eq!(u64::from_le_bytes(data[8..16].try_into().unwrap()), 1_000);
+        assert_eq!(u64::from_le_bytes(data[16..24].try_into().unwrap()), 997);
+        assert_eq!(data[24], SWAP_DIRECTION_B_TO_A);
+        // anchor's discriminator for `on_swap`, so a hook can be an anchor program
+        let hash = solana_program::hash::hash(b"global:on_swap");
+        assert_eq!(SWAP_HOOK_DISCRIMINATOR, hash.to_bytes()[..8]);
+    }
+
+    fn system_program_verifier_swap(p: &Pool) -> Result<()> {
+        let system = anchor_lang::solana_program::system_program::ID;
+        let (mut lamports, mut data) = (1, Vec::new());
+        let verifier = AccountInfo::new(&system, false, false, &mut lamports, &mut data, &system, true, 0);
+        let proof = SwapProof { verifier_program: verifier, verifier_state: None, proof: &[1; 256], public_inputs: &[1; 32] };
+        verify_swap_proof(p, &proof)
+    }
+
+    #[cfg(feature = "test-mode")]
+    #[test]
+    fn test_bypass_needs_pool_flag_in_test_mode_builds() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        p.expected_verifier = Pubkey::new_unique();
+        assert!(system_program_verifier_swap(&p).is_err());
+        p.test_mode = true;
+        assert!(system_program_verifier_swap(&p).is_ok());
+    }
+
+    #[cfg(not(feature = "test-mode"))]
+    #[test]
+    fn test_system_program_verifier_rejected_without_test_mode() {
+        let mut p = pool(Pubkey::new_unique(), Pubkey::new_unique());
+        p.test_mode = true;
+        // even a pool that expects the system program can't skip the proof
+        p.expected_verifier = anchor_lang::solana_program::system_program::ID;
+        assert!(!p.verifier_bypass_enabled());
+        assert_eq!(system_program_verifier_swap(&p).unwrap_err(), ErrorCode::InvalidVerifier.into());
+    }
+
+
+    #[test]
+    fn test_amount_after_transfer_fee() {
+        use spl_token_2022::extension::transfer_fee::TransferFee;
+        use spl_token_2022::extension::{BaseStateWithExtensionsMut, StateWithExtensionsMut};
+
+        let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[ExtensionType::TransferFeeConfig])
+            .unwrap();
+        let mut data = vec![0u8; len];
+        let mut state = StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data).unwrap();
+        let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        // 1% from epoch 0, capped at 5_000
+        let fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: 5_000.into(),
+            transfer_fee_basis_points: 100.into(),
+        };
+        config.older_transfer_fee = fee;
+        config.newer_transfer_fee = fee;
+        state.base.is_initialized = true;
+        state.pack_base();
+        state.init_account_type().unwrap();
+
+        let key = Pubkey::new_unique();
+        let owner = spl_token_2022::ID;
+        let mut lamports = 1;
+        let mint = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        assert_eq!(amount_after_transfer_fee(&mint, 100_000, 3).unwrap(), 99_000);
+        assert_eq!(amount_after_transfer_fee(&mint, 1_000_000, 3).unwrap(), 995_000);
+        // and grossing the net amounts back up recovers what was sent
+        assert_eq!(amount_before_transfer_fee(&mint, 99_000, 3).unwrap(), 100_000);
+        assert_eq!(amount_before_transfer_fee(&mint, 995_000, 3).unwrap(), 1_000_000);
+
+        // a plain spl mint delivers the full amount
+        let owner = token::ID;
+        let (mut lamports, mut data) = (1, vec![0u8; 82]);
+        let plain = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        assert_eq!(amount_after_transfer_fee(&plain, 100_000, 3).unwrap(), 100_000);
+        assert_eq!(amount_before_transfer_fee(&plain, 100_000, 3).unwrap(), 100_000);
+    }
 }
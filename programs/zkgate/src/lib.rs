@@ -1,15 +1,30 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Transfer};
+use anchor_spl::token_interface::{self, Burn, Mint, MintTo, TransferChecked};
+
+/// debug log that only runs with the `verbose-logs` feature. the arguments
+/// still type-check in every build, so nothing goes unused when it's off
+macro_rules! debug_msg {
+    ($($arg:tt)*) => {
+        if cfg!(feature = "verbose-logs") {
+            anchor_lang::prelude::msg!($($arg)*);
+        }
+    };
+}
 
 pub mod errors;
 pub mod state;
 pub mod math;
+pub mod crypto;
 pub mod contexts;
 pub mod instructions;
 
 use errors::ErrorCode;
-use state::PoolInfo;
-use state::roots::StateRootHistory;
+use state::{
+    BestPoolQuote, FeeSnapshot, LiquidityQuote, LpPosition, Pool, PoolActivity, PoolInfo, QuoteDetail, RouteBounds, SwapAccounts, SwapBounds, SwapResult,
+    ReserveUpdateEvent, ReserveUpdateReason,
+};
+use state::roots::{StateRootHistory, DEFAULT_ROOT_HISTORY_LEN};
+use state::shielded::{CommitmentOpening, MerkleProof, ShieldedPoolInfo, ShieldedSpend, SwapPrivateArgs};
 use contexts::*;
 use instructions::swap;
 use instructions::shielded_pool::*;
@@ -20,135 +35,626 @@ declare_id!("3TKv2Y8SaxJd2wmmtBS58GjET4mLz5esMZjnGfrstG72");
 pub mod zkgate {
     use super::*;
 
-    pub fn create_pool(ctx: Context<CreatePool>, init_a: u64, init_b: u64) -> Result<()> {
+    pub fn create_pool(
+        ctx: Context<CreatePool>,
+        init_a: u64,
+        init_b: u64,
+        reject_freezable_mints: bool,
+        fee_bps: u16,
+        expected_verifier: Pubkey,
+        max_swap_bps: u16,
+    ) -> Result<()> {
         msg!("Instruction: CreatePool");
-        let pool = &mut ctx.accounts.pool;
-        pool.token_a_mint = ctx.accounts.token_a_mint.key();
-        pool.token_b_mint = ctx.accounts.token_b_mint.key();
-        pool.token_a_reserve = init_a;
-        pool.token_b_reserve = init_b;
-        pool.k = (init_a as u128).checked_mul(init_b as u128).ok_or(ErrorCode::MathOverflow)?;
-        pool.bump = ctx.bumps.pool;
-        pool.authority = ctx.accounts.user.key();
-        pool.total_fees_a = 0;
-        pool.total_fees_b = 0;
+        init_pool(
+            &mut ctx.accounts.pool,
+            (&ctx.accounts.token_a_mint, &ctx.accounts.token_b_mint),
+            ctx.accounts.lp_mint.key(),
+            ctx.accounts.user.key(),
+            ctx.bumps.pool,
+            (init_a, init_b),
+            PoolConfig { reject_freezable_mints, fee_bps, expected_verifier, max_swap_bps },
+        )?;
         msg!("Pool created: A={}, B={}", init_a, init_b);
         Ok(())
     }
 
+    /// create_pool plus both reserve atas in one transaction. the reserves
+    /// start empty and the first add_liquidity seeds the price
+    pub fn create_pool_with_reserves(
+        ctx: Context<CreatePoolWithReserves>,
+        reject_freezable_mints: bool,
+        fee_bps: u16,
+        expected_verifier: Pubkey,
+        max_swap_bps: u16,
+    ) -> Result<()> {
+        msg!("Instruction: CreatePoolWithReserves");
+        init_pool(
+            &mut ctx.accounts.pool,
+            (&ctx.accounts.token_a_mint, &ctx.accounts.token_b_mint),
+            ctx.accounts.lp_mint.key(),
+            ctx.accounts.user.key(),
+            ctx.bumps.pool,
+            (0, 0),
+            PoolConfig { reject_freezable_mints, fee_bps, expected_verifier, max_swap_bps },
+        )?;
+        msg!(
+            "Pool created with reserves: A={}, B={}",
+            ctx.accounts.token_a_reserve.key(),
+            ctx.accounts.token_b_reserve.key()
+        );
+        Ok(())
+    }
+
     pub fn add_liquidity(ctx: Context<AddLiquidity>, amount_a: u64, amount_b: u64) -> Result<()> {
         msg!("Instruction: AddLiquidity");
-        token::transfer(
+        // shares are minted from the smaller side, so a one-sided deposit
+        // would mint nothing. single-sided deposits go through add_liquidity_single
+        require!(amount_a > 0 && amount_b > 0, ErrorCode::ZeroAmount);
+        // credit what the reserves actually received rather than the nominal amounts
+        let balance_a_before = ctx.accounts.token_a_reserve.amount;
+        let balance_b_before = ctx.accounts.token_b_reserve.amount;
+        token_interface::transfer_checked(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                TransferChecked {
                     from: ctx.accounts.user_token_a.to_account_info(),
+                    mint: ctx.accounts.token_a_mint.to_account_info(),
                     to: ctx.accounts.token_a_reserve.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
                 },
             ),
             amount_a,
+            ctx.accounts.token_a_mint.decimals,
         )?;
 
-        token::transfer(
+        token_interface::transfer_checked(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                TransferChecked {
                     from: ctx.accounts.user_token_b.to_account_info(),
+                    mint: ctx.accounts.token_b_mint.to_account_info(),
                     to: ctx.accounts.token_b_reserve.to_account_info(),
                     authority: ctx.accounts.user.to_account_info(),
                 },
             ),
             amount_b,
+            ctx.accounts.token_b_mint.decimals,
+        )?;
+
+        ctx.accounts.token_a_reserve.reload()?;
+        ctx.accounts.token_b_reserve.reload()?;
+        // only token-2022 mints with a transfer fee may deliver short
+        let received_a = math::received_amount(
+            balance_a_before,
+            ctx.accounts.token_a_reserve.amount,
+            amount_a,
+            swap::mint_charges_transfer_fee(&ctx.accounts.token_a_mint.to_account_info())?,
+        )?;
+        let received_b = math::received_amount(
+            balance_b_before,
+            ctx.accounts.token_b_reserve.amount,
+            amount_b,
+            swap::mint_charges_transfer_fee(&ctx.accounts.token_b_mint.to_account_info())?,
         )?;
 
         let pool = &mut ctx.accounts.pool;
-        pool.token_a_reserve = pool.token_a_reserve.checked_add(amount_a).ok_or(ErrorCode::MathOverflow)?;
-        pool.token_b_reserve = pool.token_b_reserve.checked_add(amount_b).ok_or(ErrorCode::MathOverflow)?;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        // a drained pool has no price left to follow, so the next deposit
+        // re-seeds it like the first one and re-locks MINIMUM_LIQUIDITY
+        let reseed = math::is_seeding(pool.token_a_reserve, pool.token_b_reserve, lp_supply);
+        let (shares, locked) =
+            math::deposit_shares(received_a, received_b, pool.token_a_reserve, pool.token_b_reserve, lp_supply)?;
+        require!(shares > 0, ErrorCode::ZeroLiquidityMinted);
+
+        let seeds = &[
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &[pool.bump],
+        ];
+        if locked > 0 {
+            token_interface::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    MintTo {
+                        mint: ctx.accounts.lp_mint.to_account_info(),
+                        to: ctx.accounts.lp_lock.to_account_info(),
+                        authority: pool.to_account_info(),
+                    },
+                    &[&seeds[..]],
+                ),
+                locked,
+            )?;
+        }
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.user_lp.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                &[&seeds[..]],
+            ),
+            shares,
+        )?;
+
+        let reserve_a_before = pool.token_a_reserve;
+        let reserve_b_before = pool.token_b_reserve;
+        pool.token_a_reserve = pool.token_a_reserve.checked_add(received_a).ok_or(ErrorCode::MathOverflow)?;
+        pool.token_b_reserve = pool.token_b_reserve.checked_add(received_b).ok_or(ErrorCode::MathOverflow)?;
         pool.k = (pool.token_a_reserve as u128).checked_mul(pool.token_b_reserve as u128).ok_or(ErrorCode::MathOverflow)?;
-        msg!("Liquidity added: A={}, B={}", amount_a, amount_b);
+        emit!(ReserveUpdateEvent {
+            pool: pool.key(),
+            reserve_a_before,
+            reserve_a_after: pool.token_a_reserve,
+            reserve_b_before,
+            reserve_b_after: pool.token_b_reserve,
+            reason: ReserveUpdateReason::AddLiquidity,
+        });
+        if reseed {
+            debug_msg!("Pool reseeded: A={}, B={}", pool.token_a_reserve, pool.token_b_reserve);
+        }
+        debug_msg!("Liquidity added: A={}, B={}, shares={}", received_a, received_b, shares);
         Ok(())
     }
 
-    pub fn zk_swap(
-        ctx: Context<ZKSwap>,
+    /// deposits one token only. the fee-aware split from
+    /// math::single_sided_swap_amount is swapped as a proof-gated swap into
+    /// user_token_out, then the rest goes in with the swap output as a
+    /// balanced add against the post-swap reserves
+    pub fn add_liquidity_single<'info>(
+        ctx: Context<'_, '_, '_, 'info, AddLiquiditySingle<'info>>,
         amount_in: u64,
-        min_out: u64,
+        is_token_a: bool,
+        min_lp: u64,
         proof: Vec<u8>,
         public_inputs: Vec<u8>,
     ) -> Result<()> {
+        msg!("Instruction: AddLiquiditySingle");
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.pool,
+            &ctx.accounts.verifier_program,
+            &ctx.accounts.verifier_state,
+            &proof,
+            &public_inputs,
+        );
+
+        let (reserve_in, direction) = if is_token_a {
+            (ctx.accounts.pool.token_a_reserve, swap::SwapDirection::AToB)
+        } else {
+            (ctx.accounts.pool.token_b_reserve, swap::SwapDirection::BToA)
+        };
+        // the split is solved at the base fee, the swap then pays whatever
+        // fee its size calls for
+        let swap_amount = math::single_sided_swap_amount(amount_in, reserve_in, ctx.accounts.pool.fee_bps)?;
+        let user_out_before = ctx.accounts.user_token_out.amount;
+        let (token_in, token_out) = (
+            (ctx.accounts.user_token_in.to_account_info(), ctx.accounts.reserve_in.to_account_info(), ctx.accounts.mint_in.to_account_info()),
+            (ctx.accounts.user_token_out.to_account_info(), ctx.accounts.reserve_out.to_account_info(), ctx.accounts.mint_out.to_account_info()),
+        );
+        let ((user_token_a, token_a_reserve, token_a_mint), (user_token_b, token_b_reserve, token_b_mint)) =
+            if is_token_a { (token_in, token_out) } else { (token_out, token_in) };
+        let accounts = swap::PoolSwapAccounts {
+            token_program: ctx.accounts.token_program.to_account_info(),
+            user: ctx.accounts.user.to_account_info(),
+            user_token_a,
+            user_token_b,
+            token_a_reserve,
+            token_b_reserve,
+            token_a_mint,
+            token_b_mint,
+            hook_accounts: ctx.remaining_accounts,
+        };
+        swap::swap_for_deposit(&mut ctx.accounts.pool, accounts, &proof, swap_amount, direction)?;
+        // a fee-bearing mint_out delivers less than the swap paid out, only
+        // what arrived can go back in
+        ctx.accounts.user_token_out.reload()?;
+        let swap_out = ctx
+            .accounts
+            .user_token_out
+            .amount
+            .checked_sub(user_out_before)
+            .ok_or(ErrorCode::TransferAmountMismatch)?;
+        let rest = amount_in - swap_amount;
+
+        // the swap moved both reserves, measure the deposit from where it left them
+        ctx.accounts.reserve_in.reload()?;
+        ctx.accounts.reserve_out.reload()?;
+        let reserve_in_before = ctx.accounts.reserve_in.amount;
+        let reserve_out_before = ctx.accounts.reserve_out.amount;
+        for (from, mint, to, amount) in [
+            (&ctx.accounts.user_token_in, &ctx.accounts.mint_in, &ctx.accounts.reserve_in, rest),
+            (&ctx.accounts.user_token_out, &ctx.accounts.mint_out, &ctx.accounts.reserve_out, swap_out),
+        ] {
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: from.to_account_info(),
+                        mint: mint.to_account_info(),
+                        to: to.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                amount,
+                mint.decimals,
+            )?;
+        }
+        ctx.accounts.reserve_in.reload()?;
+        ctx.accounts.reserve_out.reload()?;
+        let received_in = math::received_amount(
+            reserve_in_before,
+            ctx.accounts.reserve_in.amount,
+            rest,
+            swap::mint_charges_transfer_fee(&ctx.accounts.mint_in.to_account_info())?,
+        )?;
+        let received_out = math::received_amount(
+            reserve_out_before,
+            ctx.accounts.reserve_out.amount,
+            swap_out,
+            swap::mint_charges_transfer_fee(&ctx.accounts.mint_out.to_account_info())?,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        let (reserve_in, reserve_out) = if is_token_a {
+            (pool.token_a_reserve, pool.token_b_reserve)
+        } else {
+            (pool.token_b_reserve, pool.token_a_reserve)
+        };
+        let shares = math::shares_to_mint(received_in, received_out, reserve_in, reserve_out, ctx.accounts.lp_mint.supply)?;
+        require!(shares > 0, ErrorCode::ZeroLiquidityMinted);
+        require!(shares >= min_lp, ErrorCode::SlippageExceeded);
+
+        let seeds = &[
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &[pool.bump],
+        ];
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.user_lp.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                &[&seeds[..]],
+            ),
+            shares,
+        )?;
+
+        let (received_a, received_b) = if is_token_a { (received_in, received_out) } else { (received_out, received_in) };
+        let reserve_a_before = pool.token_a_reserve;
+        let reserve_b_before = pool.token_b_reserve;
+        pool.token_a_reserve = pool.token_a_reserve.checked_add(received_a).ok_or(ErrorCode::MathOverflow)?;
+        pool.token_b_reserve = pool.token_b_reserve.checked_add(received_b).ok_or(ErrorCode::MathOverflow)?;
+        pool.k = (pool.token_a_reserve as u128).checked_mul(pool.token_b_reserve as u128).ok_or(ErrorCode::MathOverflow)?;
+        emit!(ReserveUpdateEvent {
+            pool: pool.key(),
+            reserve_a_before,
+            reserve_a_after: pool.token_a_reserve,
+            reserve_b_before,
+            reserve_b_after: pool.token_b_reserve,
+            reason: ReserveUpdateReason::AddLiquidity,
+        });
+        debug_msg!("Single-sided liquidity: in={}, swapped={}, out={}, shares={}", amount_in, swap_amount, swap_out, shares);
+        Ok(())
+    }
+
+    pub fn remove_liquidity(ctx: Context<RemoveLiquidity>, lp_amount: u64, min_a: u64, min_b: u64) -> Result<()> {
+        msg!("Instruction: RemoveLiquidity");
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        let (amount_a, amount_b) = math::shares_to_withdraw(
+            lp_amount,
+            ctx.accounts.pool.token_a_reserve,
+            ctx.accounts.pool.token_b_reserve,
+            lp_supply,
+        )?;
+        require!(amount_a >= min_a && amount_b >= min_b, ErrorCode::SlippageExceeded);
+
+        token_interface::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.validate_pda(&pool.key())?;
+        let seeds = &[
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &[pool.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.token_a_reserve.to_account_info(),
+                    mint: ctx.accounts.token_a_mint.to_account_info(),
+                    to: ctx.accounts.user_token_a.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount_a,
+            pool.token_a_decimals,
+        )?;
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.token_b_reserve.to_account_info(),
+                    mint: ctx.accounts.token_b_mint.to_account_info(),
+                    to: ctx.accounts.user_token_b.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount_b,
+            pool.token_b_decimals,
+        )?;
+
+        let reserve_a_before = pool.token_a_reserve;
+        let reserve_b_before = pool.token_b_reserve;
+        pool.token_a_reserve = pool.token_a_reserve.checked_sub(amount_a).ok_or(ErrorCode::MathOverflow)?;
+        pool.token_b_reserve = pool.token_b_reserve.checked_sub(amount_b).ok_or(ErrorCode::MathOverflow)?;
+        pool.k = (pool.token_a_reserve as u128).checked_mul(pool.token_b_reserve as u128).ok_or(ErrorCode::MathOverflow)?;
+        math::check_liquidity_invariant(pool.token_a_reserve, pool.token_b_reserve, lp_supply - lp_amount)?;
+        emit!(ReserveUpdateEvent {
+            pool: pool.key(),
+            reserve_a_before,
+            reserve_a_after: pool.token_a_reserve,
+            reserve_b_before,
+            reserve_b_after: pool.token_b_reserve,
+            reason: ReserveUpdateReason::RemoveLiquidity,
+        });
+        debug_msg!("Liquidity removed: A={}, B={}, shares={}", amount_a, amount_b, lp_amount);
+        Ok(())
+    }
+
+    pub fn zk_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, ZKSwap<'info>>,
+        amount_in: u64,
+        bounds: SwapBounds,
+        proof: Vec<u8>,
+        public_inputs: Vec<u8>,
+    ) -> Result<SwapResult> {
         msg!("Instruction: ZkSwap");
         
         // 1. Verify that the State Root used in the proof is valid
-        let claimed_root: [u8; 32] = public_inputs[0..32].try_into().map_err(|_| ErrorCode::InvalidProof)?;
-        require!(ctx.accounts.history.contains(&claimed_root), ErrorCode::InvalidStateRoot);
-
-        swap::zk_swap(
-            &mut ctx.accounts.pool,
-            &ctx.accounts.token_program,
-            ctx.accounts.user_token_a.to_account_info(),
-            ctx.accounts.user_token_b.to_account_info(),
-            ctx.accounts.token_a_reserve.to_account_info(),
-            ctx.accounts.token_b_reserve.to_account_info(),
-            &ctx.accounts.user,
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.pool,
             &ctx.accounts.verifier_program,
-            amount_in,
-            min_out,
+            &ctx.accounts.verifier_state,
             &proof,
             &public_inputs,
-        )
+        );
+
+        let output_account = match &ctx.accounts.recipient_token_b {
+            Some(recipient) => recipient.to_account_info(),
+            None => ctx.accounts.user_token_b.to_account_info(),
+        };
+
+        let accounts = zk_swap_accounts(ctx.accounts, output_account, ctx.remaining_accounts);
+        swap::zk_swap(&mut ctx.accounts.pool, accounts, &proof, amount_in, bounds)
     }
 
-    pub fn zk_swap_reverse(
-        ctx: Context<ZKSwapReverse>,
+    pub fn zk_swap_reverse<'info>(
+        ctx: Context<'_, '_, '_, 'info, ZKSwapReverse<'info>>,
         amount_in: u64,
         min_out: u64,
+        accept_any_output: bool,
+        deadline: i64,
         proof: Vec<u8>,
         public_inputs: Vec<u8>,
-    ) -> Result<()> {
+    ) -> Result<SwapResult> {
         msg!("Instruction: ZkSwapReverse");
         
         // 1. Verify that the State Root used in the proof is valid
-        let claimed_root: [u8; 32] = public_inputs[0..32].try_into().map_err(|_| ErrorCode::InvalidProof)?;
-        require!(ctx.accounts.history.contains(&claimed_root), ErrorCode::InvalidStateRoot);
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.pool,
+            &ctx.accounts.verifier_program,
+            &ctx.accounts.verifier_state,
+            &proof,
+            &public_inputs,
+        );
 
-        swap::zk_swap_reverse(
-            &mut ctx.accounts.pool,
-            &ctx.accounts.token_program,
-            ctx.accounts.user_token_a.to_account_info(),
-            ctx.accounts.user_token_b.to_account_info(),
-            ctx.accounts.token_a_reserve.to_account_info(),
-            ctx.accounts.token_b_reserve.to_account_info(),
-            &ctx.accounts.user,
+        let accounts = swap::PoolSwapAccounts {
+            token_program: ctx.accounts.token_program.to_account_info(),
+            user: ctx.accounts.user.to_account_info(),
+            user_token_a: ctx.accounts.user_token_a.to_account_info(),
+            user_token_b: ctx.accounts.user_token_b.to_account_info(),
+            token_a_reserve: ctx.accounts.token_a_reserve.to_account_info(),
+            token_b_reserve: ctx.accounts.token_b_reserve.to_account_info(),
+            token_a_mint: ctx.accounts.token_a_mint.to_account_info(),
+            token_b_mint: ctx.accounts.token_b_mint.to_account_info(),
+            hook_accounts: ctx.remaining_accounts,
+        };
+        swap::zk_swap_reverse(&mut ctx.accounts.pool, accounts, &proof, amount_in, min_out, accept_any_output, deadline)
+    }
+
+    pub fn swap_exact_in<'info>(
+        ctx: Context<'_, '_, '_, 'info, ZKSwap<'info>>,
+        amount_in: u64,
+        min_out: u64,
+        accept_any_output: bool,
+        is_a_to_b: bool,
+        proof: Vec<u8>,
+        public_inputs: Vec<u8>,
+    ) -> Result<SwapResult> {
+        msg!("Instruction: SwapExactIn");
+
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.pool,
             &ctx.accounts.verifier_program,
-            amount_in,
-            min_out,
+            &ctx.accounts.verifier_state,
             &proof,
             &public_inputs,
-        )
+        );
+
+        let token_b_account = output_token_b(ctx.accounts, is_a_to_b)?;
+        let accounts = zk_swap_accounts(ctx.accounts, token_b_account, ctx.remaining_accounts);
+        swap::swap_exact_in(&mut ctx.accounts.pool, accounts, &proof, amount_in, min_out, accept_any_output, is_a_to_b)
+    }
+
+    pub fn swap_exact_out<'info>(
+        ctx: Context<'_, '_, '_, 'info, ZKSwap<'info>>,
+        amount_out: u64,
+        max_in: u64,
+        is_a_to_b: bool,
+        proof: Vec<u8>,
+        public_inputs: Vec<u8>,
+    ) -> Result<()> {
+        msg!("Instruction: SwapExactOut");
+
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.pool,
+            &ctx.accounts.verifier_program,
+            &ctx.accounts.verifier_state,
+            &proof,
+            &public_inputs,
+        );
+
+        let token_b_account = output_token_b(ctx.accounts, is_a_to_b)?;
+        let accounts = zk_swap_accounts(ctx.accounts, token_b_account, ctx.remaining_accounts);
+        swap::swap_exact_out(&mut ctx.accounts.pool, accounts, &proof, amount_out, max_in, is_a_to_b)
+    }
+
+    pub fn swap_with_limit<'info>(
+        ctx: Context<'_, '_, '_, 'info, ZKSwap<'info>>,
+        amount_in: u64,
+        limit_price: u128,
+        is_a_to_b: bool,
+        proof: Vec<u8>,
+        public_inputs: Vec<u8>,
+    ) -> Result<()> {
+        msg!("Instruction: SwapWithLimit");
+
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.pool,
+            &ctx.accounts.verifier_program,
+            &ctx.accounts.verifier_state,
+            &proof,
+            &public_inputs,
+        );
+
+        let token_b_account = output_token_b(ctx.accounts, is_a_to_b)?;
+        let accounts = zk_swap_accounts(ctx.accounts, token_b_account, ctx.remaining_accounts);
+        swap::swap_with_limit(&mut ctx.accounts.pool, accounts, &proof, amount_in, limit_price, is_a_to_b)
+    }
+
+    pub fn swap_with_slippage<'info>(
+        ctx: Context<'_, '_, '_, 'info, ZKSwap<'info>>,
+        amount_in: u64,
+        slippage_bps: u16,
+        is_a_to_b: bool,
+        proof: Vec<u8>,
+        public_inputs: Vec<u8>,
+    ) -> Result<()> {
+        msg!("Instruction: SwapWithSlippage");
+
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.pool,
+            &ctx.accounts.verifier_program,
+            &ctx.accounts.verifier_state,
+            &proof,
+            &public_inputs,
+        );
+
+        let token_b_account = output_token_b(ctx.accounts, is_a_to_b)?;
+        let accounts = zk_swap_accounts(ctx.accounts, token_b_account, ctx.remaining_accounts);
+        swap::swap_with_slippage(&mut ctx.accounts.pool, accounts, &proof, amount_in, slippage_bps, is_a_to_b)
     }
 
-    pub fn deposit(ctx: Context<DepositShielded>, amount: u64, commitment: [u8; 32]) -> Result<()> {
+    /// `opening` reveals the note and is only accepted by builds with the
+    /// `plain-commitments` feature, private deposits pass none
+    pub fn deposit(
+        ctx: Context<DepositShielded>,
+        amount: u64,
+        commitment: [u8; 32],
+        opening: Option<CommitmentOpening>,
+    ) -> Result<()> {
         msg!("Instruction: DepositShielded");
-        instructions::shielded_pool::deposit_shielded(ctx, amount, commitment)
+        instructions::shielded_pool::deposit_shielded(ctx, amount, commitment, opening)
+    }
+
+    /// several deposits paid for with a single token transfer
+    pub fn deposit_shielded_batch(
+        ctx: Context<DepositShielded>,
+        amounts: Vec<u64>,
+        commitments: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        msg!("Instruction: DepositShieldedBatch");
+        instructions::shielded_pool::deposit_shielded_batch(ctx, amounts, commitments)
+    }
+
+    /// deposit that is rooted immediately, returns the new root
+    pub fn deposit_and_commit(ctx: Context<DepositAndCommit>, amount: u64, commitment: [u8; 32]) -> Result<[u8; 32]> {
+        msg!("Instruction: DepositAndCommit");
+        instructions::shielded_pool::deposit_and_commit(ctx, amount, commitment)
     }
 
     pub fn swap_private<'info>(
         ctx: Context<'_, '_, '_, 'info, SwapPrivate<'info>>,
+        args: SwapPrivateArgs,
+    ) -> Result<()> {
+        msg!("Instruction: SwapPrivate");
+        instructions::shielded_pool::swap_private(ctx, args)
+    }
+
+    /// verifies a swap_private proof ahead of time so a retried swap can skip the cpi
+    pub fn cache_proof_verification(
+        ctx: Context<CacheProofVerification>,
         proof: Vec<u8>,
         public_inputs: Vec<u8>,
-        amount_in: u64,
-        min_out: u64,
-        is_a_to_b: bool,
         nullifier_hash: [u8; 32],
     ) -> Result<()> {
-        msg!("Instruction: SwapPrivate");
-        instructions::shielded_pool::swap_private(ctx, proof, public_inputs, amount_in, min_out, is_a_to_b, nullifier_hash)
+        msg!("Instruction: CacheProofVerification");
+        instructions::shielded_pool::cache_proof_verification(ctx, proof, public_inputs, nullifier_hash)
     }
 
-    pub fn initialize_shielded_pool(ctx: Context<InitializeShieldedPool>) -> Result<()> {
+    pub fn initialize_shielded_pool(ctx: Context<InitializeShieldedPool>, expected_verifier: Pubkey) -> Result<()> {
         msg!("Instruction: InitializeShieldedPool");
-        instructions::shielded_pool::initialize_shielded_pool(ctx)
+        instructions::shielded_pool::initialize_shielded_pool(ctx, expected_verifier)
+    }
+
+    pub fn initialize_shielded_tree(ctx: Context<InitializeShieldedTree>) -> Result<()> {
+        msg!("Instruction: InitializeShieldedTree");
+        instructions::shielded_pool::initialize_shielded_tree(ctx)
+    }
+
+    pub fn initialize_shielded_mint_allowlist(ctx: Context<InitializeShieldedMintAllowlist>) -> Result<()> {
+        msg!("Instruction: InitializeShieldedMintAllowlist");
+        instructions::shielded_pool::initialize_shielded_mint_allowlist(ctx)
+    }
+
+    pub fn add_shielded_mint(ctx: Context<UpdateShieldedMintAllowlist>, mint: Pubkey) -> Result<()> {
+        msg!("Instruction: AddShieldedMint");
+        instructions::shielded_pool::add_shielded_mint(ctx, mint)
+    }
+
+    pub fn remove_shielded_mint(ctx: Context<UpdateShieldedMintAllowlist>, mint: Pubkey) -> Result<()> {
+        msg!("Instruction: RemoveShieldedMint");
+        instructions::shielded_pool::remove_shielded_mint(ctx, mint)
     }
 
     pub fn initialize_shielded_root_history(ctx: Context<InitializeShieldedRootHistory>) -> Result<()> {
@@ -165,22 +671,111 @@ pub mod zkgate {
         instructions::shielded_pool::update_shielded_root(ctx, new_root, included_leaves)
     }
 
+    pub fn sync_current_root(ctx: Context<SyncShieldedRoot>) -> Result<()> {
+        msg!("Instruction: SyncCurrentRoot");
+        instructions::shielded_pool::sync_current_root(ctx)
+    }
+
+    pub fn get_shielded_index(ctx: Context<GetShieldedPool>) -> Result<u64> {
+        Ok(ctx.accounts.shielded_pool.next_index)
+    }
+
+    /// the leaf index and root a relayer needs before building a deposit or spend
+    pub fn get_shielded_pool_info(ctx: Context<GetShieldedPool>) -> Result<ShieldedPoolInfo> {
+        let pool = &ctx.accounts.shielded_pool;
+        Ok(ShieldedPoolInfo {
+            mint: pool.mint,
+            vault: pool.vault,
+            authority: pool.authority,
+            current_root: pool.current_root,
+            next_index: pool.next_index,
+            root_history: pool.root_history,
+            has_tree: pool.has_tree,
+        })
+    }
+
+    /// lamports a nullifier pda needs to be rent exempt under the current rent schedule
+    pub fn nullifier_rent(_ctx: Context<NullifierRent>) -> Result<u64> {
+        instructions::shielded_pool::nullifier_rent()
+    }
+
+    pub fn set_next_index(ctx: Context<ManageShieldedPool>, new_index: u64) -> Result<()> {
+        msg!("Instruction: SetNextIndex");
+        instructions::shielded_pool::set_next_index(ctx, new_index)
+    }
+
+    pub fn request_emergency(ctx: Context<ManageShieldedPool>) -> Result<()> {
+        msg!("Instruction: RequestEmergency");
+        instructions::shielded_pool::request_emergency(ctx)
+    }
+
+    pub fn cancel_emergency(ctx: Context<ManageShieldedPool>) -> Result<()> {
+        msg!("Instruction: CancelEmergency");
+        instructions::shielded_pool::cancel_emergency(ctx)
+    }
+
+    pub fn set_emergency_delay(ctx: Context<ManageShieldedPool>, delay: i64) -> Result<()> {
+        msg!("Instruction: SetEmergencyDelay");
+        instructions::shielded_pool::set_emergency_delay(ctx, delay)
+    }
+
+    /// authority sweep of the shielded vault, only after the emergency timelock
+    pub fn emergency_withdraw(ctx: Context<EmergencyWithdraw>, amount: u64) -> Result<()> {
+        msg!("Instruction: EmergencyWithdraw");
+        instructions::shielded_pool::emergency_withdraw(ctx, amount)
+    }
+
+    /// spends a shielded note to a token account. `merkle_proof` is
+    /// inclusion-only: its leaf is not a public input of the spend proof, so
+    /// the path shows that some leaf is in the tree at `current_root`, not
+    /// that it is the note being spent. what it changes is the root, the
+    /// spend proof then has to be built on `current_root`
     pub fn withdraw_shielded<'info>(
         ctx: Context<'_, '_, '_, 'info, WithdrawShielded<'info>>,
+        spend: ShieldedSpend,
+        root_index: Option<u64>,
+        merkle_proof: Option<MerkleProof>,
+    ) -> Result<()> {
+        msg!("Instruction: WithdrawShielded");
+        instructions::shielded_pool::withdraw_shielded(ctx, spend, root_index, merkle_proof)
+    }
+
+    /// spends several notes, each with its own proof and possibly its own
+    /// root, and pays their sum to one token account
+    pub fn withdraw_shielded_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawShieldedBatch<'info>>,
+        spends: Vec<ShieldedSpend>,
+    ) -> Result<()> {
+        msg!("Instruction: WithdrawShieldedBatch");
+        instructions::shielded_pool::withdraw_shielded_batch(ctx, spends)
+    }
+
+    pub fn withdraw_shielded_sol(
+        ctx: Context<WithdrawShieldedSol>,
         amount: u64,
         nullifier_hash: [u8; 32],
+        change_commitment: [u8; 32],
         proof: Vec<u8>,
         public_inputs: Vec<u8>,
+        root_index: Option<u64>,
     ) -> Result<()> {
-        msg!("Instruction: WithdrawShielded");
-        instructions::shielded_pool::withdraw_shielded(ctx, amount, nullifier_hash, proof, public_inputs)
+        msg!("Instruction: WithdrawShieldedSol");
+        instructions::shielded_pool::withdraw_shielded_sol(
+            ctx,
+            amount,
+            nullifier_hash,
+            change_commitment,
+            proof,
+            public_inputs,
+            root_index,
+        )
     }
 
     pub fn update_roots(ctx: Context<UpdateRoots>, new_root: [u8; 32]) -> Result<()> {
         msg!("Instruction: UpdateRoots");
         let history = &mut ctx.accounts.history;
         history.append(new_root);
-        msg!("Root appended: {:?}", new_root);
+        debug_msg!("Root appended: {:?}", new_root);
         Ok(())
     }
 
@@ -189,10 +784,314 @@ pub mod zkgate {
         let history = &mut ctx.accounts.history;
         history.authority = ctx.accounts.authority.key();
         history.current_index = 0;
+        history.roots = vec![[0u8; 32]; DEFAULT_ROOT_HISTORY_LEN];
+        history.written_at = vec![0; DEFAULT_ROOT_HISTORY_LEN];
         msg!("State Root History Initialized");
         Ok(())
     }
 
+    /// grows the state root ring so slow relayers stay inside the window,
+    /// the account is reallocated by the context before the remap
+    pub fn resize_root_history(ctx: Context<ResizeRootHistory>, new_len: u32) -> Result<()> {
+        msg!("Instruction: ResizeRootHistory");
+        let history = &mut ctx.accounts.history;
+        let old_len = history.capacity();
+        history.resize(new_len as usize)?;
+        msg!("Root history resized: {} -> {}", old_len, new_len);
+        Ok(())
+    }
+
+    /// moves a history created before resize_root_history onto the
+    /// resizable layout, its roots and current_index carry over
+    pub fn migrate_root_history(ctx: Context<MigrateRootHistory>) -> Result<()> {
+        msg!("Instruction: MigrateRootHistory");
+        instructions::admin::migrate_root_history(ctx)
+    }
+
+    pub fn commit_proof<'info>(
+        ctx: Context<'_, '_, '_, 'info, CommitProof<'info>>,
+        amount_in: u64,
+        min_out: u64,
+        accept_any_output: bool,
+        is_a_to_b: bool,
+        proof: Vec<u8>,
+        public_inputs: Vec<u8>,
+    ) -> Result<()> {
+        msg!("Instruction: CommitProof");
+
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.pool,
+            &ctx.accounts.verifier_program,
+            &ctx.accounts.verifier_state,
+            &proof,
+            &public_inputs,
+        );
+
+        swap::commit_proof(ctx, &proof, amount_in, min_out, accept_any_output, is_a_to_b)
+    }
+
+    pub fn settle_swap<'info>(ctx: Context<'_, '_, '_, 'info, SettleSwap<'info>>) -> Result<()> {
+        msg!("Instruction: SettleSwap");
+        swap::settle_swap(ctx)
+    }
+
+    pub fn migrate_reserve(ctx: Context<MigrateReserve>) -> Result<()> {
+        msg!("Instruction: MigrateReserve");
+        instructions::admin::migrate_reserve(ctx)
+    }
+
+    /// appends written_at to a shielded root history created before it
+    /// existed, run after migrate_shielded_pool
+    pub fn migrate_shielded_root_history(ctx: Context<MigrateShieldedRootHistory>) -> Result<()> {
+        msg!("Instruction: MigrateShieldedRootHistory");
+        instructions::admin::migrate_shielded_root_history(ctx)
+    }
+
+    /// grows a shielded pool created with the original layout, its spend
+    /// proofs must then go through `expected_verifier`
+    pub fn migrate_shielded_pool(ctx: Context<MigrateShieldedPool>, expected_verifier: Pubkey) -> Result<()> {
+        msg!("Instruction: MigrateShieldedPool");
+        instructions::admin::migrate_shielded_pool(ctx, expected_verifier)
+    }
+
+    pub fn recreate_reserve(ctx: Context<RecreateReserve>) -> Result<()> {
+        msg!("Instruction: RecreateReserve");
+        instructions::admin::recreate_reserve(ctx)
+    }
+
+    /// reclaims the rent of a pool with no lp shares outstanding beyond the
+    /// locked floor, sweeping the reserves behind that floor to the authority
+    pub fn close_pool(ctx: Context<ClosePool>) -> Result<()> {
+        msg!("Instruction: ClosePool");
+        instructions::admin::close_pool(ctx)
+    }
+
+    pub fn set_verifier_config(ctx: Context<ManagePool>, requires_state: bool) -> Result<()> {
+        msg!("Instruction: SetVerifierConfig");
+        ctx.accounts.pool.verifier_requires_state = requires_state;
+        Ok(())
+    }
+
+    /// points the pool at a new verifier program, e.g. after a verifier redeploy
+    pub fn set_expected_verifier(ctx: Context<ManagePool>, verifier_program: Pubkey) -> Result<()> {
+        msg!("Instruction: SetExpectedVerifier");
+        ctx.accounts.pool.expected_verifier = verifier_program;
+        msg!("Pool verifier: {}", verifier_program);
+        Ok(())
+    }
+
+    /// points post-swap notifications at `hook_program`, none turns them off
+    pub fn set_swap_hook(ctx: Context<ManagePool>, hook_program: Option<Pubkey>, hook_required: bool) -> Result<()> {
+        msg!("Instruction: SetSwapHook");
+        let pool = &mut ctx.accounts.pool;
+        pool.hook_program = hook_program;
+        pool.hook_required = hook_program.is_some() && hook_required;
+        msg!("Swap hook: {:?} (required: {})", pool.hook_program, pool.hook_required);
+        Ok(())
+    }
+
+    /// first half of an authority transfer, the pool keeps its current
+    /// authority until `new_authority` signs accept_authority. proposing
+    /// the default pubkey cancels a pending transfer
+    pub fn propose_authority(ctx: Context<ManagePool>, new_authority: Pubkey) -> Result<()> {
+        msg!("Instruction: ProposeAuthority");
+        ctx.accounts.pool.pending_authority = new_authority;
+        msg!("Pending authority: {}", new_authority);
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        msg!("Instruction: AcceptAuthority");
+        let pool = &mut ctx.accounts.pool;
+        pool.accept_authority(&ctx.accounts.new_authority.key())?;
+        msg!("Pool authority: {}", pool.authority);
+        Ok(())
+    }
+
+    pub fn set_shielded_verifier(ctx: Context<ManageShieldedPool>, verifier_program: Pubkey) -> Result<()> {
+        msg!("Instruction: SetShieldedVerifier");
+        ctx.accounts.shielded_pool.expected_verifier = verifier_program;
+        msg!("Shielded pool verifier: {}", verifier_program);
+        Ok(())
+    }
+
+    /// halts swaps on the pool, liquidity and views are unaffected
+    pub fn set_pause(ctx: Context<ManagePool>, paused: bool) -> Result<()> {
+        msg!("Instruction: SetPause");
+        ctx.accounts.pool.paused = paused;
+        msg!("Pool paused: {}", paused);
+        Ok(())
+    }
+
+    /// turns on the size-dependent fee, rising from fee_bps to `max_fee_bps`
+    /// for a swap taking half the input reserve. 0 restores the fixed fee
+    pub fn set_dynamic_fee(ctx: Context<ManagePool>, max_fee_bps: u16) -> Result<()> {
+        msg!("Instruction: SetDynamicFee");
+        let pool = &mut ctx.accounts.pool;
+        require!(
+            max_fee_bps == 0 || (max_fee_bps >= pool.fee_bps && max_fee_bps <= math::MAX_FEE_BPS),
+            ErrorCode::InvalidFeeBps
+        );
+        pool.max_fee_bps = max_fee_bps;
+        Ok(())
+    }
+
+    /// floor on private swap output, keeps unspendable dust out of the shielded set
+    pub fn set_min_output(ctx: Context<ManagePool>, min_output: u64) -> Result<()> {
+        msg!("Instruction: SetMinOutput");
+        ctx.accounts.pool.min_output = min_output;
+        msg!("Pool min output: {}", min_output);
+        Ok(())
+    }
+
+    /// makes withdrawals and private swaps prove against the latest root only
+    pub fn set_strict_root(ctx: Context<ManageShieldedPool>, strict_root: bool) -> Result<()> {
+        msg!("Instruction: SetStrictRoot");
+        ctx.accounts.shielded_pool.strict_root = strict_root;
+        msg!("Shielded pool strict root: {}", strict_root);
+        Ok(())
+    }
+
+    /// halts deposits, withdrawals and private swaps out of the shielded pool
+    pub fn set_shielded_pause(ctx: Context<ManageShieldedPool>, paused: bool) -> Result<()> {
+        msg!("Instruction: SetShieldedPause");
+        ctx.accounts.shielded_pool.paused = paused;
+        msg!("Shielded pool paused: {}", paused);
+        Ok(())
+    }
+
+    /// only has an effect when the program is built with the `test-mode` feature
+    pub fn set_test_mode(ctx: Context<ManagePool>, enabled: bool) -> Result<()> {
+        msg!("Instruction: SetTestMode");
+        ctx.accounts.pool.test_mode = enabled;
+        if enabled && !cfg!(feature = "test-mode") {
+            msg!("test-mode feature not compiled in, flag is ignored");
+        }
+        Ok(())
+    }
+
+    pub fn set_fee_distribution(
+        ctx: Context<SetFeeDistribution>,
+        recipients: Vec<Pubkey>,
+        weights: Vec<u16>,
+    ) -> Result<()> {
+        msg!("Instruction: SetFeeDistribution");
+        instructions::admin::set_fee_distribution(ctx, recipients, weights)
+    }
+
+    pub fn distribute_fees<'info>(
+        ctx: Context<'_, '_, '_, 'info, DistributeFees<'info>>,
+    ) -> Result<()> {
+        msg!("Instruction: DistributeFees");
+        instructions::admin::distribute_fees(ctx)
+    }
+
+    pub fn collect_fees(ctx: Context<CollectFees>, amount_a: u64, amount_b: u64) -> Result<()> {
+        msg!("Instruction: CollectFees");
+        instructions::admin::collect_fees(ctx, amount_a, amount_b)
+    }
+
+    /// token b amount matching `amount_a` and the lp shares add_liquidity
+    /// would mint for the pair. all zero while the pool is seeding
+    pub fn quote_add_liquidity(ctx: Context<GetLpPosition>, amount_a: u64) -> Result<LiquidityQuote> {
+        let pool = &ctx.accounts.pool;
+        let (amount_b, shares) =
+            math::quote_liquidity(amount_a, pool.token_a_reserve, pool.token_b_reserve, ctx.accounts.lp_mint.supply)?;
+        Ok(LiquidityQuote { amount_b, shares })
+    }
+
+    /// exact-in quote against the current reserves, same math as the swap
+    pub fn quote(ctx: Context<GetPoolInfo>, amount_in: u64, is_a_to_b: bool) -> Result<u64> {
+        let pool = &ctx.accounts.pool;
+        if is_a_to_b {
+            let fee_bps = pool.swap_fee_bps(amount_in, pool.token_a_reserve);
+            math::get_amount_out(amount_in, pool.token_a_reserve, pool.token_b_reserve, fee_bps)
+        } else {
+            let fee_bps = pool.swap_fee_bps(amount_in, pool.token_b_reserve);
+            math::get_amount_out(amount_in, pool.token_b_reserve, pool.token_a_reserve, fee_bps)
+        }
+    }
+
+    /// a -> b -> c through two pools atomically, returns the final output
+    pub fn swap_route_two<'info>(
+        ctx: Context<'_, '_, '_, 'info, SwapRouteTwo<'info>>,
+        amount_in: u64,
+        bounds: RouteBounds,
+        first_hook_accounts: u8,
+        proof: Vec<u8>,
+        public_inputs: Vec<u8>,
+    ) -> Result<u64> {
+        msg!("Instruction: SwapRouteTwo");
+        ctx.accounts.history.check_proof_root(&public_inputs)?;
+        let proof = swap_proof(
+            &ctx.accounts.first_pool,
+            &ctx.accounts.verifier_program,
+            &ctx.accounts.verifier_state,
+            &proof,
+            &public_inputs,
+        );
+        swap::swap_route_two(ctx, &proof, amount_in, bounds, first_hook_accounts)
+    }
+
+    /// quote with the fee broken out, for showing what the fee costs a trade
+    pub fn quote_detailed(ctx: Context<GetPoolInfo>, amount_in: u64, is_a_to_b: bool) -> Result<QuoteDetail> {
+        let pool = &ctx.accounts.pool;
+        if is_a_to_b {
+            let fee_bps = pool.swap_fee_bps(amount_in, pool.token_a_reserve);
+            math::quote_detail(amount_in, pool.token_a_reserve, pool.token_b_reserve, fee_bps)
+        } else {
+            let fee_bps = pool.swap_fee_bps(amount_in, pool.token_b_reserve);
+            math::quote_detail(amount_in, pool.token_b_reserve, pool.token_a_reserve, fee_bps)
+        }
+    }
+
+    /// quotes `amount_in` of `mint_in` against two pools for the same pair and
+    /// returns the one with more output. mint order may differ between pools
+    pub fn compare_pools(ctx: Context<ComparePools>, amount_in: u64, mint_in: Pubkey) -> Result<BestPoolQuote> {
+        let first = &ctx.accounts.first_pool;
+        let second = &ctx.accounts.second_pool;
+        let same_pair = (first.token_a_mint == second.token_a_mint && first.token_b_mint == second.token_b_mint)
+            || (first.token_a_mint == second.token_b_mint && first.token_b_mint == second.token_a_mint);
+        require!(same_pair, ErrorCode::PoolPairMismatch);
+        require!(amount_in > 0, ErrorCode::ZeroAmount);
+
+        let (first_in, first_out) = first.reserves_for(&mint_in).ok_or(ErrorCode::PoolPairMismatch)?;
+        let (second_in, second_out) = second.reserves_for(&mint_in).ok_or(ErrorCode::PoolPairMismatch)?;
+        // an empty pool just loses the comparison
+        let first_fee_bps = first.swap_fee_bps(amount_in, first_in);
+        let second_fee_bps = second.swap_fee_bps(amount_in, second_in);
+        let first_amount = math::get_amount_out(amount_in, first_in, first_out, first_fee_bps).unwrap_or(0);
+        let second_amount = math::get_amount_out(amount_in, second_in, second_out, second_fee_bps).unwrap_or(0);
+        require!(first_amount > 0 || second_amount > 0, ErrorCode::InsufficientLiquidity);
+
+        Ok(if first_amount >= second_amount {
+            BestPoolQuote { pool: first.key(), amount_out: first_amount }
+        } else {
+            BestPoolQuote { pool: second.key(), amount_out: second_amount }
+        })
+    }
+
+    /// "1 A = X B" (or B in A) in whole tokens, scaled by 10^DISPLAY_PRICE_DECIMALS
+    pub fn get_display_price(ctx: Context<GetPoolInfo>, is_a_to_b: bool) -> Result<u64> {
+        let pool = &ctx.accounts.pool;
+        if is_a_to_b {
+            math::display_price(pool.token_a_reserve, pool.token_b_reserve, pool.token_a_decimals, pool.token_b_decimals)
+        } else {
+            math::display_price(pool.token_b_reserve, pool.token_a_reserve, pool.token_b_decimals, pool.token_a_decimals)
+        }
+    }
+
+    /// every derivable address a zk_swap on this pair needs, no account reads
+    pub fn swap_accounts(
+        _ctx: Context<DeriveSwapAccounts>,
+        token_a_mint: Pubkey,
+        token_b_mint: Pubkey,
+        user: Pubkey,
+    ) -> Result<SwapAccounts> {
+        Ok(swap::derive_swap_accounts(&token_a_mint, &token_b_mint, &user))
+    }
+
     pub fn get_pool_info(ctx: Context<GetPoolInfo>) -> Result<PoolInfo> {
         let pool = &ctx.accounts.pool;
         Ok(PoolInfo {
@@ -203,10 +1102,133 @@ pub mod zkgate {
             k: pool.k,
             total_fees_a: pool.total_fees_a,
             total_fees_b: pool.total_fees_b,
+            volume_a: pool.volume_a,
+            volume_b: pool.volume_b,
+        })
+    }
+
+    /// (token a, token b) fees collect_fees would accept, read before paying
+    /// for a collection transaction
+    pub fn get_claimable_fees(ctx: Context<GetPoolInfo>) -> Result<(u64, u64)> {
+        Ok(ctx.accounts.pool.claimable_fees())
+    }
+
+    /// token a priced in token b from the live reserves, scaled by math::PRICE_PRECISION
+    pub fn get_spot_price(ctx: Context<GetPoolInfo>) -> Result<u128> {
+        let pool = &ctx.accounts.pool;
+        math::spot_price(pool.token_a_reserve, pool.token_b_reserve)
+    }
+
+    /// token_a_reserve * token_b_reserve recomputed from the reserves, so it
+    /// can be checked against the stored `k` for accounting drift
+    pub fn get_k(ctx: Context<GetPoolInfo>) -> Result<u128> {
+        let pool = &ctx.accounts.pool;
+        math::live_k(pool.token_a_reserve, pool.token_b_reserve)
+    }
+
+    /// pool tvl in token b. `reference_price` is the token a price in token b
+    /// scaled by math::PRICE_PRECISION and is trusted as supplied by the client
+    pub fn get_tvl(ctx: Context<GetPoolInfo>, reference_price: u128) -> Result<u128> {
+        let pool = &ctx.accounts.pool;
+        math::value_in_token_b(pool.token_a_reserve, pool.token_b_reserve, reference_price)
+    }
+
+    /// lifetime fee counters with the slot and time they were read. two
+    /// samples difference to the fees earned over the window between them
+    pub fn get_fee_snapshot(ctx: Context<GetPoolInfo>) -> Result<FeeSnapshot> {
+        let pool = &ctx.accounts.pool;
+        let clock = Clock::get()?;
+        Ok(FeeSnapshot {
+            lifetime_fees_a: pool.lifetime_fees_a,
+            lifetime_fees_b: pool.lifetime_fees_b,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        })
+    }
+
+    /// what `lp_balance` shares redeem for, and how much of that is accrued fees.
+    /// the supply lives on the lp mint, so it is the one extra account
+    pub fn get_lp_position(ctx: Context<GetLpPosition>, lp_balance: u64) -> Result<LpPosition> {
+        let pool = &ctx.accounts.pool;
+        math::lp_position(
+            lp_balance,
+            ctx.accounts.lp_mint.supply,
+            (pool.token_a_reserve, pool.token_b_reserve),
+            (pool.total_fees_a, pool.total_fees_b),
+        )
+    }
+
+    pub fn get_pool_activity(ctx: Context<GetPoolInfo>) -> Result<PoolActivity> {
+        let pool = &ctx.accounts.pool;
+        Ok(PoolActivity {
+            created_at: pool.created_at,
+            last_swap_at: pool.last_swap_at,
         })
     }
 }
 
+/// creation settings shared by create_pool and create_pool_with_reserves
+struct PoolConfig {
+    reject_freezable_mints: bool,
+    fee_bps: u16,
+    expected_verifier: Pubkey,
+    max_swap_bps: u16,
+}
+
+/// checks the mints and config, then writes a fresh pool tracking
+/// `init_a`/`init_b` as its reserves
+fn init_pool(
+    pool: &mut Pool,
+    (token_a_mint, token_b_mint): (&InterfaceAccount<Mint>, &InterfaceAccount<Mint>),
+    lp_mint: Pubkey,
+    authority: Pubkey,
+    bump: u8,
+    (init_a, init_b): (u64, u64),
+    config: PoolConfig,
+) -> Result<()> {
+    require!(config.fee_bps <= math::MAX_FEE_BPS, ErrorCode::InvalidFeeBps);
+    Pool::validate_mint_order(&token_a_mint.key(), &token_b_mint.key())?;
+    // opt-in, a mint freeze authority can freeze the pool's reserves
+    if config.reject_freezable_mints {
+        require!(token_a_mint.freeze_authority.is_none(), ErrorCode::MintFreezable);
+        require!(token_b_mint.freeze_authority.is_none(), ErrorCode::MintFreezable);
+    }
+    pool.token_a_mint = token_a_mint.key();
+    pool.token_b_mint = token_b_mint.key();
+    pool.token_a_reserve = init_a;
+    pool.token_b_reserve = init_b;
+    pool.k = (init_a as u128).checked_mul(init_b as u128).ok_or(ErrorCode::MathOverflow)?;
+    pool.bump = bump;
+    pool.authority = authority;
+    pool.total_fees_a = 0;
+    pool.total_fees_b = 0;
+    pool.lifetime_fees_a = 0;
+    pool.lifetime_fees_b = 0;
+    pool.created_at = Clock::get()?.unix_timestamp;
+    pool.last_swap_at = 0;
+    pool.price_cumulative_a = 0;
+    pool.price_cumulative_b = 0;
+    pool.last_update_ts = pool.created_at;
+    pool.verifier_requires_state = false;
+    pool.test_mode = false;
+    pool.lp_mint = lp_mint;
+    pool.token_a_decimals = token_a_mint.decimals;
+    pool.token_b_decimals = token_b_mint.decimals;
+    pool.fee_bps = config.fee_bps;
+    pool.max_fee_bps = 0;
+    pool.locked = false;
+    pool.paused = false;
+    pool.min_output = 0;
+    pool.expected_verifier = config.expected_verifier;
+    pool.max_swap_bps = config.max_swap_bps;
+    pool.volume_a = 0;
+    pool.volume_b = 0;
+    pool.hook_program = None;
+    pool.hook_required = false;
+    pool.pending_authority = Pubkey::default();
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct UpdateRoots<'info> {
     #[account(mut, has_one = authority)]
@@ -214,6 +1236,22 @@ pub struct UpdateRoots<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(new_len: u32)]
+pub struct ResizeRootHistory<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        realloc = StateRootHistory::space(new_len as usize),
+        realloc::payer = authority,
+        realloc::zero = false
+    )]
+    pub history: Box<Account<'info, StateRootHistory>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct InitializeHistory<'info> {
     #[account(
@@ -226,3 +1264,52 @@ pub struct InitializeHistory<'info> {
     pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
+
+/// the proof a swap instruction carries, with the verifier state passed on
+/// only when the pool's verifier needs one
+fn swap_proof<'a, 'info>(
+    pool: &Pool,
+    verifier_program: &UncheckedAccount<'info>,
+    verifier_state: &UncheckedAccount<'info>,
+    proof: &'a [u8],
+    public_inputs: &'a [u8],
+) -> swap::SwapProof<'a, 'info> {
+    swap::SwapProof {
+        verifier_program: verifier_program.to_account_info(),
+        verifier_state: pool.verifier_requires_state.then(|| verifier_state.to_account_info()),
+        proof,
+        public_inputs,
+    }
+}
+
+/// user_token_b, or recipient_token_b when one is passed. the recipient only
+/// stands in for the output side, so a b to a swap rejects it
+fn output_token_b<'info>(accounts: &ZKSwap<'info>, is_a_to_b: bool) -> Result<AccountInfo<'info>> {
+    match &accounts.recipient_token_b {
+        Some(recipient) => {
+            require!(is_a_to_b, ErrorCode::InvalidRecipient);
+            Ok(recipient.to_account_info())
+        }
+        None => Ok(accounts.user_token_b.to_account_info()),
+    }
+}
+
+/// the swap accounts of a ZKSwap instruction, `token_b_account` standing in
+/// for user_token_b so a recipient can take the b side
+fn zk_swap_accounts<'c, 'info>(
+    accounts: &ZKSwap<'info>,
+    token_b_account: AccountInfo<'info>,
+    hook_accounts: &'c [AccountInfo<'info>],
+) -> swap::PoolSwapAccounts<'c, 'info> {
+    swap::PoolSwapAccounts {
+        token_program: accounts.token_program.to_account_info(),
+        user: accounts.user.to_account_info(),
+        user_token_a: accounts.user_token_a.to_account_info(),
+        user_token_b: token_b_account,
+        token_a_reserve: accounts.token_a_reserve.to_account_info(),
+        token_b_reserve: accounts.token_b_reserve.to_account_info(),
+        token_a_mint: accounts.token_a_mint.to_account_info(),
+        token_b_mint: accounts.token_b_mint.to_account_info(),
+        hook_accounts,
+    }
+}
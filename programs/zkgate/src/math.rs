@@ -1,57 +1,569 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::program::invoke;
-use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
 use crate::errors::ErrorCode;
+use crate::state::{LpPosition, QuoteDetail, FEE_WEIGHT_DENOMINATOR};
 
-pub const FEE_BPS: u64 = 30;
+/// default pool fee, pools store their own in Pool::fee_bps
+pub const FEE_BPS: u16 = 30;
+pub const MAX_FEE_BPS: u16 = 1000;
 pub const FEE_DENOMINATOR: u64 = 10000;
-const FEE_NUMERATOR: u128 = 997;
-const FEE_DENOM: u128 = 1000;
+/// fixed-point scale for prices, 1.0 == PRICE_PRECISION
+pub const PRICE_PRECISION: u128 = 1_000_000_000_000;
+/// decimal places of the ui-facing price returned by display_price
+pub const DISPLAY_PRICE_DECIMALS: u32 = 6;
+/// cap on total lp share supply. initial shares are sqrt(a*b) of two u64
+/// reserves so they already fit in u64, the cap keeps headroom below u64::MAX
+/// so supply additions can't overflow and share*reserve products stay well
+/// inside u128 for the proportional mint/burn math
+pub const MAX_LP_SUPPLY: u64 = 1 << 62;
+/// lp shares locked for good on a pool's first deposit. a first depositor
+/// who mints a dust supply and then inflates the reserves can round later
+/// deposits down to zero shares, with this floor under the supply the same
+/// attack leaves them diluted by at most 1/MINIMUM_LIQUIDITY
+pub const MINIMUM_LIQUIDITY: u64 = 1000;
+/// ceiling for swap_with_slippage, past half the quote min_out stops protecting anything
+pub const MAX_SLIPPAGE_BPS: u16 = 5000;
 
-pub fn calculate_fee(amount: u64) -> Result<u64> {
+pub fn calculate_fee(amount: u64, fee_bps: u16) -> Result<u64> {
     let fee = (amount as u128)
-        .checked_mul(FEE_BPS as u128)
+        .checked_mul(fee_bps as u128)
         .ok_or(ErrorCode::MathOverflow)?
         .checked_div(FEE_DENOMINATOR as u128)
         .ok_or(ErrorCode::MathOverflow)?;
     Ok(fee as u64)
 }
 
-pub fn get_amount_out(amount_in: u64, reserve_in: u64, reserve_out: u64) -> Result<u64> {
+/// full 256-bit product of two u128s as (hi, lo)
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a1, a0) = (a >> 64, a & MASK);
+    let (b1, b0) = (b >> 64, b & MASK);
+    let p00 = a0 * b0;
+    let p01 = a0 * b1;
+    let p10 = a1 * b0;
+    let p11 = a1 * b1;
+    let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+    let lo = (p00 & MASK) | (mid << 64);
+    let hi = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+/// floor(a * b / c) without overflowing on the intermediate product. the
+/// common case stays in u128, larger products fall back to 256-bit long division
+pub fn mul_div(a: u128, b: u128, c: u128) -> Result<u128> {
+    require!(c > 0, ErrorCode::MathOverflow);
+    if let Some(product) = a.checked_mul(b) {
+        return Ok(product / c);
+    }
+
+    let (hi, lo) = full_mul(a, b);
+    // the quotient only fits in u128 when the high half is below the divisor
+    require!(hi < c, ErrorCode::MathOverflow);
+    let mut rem = hi;
+    let mut quotient: u128 = 0;
+    for i in (0..128).rev() {
+        let carry = rem >> 127;
+        rem = (rem << 1) | ((lo >> i) & 1);
+        quotient <<= 1;
+        if carry == 1 || rem >= c {
+            rem = rem.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    Ok(quotient)
+}
+
+/// size-dependent fee: rises linearly from `base_fee_bps` with the share of
+/// the input reserve a swap takes, reaching `max_fee_bps` at half the
+/// reserve. a max at or below the base leaves the fee fixed at the base
+pub fn effective_fee_bps(amount_in: u64, reserve_in: u64, base_fee_bps: u16, max_fee_bps: u16) -> u16 {
+    if max_fee_bps <= base_fee_bps {
+        return base_fee_bps;
+    }
+    if reserve_in == 0 {
+        return max_fee_bps;
+    }
+    let spread = (max_fee_bps - base_fee_bps) as u128;
+    let scaled = spread * amount_in as u128 * 2 / reserve_in as u128;
+    base_fee_bps + scaled.min(spread) as u16
+}
+
+/// constant product output with the fee taken from the input,
+/// the fee multiplier is (FEE_DENOMINATOR - fee_bps) / FEE_DENOMINATOR
+pub fn get_amount_out(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Result<u64> {
     require!(amount_in > 0, ErrorCode::ZeroAmount);
     require!(reserve_in > 0 && reserve_out > 0, ErrorCode::InsufficientLiquidity);
+    require!(fee_bps <= MAX_FEE_BPS, ErrorCode::InvalidFeeBps);
 
     let amount_in_with_fee = (amount_in as u128)
-        .checked_mul(FEE_NUMERATOR)
+        .checked_mul((FEE_DENOMINATOR - fee_bps as u64) as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    let denominator = (reserve_in as u128)
+        .checked_mul(FEE_DENOMINATOR as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_add(amount_in_with_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    // amount_in_with_fee * reserve_out can pass u128 on large pools, the
+    // quotient itself is always below reserve_out
+    let amount_out = mul_div(amount_in_with_fee, reserve_out as u128, denominator)?;
+
+    Ok(amount_out as u64)
+}
+
+/// input needed to receive `amount_out`, the inverse of get_amount_out rounded
+/// up so the swap is never short: get_amount_out(get_amount_in(x)) >= x
+pub fn get_amount_in(amount_out: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Result<u64> {
+    require!(amount_out > 0, ErrorCode::ZeroAmount);
+    require!(reserve_in > 0 && reserve_out > 0, ErrorCode::InsufficientLiquidity);
+    require!(amount_out < reserve_out, ErrorCode::InsufficientLiquidity);
+    require!(fee_bps <= MAX_FEE_BPS, ErrorCode::InvalidFeeBps);
+
+    let denominator = ((reserve_out - amount_out) as u128)
+        .checked_mul((FEE_DENOMINATOR - fee_bps as u64) as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    let scaled_reserve_in = (reserve_in as u128) * (FEE_DENOMINATOR as u128);
+    let amount_in = mul_div(scaled_reserve_in, amount_out as u128, denominator)?
+        .checked_add(1)
         .ok_or(ErrorCode::MathOverflow)?;
 
-    let numerator = amount_in_with_fee
-        .checked_mul(reserve_out as u128)
+    u64::try_from(amount_in).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// get_amount_in under a size-dependent fee. the fee only rises with the
+/// input, so stepping up from the base fee stops at the first fee that
+/// covers the input it implies
+pub fn get_amount_in_dynamic(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    base_fee_bps: u16,
+    max_fee_bps: u16,
+) -> Result<u64> {
+    let mut fee_bps = base_fee_bps;
+    loop {
+        let amount_in = get_amount_in(amount_out, reserve_in, reserve_out, fee_bps)?;
+        let required = effective_fee_bps(amount_in, reserve_in, base_fee_bps, max_fee_bps);
+        if required <= fee_bps {
+            return Ok(amount_in);
+        }
+        fee_bps = required;
+    }
+}
+
+/// how far the execution price of a trade sits below the spot price, in bps
+/// of the spot price and rounded up. includes the fee, since the trader pays it
+pub fn price_impact_bps(amount_in: u64, amount_out: u64, reserve_in: u64, reserve_out: u64) -> Result<u16> {
+    require!(amount_in > 0, ErrorCode::ZeroAmount);
+    require!(reserve_in > 0 && reserve_out > 0, ErrorCode::InsufficientLiquidity);
+
+    // spot = reserve_out / reserve_in, execution = amount_out / amount_in
+    // impact = 1 - execution / spot = (spot_term - exec_term) / spot_term
+    let spot_term = (amount_in as u128) * (reserve_out as u128);
+    let exec_term = (amount_out as u128) * (reserve_in as u128);
+    let diff = spot_term.saturating_sub(exec_term);
+
+    let bps = FEE_DENOMINATOR as u128;
+    // both terms are up to u64::MAX^2, drop low bits before scaling if needed
+    let (diff, spot_term) = if diff > u128::MAX / bps {
+        (diff >> 16, spot_term >> 16)
+    } else {
+        (diff, spot_term)
+    };
+    let impact = (diff * bps).div_ceil(spot_term);
+    Ok(impact.min(bps) as u16)
+}
+
+/// optional per-swap guard on top of min_out: rejects a trade whose
+/// execution price sits more than `max_impact_bps` below the spot price.
+/// the fee counts towards the impact, so 0 rejects every swap
+pub fn check_price_impact(
+    amount_in: u64,
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    max_impact_bps: Option<u16>,
+) -> Result<()> {
+    let Some(max_impact_bps) = max_impact_bps else {
+        return Ok(());
+    };
+    let impact = price_impact_bps(amount_in, amount_out, reserve_in, reserve_out)?;
+    require!(impact <= max_impact_bps, ErrorCode::ExcessivePriceImpact);
+    Ok(())
+}
+
+/// quotes the internal swap of a single-sided deposit and rejects it when its
+/// price impact exceeds what the depositor accepted
+pub fn quote_internal_swap(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u16,
+    max_internal_slippage_bps: u16,
+) -> Result<u64> {
+    let amount_out = get_amount_out(amount_in, reserve_in, reserve_out, fee_bps)?;
+    let impact = price_impact_bps(amount_in, amount_out, reserve_in, reserve_out)?;
+    require!(impact <= max_internal_slippage_bps, ErrorCode::InternalSlippageExceeded);
+    Ok(amount_out)
+}
+
+/// isqrt(x * y) for products past u128, halving both factors until the
+/// product fits and scaling the root back up
+fn sqrt_product(mut x: u128, mut y: u128) -> u128 {
+    let mut shift = 0;
+    while x.checked_mul(y).is_none() {
+        x >>= 1;
+        y >>= 1;
+        shift += 1;
+    }
+    isqrt(x * y) << shift
+}
+
+/// part of a single-sided deposit to swap first so the remainder and the swap
+/// output match the post-swap reserve ratio. solves
+/// s^2 * g + s * r * (F + g) - a * r * F = 0 with g = F - fee, so the fee
+/// shifts the split instead of leaving dust on one side
+pub fn single_sided_swap_amount(amount_in: u64, reserve_in: u64, fee_bps: u16) -> Result<u64> {
+    require!(amount_in > 0, ErrorCode::ZeroAmount);
+    require!(reserve_in > 0, ErrorCode::InsufficientLiquidity);
+    let f = FEE_DENOMINATOR as u128;
+    let g = f - fee_bps as u128;
+    let r = reserve_in as u128;
+    let a = amount_in as u128;
+
+    let b = r * (f + g);
+    let inner = r * (f + g) * (f + g) + 4 * a * g * f;
+    let root = sqrt_product(r, inner);
+    let swap = root.saturating_sub(b) / (2 * g);
+    Ok(swap.min(a) as u64)
+}
+
+/// lp shares for depositing `amount_in` of one side: swaps
+/// `swap_amount` of it, then adds the rest with the swap output against the
+/// post-swap reserves. returns (swap_out, shares)
+pub fn single_sided_shares(
+    amount_in: u64,
+    swap_amount: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    lp_supply: u64,
+    fee_bps: u16,
+) -> Result<(u64, u64)> {
+    require!(lp_supply > 0, ErrorCode::InsufficientLiquidity);
+    let swap_out = get_amount_out(swap_amount, reserve_in, reserve_out, fee_bps)?;
+    let shares = shares_to_mint(
+        amount_in - swap_amount,
+        swap_out,
+        reserve_in.checked_add(swap_amount).ok_or(ErrorCode::MathOverflow)?,
+        reserve_out - swap_out,
+        lp_supply,
+    )?;
+    Ok((swap_out, shares))
+}
+
+/// token b amount that matches `amount_a` at the current reserve ratio.
+/// an empty pool returns 0, meaning the depositor sets the initial ratio
+pub fn quote_amount_b(amount_a: u64, reserve_a: u64, reserve_b: u64) -> Result<u64> {
+    require!(amount_a > 0, ErrorCode::ZeroAmount);
+    if reserve_a == 0 || reserve_b == 0 {
+        return Ok(0);
+    }
+
+    let amount_b = (amount_a as u128)
+        .checked_mul(reserve_b as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(reserve_a as u128)
         .ok_or(ErrorCode::MathOverflow)?;
 
+    u64::try_from(amount_b).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// (amount_b, depositor shares) for adding `amount_a` at the current ratio,
+/// with the shares add_liquidity would mint. (0, 0) while the pool is
+/// seeding, the depositor sets the ratio then
+pub fn quote_liquidity(amount_a: u64, reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<(u64, u64)> {
+    require!(amount_a > 0, ErrorCode::ZeroAmount);
+    if is_seeding(reserve_a, reserve_b, lp_supply) {
+        return Ok((0, 0));
+    }
+    let amount_b = quote_amount_b(amount_a, reserve_a, reserve_b)?;
+    let (shares, _) = deposit_shares(amount_a, amount_b, reserve_a, reserve_b, lp_supply)?;
+    Ok((amount_b, shares))
+}
+
+/// realized price of a swap as output per unit of input, scaled by PRICE_PRECISION
+pub fn execution_price(amount_in: u64, amount_out: u64) -> Result<u128> {
+    require!(amount_in > 0, ErrorCode::ZeroAmount);
+    (amount_out as u128)
+        .checked_mul(PRICE_PRECISION)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(amount_in as u128)
+        .ok_or(ErrorCode::MathOverflow.into())
+}
+
+/// the fee-bearing quote next to the same trade at a zero fee, so the cost
+/// of the fee is amount_out_no_fee - amount_out
+pub fn quote_detail(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Result<QuoteDetail> {
+    Ok(QuoteDetail {
+        amount_out: get_amount_out(amount_in, reserve_in, reserve_out, fee_bps)?,
+        amount_out_no_fee: get_amount_out(amount_in, reserve_in, reserve_out, 0)?,
+        fee_amount: calculate_fee(amount_in, fee_bps)?,
+        mid_price: execution_price(reserve_in, reserve_out)?,
+    })
+}
+
+/// raw spot price of token a in token b, scaled by PRICE_PRECISION
+pub fn spot_price(reserve_a: u64, reserve_b: u64) -> Result<u128> {
+    require!(reserve_a > 0 && reserve_b > 0, ErrorCode::InsufficientLiquidity);
+    execution_price(reserve_a, reserve_b)
+}
+
+/// live constant-product invariant of the tracked reserves
+pub fn live_k(reserve_a: u64, reserve_b: u64) -> Result<u128> {
+    require!(reserve_a > 0 && reserve_b > 0, ErrorCode::InsufficientLiquidity);
+    Ok(reserve_a as u128 * reserve_b as u128)
+}
+
+/// spot price of one whole `in` token in whole `out` tokens, corrected for
+/// both mints' decimals and scaled by 10^DISPLAY_PRICE_DECIMALS
+pub fn display_price(reserve_in: u64, reserve_out: u64, decimals_in: u8, decimals_out: u8) -> Result<u64> {
+    require!(reserve_in > 0 && reserve_out > 0, ErrorCode::InsufficientLiquidity);
+    let pow = |d: u32| 10u128.checked_pow(d).ok_or(ErrorCode::MathOverflow);
+
+    let numerator = (reserve_out as u128)
+        .checked_mul(pow(decimals_in as u32 + DISPLAY_PRICE_DECIMALS)?)
+        .ok_or(ErrorCode::MathOverflow)?;
     let denominator = (reserve_in as u128)
-        .checked_mul(FEE_DENOM)
+        .checked_mul(pow(decimals_out as u32)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let price = numerator.checked_div(denominator).ok_or(ErrorCode::MathOverflow)?;
+
+    u64::try_from(price).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// total value of both reserves denominated in token b. `price_a_in_b` is the
+/// value of one raw unit of token a in raw token b units, scaled by PRICE_PRECISION.
+/// the price is supplied by the caller and trusted as-is, nothing here checks it
+pub fn value_in_token_b(reserve_a: u64, reserve_b: u64, price_a_in_b: u128) -> Result<u128> {
+    let value_a = (reserve_a as u128)
+        .checked_mul(price_a_in_b)
         .ok_or(ErrorCode::MathOverflow)?
-        .checked_add(amount_in_with_fee)
+        .checked_div(PRICE_PRECISION)
         .ok_or(ErrorCode::MathOverflow)?;
+    value_a.checked_add(reserve_b as u128).ok_or(ErrorCode::MathOverflow.into())
+}
+
+/// post-condition for liquidity removal: both reserves and the lp supply must
+/// reach zero together, otherwise the pool is left with shares backed by
+/// nothing (or reserves nobody can claim). any permanently locked liquidity
+/// keeps the supply above zero, so the reserves must stay non-zero with it
+pub fn check_liquidity_invariant(reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<()> {
+    let shares_empty = lp_supply == 0;
+    require!((reserve_a == 0) == shares_empty, ErrorCode::InvariantViolated);
+    require!((reserve_b == 0) == shares_empty, ErrorCode::InvariantViolated);
+    Ok(())
+}
+
+/// a pool is empty once only the never-spent MINIMUM_LIQUIDITY shares in its
+/// lp lock are outstanding. the reserves left behind back nothing but those,
+/// any other lp share still has a claim on them
+pub fn check_pool_empty(lp_supply: u64, locked_lp: u64) -> Result<()> {
+    require!(lp_supply == locked_lp, ErrorCode::PoolNotEmpty);
+    Ok(())
+}
+
+/// post-condition for a swap: the fee stays in the reserves, so k can only
+/// grow. a drop means the reserve bookkeeping paid out more than it took in
+pub fn check_swap_invariant(k_before: u128, k_after: u128) -> Result<()> {
+    require!(k_after >= k_before, ErrorCode::InvariantViolated);
+    Ok(())
+}
+
+/// per-pool circuit breaker: a single swap may take in at most
+/// `max_swap_bps` of the input reserve. 0 leaves swaps uncapped
+pub fn check_swap_size(amount_in: u64, reserve_in: u64, max_swap_bps: u16) -> Result<()> {
+    if max_swap_bps == 0 {
+        return Ok(());
+    }
+    let cap = (reserve_in as u128)
+        .checked_mul(max_swap_bps as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / FEE_DENOMINATOR as u128;
+    require!(amount_in as u128 <= cap, ErrorCode::SwapTooLarge);
+    Ok(())
+}
+
+/// min_out for a trade quoted at `expected_out` that may fill up to
+/// `slippage_bps` worse, rounded down
+pub fn slippage_floor(expected_out: u64, slippage_bps: u16) -> Result<u64> {
+    require!(slippage_bps <= MAX_SLIPPAGE_BPS, ErrorCode::InvalidSlippage);
+    let floor = (expected_out as u128) * (FEE_DENOMINATOR - slippage_bps as u64) as u128 / FEE_DENOMINATOR as u128;
+    Ok(floor as u64)
+}
+
+/// floor square root, newton's method. the first guess is the power of two
+/// just above sqrt(value), so the iterates fall monotonically to the floor
+/// in a handful of steps rather than halving down from `value`
+pub fn isqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let bits = 128 - value.leading_zeros();
+    let mut x = 1u128 << bits.div_ceil(2);
+    let mut y = (x + value / x) / 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+/// amount that actually arrived in a reserve, from its balance before and
+/// after a transfer of `nominal`. fee-bearing mints may deliver less, any
+/// other mint has to deliver exactly `nominal`
+pub fn received_amount(balance_before: u64, balance_after: u64, nominal: u64, charges_fee: bool) -> Result<u64> {
+    let received = balance_after.checked_sub(balance_before).ok_or(ErrorCode::TransferAmountMismatch)?;
+    require!(received <= nominal, ErrorCode::TransferAmountMismatch);
+    require!(charges_fee || received == nominal, ErrorCode::TransferAmountMismatch);
+    Ok(received)
+}
+
+/// true when a deposit has no price to follow and sets one instead: the
+/// first deposit, or the next one after either reserve was drained to zero
+pub fn is_seeding(reserve_a: u64, reserve_b: u64, lp_supply: u64) -> bool {
+    lp_supply == 0 || reserve_a == 0 || reserve_b == 0
+}
+
+/// splits freshly minted shares into (depositor, locked). only a seeding
+/// deposit locks MINIMUM_LIQUIDITY, and it must mint at least that
+pub fn split_initial_shares(shares: u64, seeding: bool) -> Result<(u64, u64)> {
+    if !seeding {
+        return Ok((shares, 0));
+    }
+    require!(shares >= MINIMUM_LIQUIDITY, ErrorCode::InsufficientInitialLiquidity);
+    Ok((shares - MINIMUM_LIQUIDITY, MINIMUM_LIQUIDITY))
+}
+
+/// lp shares minted for a deposit. an empty pool mints sqrt(a*b), otherwise
+/// the smaller of the two proportional amounts so an unbalanced deposit
+/// can't dilute existing holders. rejects mints that push supply past MAX_LP_SUPPLY
+pub fn shares_to_mint(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Result<u64> {
+    require!(amount_a > 0 && amount_b > 0, ErrorCode::ZeroAmount);
+
+    let shares = if is_seeding(reserve_a, reserve_b, lp_supply) {
+        isqrt((amount_a as u128) * (amount_b as u128))
+    } else {
+        let from_a = (amount_a as u128) * (lp_supply as u128) / (reserve_a as u128);
+        let from_b = (amount_b as u128) * (lp_supply as u128) / (reserve_b as u128);
+        from_a.min(from_b)
+    };
 
-    let amount_out = numerator
-        .checked_div(denominator)
+    let new_supply = (lp_supply as u128)
+        .checked_add(shares)
         .ok_or(ErrorCode::MathOverflow)?;
+    require!(new_supply <= MAX_LP_SUPPLY as u128, ErrorCode::ShareCapExceeded);
+    Ok(shares as u64)
+}
 
-    Ok(amount_out as u64)
+/// (depositor, locked) shares add_liquidity mints for a deposit, a seeding
+/// deposit locks MINIMUM_LIQUIDITY of them
+pub fn deposit_shares(amount_a: u64, amount_b: u64, reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<(u64, u64)> {
+    let minted = shares_to_mint(amount_a, amount_b, reserve_a, reserve_b, lp_supply)?;
+    split_initial_shares(minted, is_seeding(reserve_a, reserve_b, lp_supply))
+}
+
+/// pro-rata reserves and accrued fees behind `lp_balance` shares, rounded down
+pub fn lp_position(
+    lp_balance: u64,
+    lp_supply: u64,
+    reserves: (u64, u64),
+    fees: (u64, u64),
+) -> Result<LpPosition> {
+    require!(lp_balance <= lp_supply, ErrorCode::InsufficientLiquidity);
+    if lp_balance == 0 {
+        return Ok(LpPosition { amount_a: 0, amount_b: 0, fees_a: 0, fees_b: 0 });
+    }
+    let share = |amount: u64| ((amount as u128) * (lp_balance as u128) / (lp_supply as u128)) as u64;
+    Ok(LpPosition {
+        amount_a: share(reserves.0),
+        amount_b: share(reserves.1),
+        fees_a: share(fees.0),
+        fees_b: share(fees.1),
+    })
+}
+
+/// reserve amounts returned for burning `shares` out of `lp_supply`, rounded down
+pub fn shares_to_withdraw(
+    shares: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Result<(u64, u64)> {
+    require!(shares > 0, ErrorCode::ZeroAmount);
+    require!(shares <= lp_supply, ErrorCode::InsufficientLiquidity);
+
+    let amount_a = (shares as u128) * (reserve_a as u128) / (lp_supply as u128);
+    let amount_b = (shares as u128) * (reserve_b as u128) / (lp_supply as u128);
+    Ok((amount_a as u64, amount_b as u64))
 }
 
+/// splits `amount` proportionally to `weights` (out of FEE_WEIGHT_DENOMINATOR),
+/// the rounding remainder goes to the last recipient so nothing is left behind
+pub fn split_by_weights(amount: u64, weights: &[u16]) -> Result<Vec<u64>> {
+    require!(!weights.is_empty(), ErrorCode::InvalidFeeDistribution);
+    let total: u32 = weights.iter().map(|w| *w as u32).sum();
+    require!(total == FEE_WEIGHT_DENOMINATOR as u32, ErrorCode::InvalidFeeDistribution);
+
+    let mut shares = Vec::with_capacity(weights.len());
+    let mut distributed: u64 = 0;
+    for weight in &weights[..weights.len() - 1] {
+        let share = (amount as u128)
+            .checked_mul(*weight as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(FEE_WEIGHT_DENOMINATOR as u128)
+            .ok_or(ErrorCode::MathOverflow)? as u64;
+        distributed = distributed.checked_add(share).ok_or(ErrorCode::MathOverflow)?;
+        shares.push(share);
+    }
+    shares.push(amount.checked_sub(distributed).ok_or(ErrorCode::MathOverflow)?);
+    Ok(shares)
+}
+
+/// `verifier_state` is forwarded read-only to verifiers that keep their
+/// verifying key in a separate account, stateless verifiers pass None
 pub fn verify_zk_proof<'info>(
     verifier_program: &AccountInfo<'info>,
+    verifier_state: Option<&AccountInfo<'info>>,
     proof: &[u8],
     public_inputs: &[u8],
 ) -> Result<()> {
     let system_program_id = anchor_lang::solana_program::system_program::ID;
     require!(*verifier_program.key != system_program_id, ErrorCode::InvalidVerifier);
+    // the verifier would only reject it, so fail with a clear error instead
+    require!(!proof.is_empty() && !public_inputs.is_empty(), ErrorCode::EmptyProof);
 
-    msg!("Verifying ZK proof via CPI to: {}", verifier_program.key);
-    msg!("Proof Len: {}, Inputs Len: {}", proof.len(), public_inputs.len());
+    let mut accounts = Vec::new();
+    let mut account_infos = vec![verifier_program.clone()];
+    if let Some(state) = verifier_state {
+        require!(
+            *state.key != Pubkey::default() && !state.data_is_empty(),
+            ErrorCode::MissingVerifierState
+        );
+        accounts.push(AccountMeta::new_readonly(*state.key, false));
+        account_infos.push(state.clone());
+    }
+
+    debug_msg!("Verifying ZK proof via CPI to: {}", verifier_program.key);
+    debug_msg!("Proof Len: {}, Inputs Len: {}", proof.len(), public_inputs.len());
 
     // gnark-solana verifier expects instruction data = proof || public_witness
     let mut instruction_data = Vec::with_capacity(proof.len() + public_inputs.len());
@@ -60,16 +572,16 @@ pub fn verify_zk_proof<'info>(
 
     let ix = Instruction {
         program_id: *verifier_program.key,
-        accounts: vec![],
+        accounts,
         data: instruction_data,
     };
 
-    invoke(&ix, &[verifier_program.clone()]).map_err(|e| {
+    invoke(&ix, &account_infos).map_err(|e| {
         msg!("ZK proof verification failed: {:?}", e);
         ErrorCode::InvalidProof
     })?;
 
-    msg!("ZK proof verified successfully");
+    debug_msg!("ZK proof verified successfully");
     Ok(())
 }
 
@@ -79,33 +591,33 @@ mod tests {
 
     #[test]
     fn test_get_amount_out_basic() {
-        let result = get_amount_out(1_000_000_000, 10_000_000_000, 10_000_000_000).unwrap();
+        let result = get_amount_out(1_000_000_000, 10_000_000_000, 10_000_000_000, FEE_BPS).unwrap();
         assert!(result > 900_000_000 && result < 1_000_000_000);
     }
 
     #[test]
     fn test_get_amount_out_small() {
-        let result = get_amount_out(1_000, 10_000_000_000, 10_000_000_000).unwrap();
+        let result = get_amount_out(1_000, 10_000_000_000, 10_000_000_000, FEE_BPS).unwrap();
         assert_eq!(result, 996);
     }
 
     #[test]
     fn test_get_amount_out_zero_fails() {
-        let result = get_amount_out(0, 10_000, 10_000);
+        let result = get_amount_out(0, 10_000, 10_000, FEE_BPS);
         assert!(result.is_err());
     }
 
     #[test]
     fn test_get_amount_out_empty_reserve_fails() {
-        assert!(get_amount_out(1_000, 0, 10_000).is_err());
-        assert!(get_amount_out(1_000, 10_000, 0).is_err());
+        assert!(get_amount_out(1_000, 0, 10_000, FEE_BPS).is_err());
+        assert!(get_amount_out(1_000, 10_000, 0, FEE_BPS).is_err());
     }
 
     #[test]
     fn test_calculate_fee() {
-        assert_eq!(calculate_fee(1_000).unwrap(), 3);
-        assert_eq!(calculate_fee(1_000_000).unwrap(), 3000);
-        assert_eq!(calculate_fee(1).unwrap(), 0);
+        assert_eq!(calculate_fee(1_000, FEE_BPS).unwrap(), 3);
+        assert_eq!(calculate_fee(1_000_000, FEE_BPS).unwrap(), 3000);
+        assert_eq!(calculate_fee(1, FEE_BPS).unwrap(), 0);
     }
 
     #[test]
@@ -114,20 +626,524 @@ mod tests {
         let reserve_b: u64 = 10_000_000_000;
         let amount_in: u64 = 1_000_000_000;
 
-        let amount_out = get_amount_out(amount_in, reserve_a, reserve_b).unwrap();
+        let amount_out = get_amount_out(amount_in, reserve_a, reserve_b, FEE_BPS).unwrap();
         let new_reserve_a = reserve_a + amount_in;
         let new_reserve_b = reserve_b - amount_out;
 
         let old_k = (reserve_a as u128) * (reserve_b as u128);
         let new_k = (new_reserve_a as u128) * (new_reserve_b as u128);
         assert!(new_k >= old_k);
+        assert!(check_swap_invariant(old_k, new_k).is_ok());
+    }
+
+    #[test]
+    fn test_check_swap_invariant() {
+        assert!(check_swap_invariant(100, 100).is_ok());
+        assert!(check_swap_invariant(100, 101).is_ok());
+        assert!(check_swap_invariant(100, 99).is_err());
+
+        // one more unit out than get_amount_out allows shrinks k
+        let (reserve_a, reserve_b, amount_in) = (10_000u64, 10_000u64, 1_000u64);
+        let amount_out = get_amount_out(amount_in, reserve_a, reserve_b, 0).unwrap();
+        let old_k = reserve_a as u128 * reserve_b as u128;
+        let k = |out: u64| (reserve_a + amount_in) as u128 * (reserve_b - out) as u128;
+        assert!(check_swap_invariant(old_k, k(amount_out)).is_ok());
+        assert!(check_swap_invariant(old_k, k(amount_out + 1)).is_err());
     }
 
     #[test]
     fn test_price_impact_increases_with_size() {
         let reserve = 10_000_000_000u64;
-        let small_out = get_amount_out(100_000_000, reserve, reserve).unwrap();
-        let large_out = get_amount_out(5_000_000_000, reserve, reserve).unwrap();
+        let small_out = get_amount_out(100_000_000, reserve, reserve, FEE_BPS).unwrap();
+        let large_out = get_amount_out(5_000_000_000, reserve, reserve, FEE_BPS).unwrap();
         assert!((large_out as f64 / 5_000_000_000.0) < (small_out as f64 / 100_000_000.0));
     }
+
+    #[test]
+    fn test_quote_amount_b_matches_ratio() {
+        assert_eq!(quote_amount_b(1_000, 10_000, 20_000).unwrap(), 2_000);
+        assert_eq!(quote_amount_b(3, 10_000, 5_000).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_quote_amount_b_empty_pool() {
+        assert_eq!(quote_amount_b(1_000, 0, 0).unwrap(), 0);
+        assert!(quote_amount_b(0, 10_000, 10_000).is_err());
+    }
+
+    #[test]
+    fn test_split_by_weights() {
+        assert_eq!(split_by_weights(1_000, &[5_000, 5_000]).unwrap(), vec![500, 500]);
+        assert_eq!(split_by_weights(1_000, &[10_000]).unwrap(), vec![1_000]);
+        // remainder lands on the last recipient
+        assert_eq!(split_by_weights(10, &[3_333, 3_333, 3_334]).unwrap(), vec![3, 3, 4]);
+    }
+
+    #[test]
+    fn test_split_by_weights_rejects_bad_total() {
+        assert!(split_by_weights(1_000, &[5_000, 4_999]).is_err());
+        assert!(split_by_weights(1_000, &[]).is_err());
+    }
+
+    #[test]
+    fn test_liquidity_invariant() {
+        assert!(check_liquidity_invariant(0, 0, 0).is_ok());
+        assert!(check_liquidity_invariant(1_000, 2_000, 1_000).is_ok());
+        // reserves drained while shares remain
+        assert!(check_liquidity_invariant(0, 0, 1_000).is_err());
+        // shares burned while reserves remain
+        assert!(check_liquidity_invariant(1_000, 2_000, 0).is_err());
+        // one side drained on its own
+        assert!(check_liquidity_invariant(0, 2_000, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_value_in_token_b() {
+        // 1 a == 2 b
+        assert_eq!(value_in_token_b(1_000, 500, 2 * PRICE_PRECISION).unwrap(), 2_500);
+        // 1 a == 0.5 b
+        assert_eq!(value_in_token_b(1_000, 500, PRICE_PRECISION / 2).unwrap(), 1_000);
+        assert_eq!(value_in_token_b(0, 0, PRICE_PRECISION).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_execution_price() {
+        assert_eq!(execution_price(1_000, 2_000).unwrap(), 2 * PRICE_PRECISION);
+        assert_eq!(execution_price(2_000, 1_000).unwrap(), PRICE_PRECISION / 2);
+        assert!(execution_price(0, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_isqrt() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(1), 1);
+        assert_eq!(isqrt(15), 3);
+        assert_eq!(isqrt(16), 4);
+        assert_eq!(isqrt((u64::MAX as u128) * (u64::MAX as u128)), u64::MAX as u128);
+    }
+
+    fn assert_floor_sqrt(n: u128) {
+        let r = isqrt(n);
+        assert!(r * r <= n, "isqrt({}) = {} too large", n, r);
+        let next = (r + 1).checked_mul(r + 1);
+        assert!(next.is_none_or(|sq| n < sq), "isqrt({}) = {} too small", n, r);
+    }
+
+    #[test]
+    fn test_isqrt_bounds() {
+        for n in 0..=100_000u128 {
+            assert_floor_sqrt(n);
+        }
+        let roots = [2u128, 3, 1_000, 65_535, 1 << 32, u32::MAX as u128, 1 << 63, u64::MAX as u128];
+        for r in roots {
+            let square = r * r;
+            assert_eq!(isqrt(square), r);
+            assert_eq!(isqrt(square - 1), r - 1);
+            assert_eq!(isqrt(square + 1), r);
+            assert_floor_sqrt(square + 2 * r);
+        }
+        for shift in 0..128 {
+            assert_floor_sqrt(1u128 << shift);
+            assert_floor_sqrt((1u128 << shift) - 1);
+        }
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+        assert_floor_sqrt(u128::MAX);
+    }
+
+    #[test]
+    fn test_shares_to_mint_proportional() {
+        assert_eq!(shares_to_mint(1_000, 4_000, 0, 0, 0).unwrap(), 2_000);
+        assert_eq!(shares_to_mint(100, 400, 1_000, 4_000, 2_000).unwrap(), 200);
+        // unbalanced deposit gets the smaller side
+        assert_eq!(shares_to_mint(100, 800, 1_000, 4_000, 2_000).unwrap(), 200);
+        assert!(shares_to_mint(0, 400, 1_000, 4_000, 2_000).is_err());
+    }
+
+    #[test]
+    fn test_share_cap_boundary() {
+        let supply = MAX_LP_SUPPLY - 1_000;
+        let reserve = MAX_LP_SUPPLY - 1_000;
+        // exactly reaching the cap is allowed and exact
+        assert_eq!(shares_to_mint(1_000, 1_000, reserve, reserve, supply).unwrap(), 1_000);
+        // one share over is rejected
+        assert!(shares_to_mint(1_001, 1_001, reserve, reserve, supply).is_err());
+        // initial mint above the cap is rejected
+        assert!(shares_to_mint(u64::MAX, u64::MAX, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_shares_round_trip_near_cap() {
+        let supply = MAX_LP_SUPPLY;
+        let reserve_a = u64::MAX;
+        let reserve_b = MAX_LP_SUPPLY / 3;
+        let (out_a, out_b) = shares_to_withdraw(supply / 2, reserve_a, reserve_b, supply).unwrap();
+        assert_eq!(out_a, reserve_a / 2);
+        assert_eq!(out_b, reserve_b / 2);
+        let (all_a, all_b) = shares_to_withdraw(supply, reserve_a, reserve_b, supply).unwrap();
+        assert_eq!((all_a, all_b), (reserve_a, reserve_b));
+        assert!(shares_to_withdraw(supply + 1, reserve_a, reserve_b, supply).is_err());
+    }
+
+    #[test]
+    fn test_display_price_decimals() {
+        // 1_000 A (9 decimals) against 2_000 B (6 decimals): 1 A = 2 B
+        assert_eq!(display_price(1_000_000_000_000, 2_000_000_000, 9, 6).unwrap(), 2_000_000);
+        // and 1 B = 0.5 A
+        assert_eq!(display_price(2_000_000_000, 1_000_000_000_000, 6, 9).unwrap(), 500_000);
+        // equal decimals reduce to the raw ratio
+        assert_eq!(display_price(1_000, 3_000, 9, 9).unwrap(), 3_000_000);
+        assert!(display_price(0, 1_000, 9, 9).is_err());
+    }
+
+    #[test]
+    fn test_get_amount_out_fee_rates() {
+        let reserve = 10_000_000_000u64;
+        // no fee is the plain constant product quote
+        assert_eq!(get_amount_out(1_000, reserve, reserve, 0).unwrap(), 999);
+        assert_eq!(get_amount_out(1_000, reserve, reserve, 100).unwrap(), 989);
+        assert!(get_amount_out(1_000, reserve, reserve, 0).unwrap() > get_amount_out(1_000, reserve, reserve, 100).unwrap());
+        assert!(get_amount_out(1_000, reserve, reserve, MAX_FEE_BPS + 1).is_err());
+    }
+
+    #[test]
+    fn test_calculate_fee_rates() {
+        assert_eq!(calculate_fee(1_000_000, 0).unwrap(), 0);
+        assert_eq!(calculate_fee(1_000_000, 100).unwrap(), 10_000);
+    }
+
+    #[test]
+    fn test_get_amount_in_round_trip() {
+        let reserve_in = 10_000_000_000u64;
+        let reserve_out = 7_000_000_000u64;
+        for amount_out in [1u64, 996, 1_000_000, 3_500_000_000] {
+            let amount_in = get_amount_in(amount_out, reserve_in, reserve_out, FEE_BPS).unwrap();
+            let received = get_amount_out(amount_in, reserve_in, reserve_out, FEE_BPS).unwrap();
+            assert!(received >= amount_out);
+            // one unit less input must fall short, so the rounding is at most 1
+            if amount_in > 1 {
+                assert!(get_amount_out(amount_in - 1, reserve_in, reserve_out, FEE_BPS).unwrap() <= amount_out);
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_amount_in_rejects_draining_output() {
+        assert!(get_amount_in(10_000, 10_000, 10_000, FEE_BPS).is_err());
+        assert!(get_amount_in(0, 10_000, 10_000, FEE_BPS).is_err());
+    }
+
+    #[test]
+    fn test_price_impact_bps() {
+        let reserve = 10_000_000_000u64;
+        // receiving 999 for 1_000 at a 1:1 spot is 0.1%
+        assert_eq!(price_impact_bps(1_000, 999, reserve, reserve).unwrap(), 10);
+        // 10% of the pool without fee: out = 0.909.., impact ~9.1%
+        let out = get_amount_out(1_000_000_000, reserve, reserve, 0).unwrap();
+        assert_eq!(price_impact_bps(1_000_000_000, out, reserve, reserve).unwrap(), 910);
+        assert_eq!(price_impact_bps(1_000, 0, reserve, reserve).unwrap(), 10_000);
+        // no overflow at the extremes
+        assert!(price_impact_bps(u64::MAX, 1, u64::MAX, u64::MAX).unwrap() <= 10_000);
+    }
+
+    #[test]
+    fn test_quote_internal_swap_bound() {
+        let reserve = 10_000_000_000u64;
+        // ~0.3% fee + ~0.1% impact
+        assert!(quote_internal_swap(10_000_000, reserve, reserve, FEE_BPS, 50).is_ok());
+        assert!(quote_internal_swap(10_000_000, reserve, reserve, FEE_BPS, 30).is_err());
+        assert!(quote_internal_swap(1_000_000_000, reserve, reserve, FEE_BPS, 500).is_err());
+    }
+
+    #[test]
+    fn test_received_amount() {
+        assert_eq!(received_amount(1_000, 1_500, 500, false).unwrap(), 500);
+        // short delivery is only accepted from fee-bearing mints
+        assert!(received_amount(1_000, 1_495, 500, false).is_err());
+        assert_eq!(received_amount(1_000, 1_495, 500, true).unwrap(), 495);
+        assert!(received_amount(1_000, 1_600, 500, true).is_err());
+        assert!(received_amount(1_000, 900, 500, true).is_err());
+    }
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div(6, 7, 4).unwrap(), 10);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX).unwrap(), u128::MAX);
+        assert_eq!(mul_div(u128::MAX, 3, 6).unwrap(), u128::MAX / 2);
+        assert_eq!(mul_div(1 << 100, 1 << 100, 1 << 90).unwrap(), 1 << 110);
+        assert!(mul_div(u128::MAX, 2, 1).is_err());
+        assert!(mul_div(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_get_amount_out_large_reserves() {
+        let reserve = u64::MAX;
+        let amount_out = get_amount_out(u64::MAX / 2, reserve, reserve, FEE_BPS).unwrap();
+        // a third of the output reserve, less the fee
+        assert!(amount_out < reserve / 3 && amount_out > reserve / 3 - reserve / 300);
+
+        let amount_in = get_amount_in(reserve / 4, reserve, reserve, FEE_BPS).unwrap();
+        assert!(get_amount_out(amount_in, reserve, reserve, FEE_BPS).unwrap() >= reserve / 4);
+    }
+
+    #[test]
+    fn test_quote_detail() {
+        let detail = quote_detail(1_000, 1_000_000, 2_000_000, FEE_BPS).unwrap();
+        assert_eq!(detail.amount_out, get_amount_out(1_000, 1_000_000, 2_000_000, FEE_BPS).unwrap());
+        assert_eq!(detail.amount_out_no_fee, 1_998);
+        assert_eq!(detail.fee_amount, 3);
+        assert_eq!(detail.mid_price, 2 * PRICE_PRECISION);
+        assert!(detail.amount_out < detail.amount_out_no_fee);
+    }
+
+    #[test]
+    fn test_quote_detail_surfaces_swap_errors() {
+        let err = |r: Result<QuoteDetail>| r.unwrap_err();
+        assert_eq!(err(quote_detail(0, 1_000_000, 2_000_000, FEE_BPS)), ErrorCode::ZeroAmount.into());
+        assert_eq!(err(quote_detail(1_000, 0, 2_000_000, FEE_BPS)), ErrorCode::InsufficientLiquidity.into());
+        assert_eq!(err(quote_detail(1_000, 1_000_000, 0, FEE_BPS)), ErrorCode::InsufficientLiquidity.into());
+    }
+
+    #[test]
+    fn test_split_initial_shares() {
+        assert_eq!(split_initial_shares(5_000, true).unwrap(), (4_000, MINIMUM_LIQUIDITY));
+        assert_eq!(split_initial_shares(5_000, false).unwrap(), (5_000, 0));
+        assert!(split_initial_shares(MINIMUM_LIQUIDITY - 1, true).is_err());
+    }
+
+    #[test]
+    fn test_minimum_liquidity_blunts_donation_attack() {
+        let donation = 1_000_000_000;
+        let deposit = 1_000_000_000;
+
+        // without a lock: attacker keeps a 1-share supply, then inflates the reserves
+        let victim = shares_to_mint(deposit, deposit, 1 + donation, 1 + donation, 1).unwrap();
+        assert_eq!(victim, 0);
+
+        // with the lock the attacker's smallest first deposit still leaves
+        // MINIMUM_LIQUIDITY shares outstanding
+        let seed = MINIMUM_LIQUIDITY + 1;
+        let shares = shares_to_mint(seed, seed, 0, 0, 0).unwrap();
+        let (attacker, locked) = split_initial_shares(shares, true).unwrap();
+        assert_eq!((attacker, locked), (1, MINIMUM_LIQUIDITY));
+
+        let victim = shares_to_mint(deposit, deposit, seed + donation, seed + donation, shares).unwrap();
+        assert!(victim > 0);
+        // the victim's claim on the pool stays close to what they put in
+        let (out_a, _) = shares_to_withdraw(victim, seed + donation + deposit, seed + donation + deposit, shares + victim)
+            .unwrap();
+        assert!(out_a >= deposit - deposit / 1_000);
+    }
+
+    #[test]
+    fn test_spot_price_and_live_k() {
+        assert_eq!(spot_price(1_000, 2_000).unwrap(), 2 * PRICE_PRECISION);
+        assert_eq!(spot_price(4, 1).unwrap(), PRICE_PRECISION / 4);
+        assert_eq!(live_k(u64::MAX, u64::MAX).unwrap(), u64::MAX as u128 * u64::MAX as u128);
+        assert!(spot_price(0, 2_000).is_err());
+        assert!(spot_price(1_000, 0).is_err());
+        assert!(live_k(0, 1).is_err());
+    }
+
+    #[test]
+    fn test_effective_fee_bps() {
+        let reserve = 1_000_000_000;
+        // tiny swap pays the base fee
+        assert_eq!(effective_fee_bps(1_000, reserve, 30, 300), 30);
+        // a swap taking half the reserve pays the max
+        assert_eq!(effective_fee_bps(reserve / 2, reserve, 30, 300), 300);
+        assert_eq!(effective_fee_bps(reserve, reserve, 30, 300), 300);
+        // a quarter of the reserve lands halfway
+        assert_eq!(effective_fee_bps(reserve / 4, reserve, 30, 300), 165);
+        // dynamic mode off
+        assert_eq!(effective_fee_bps(reserve, reserve, 30, 0), 30);
+        assert_eq!(effective_fee_bps(1, 0, 30, 300), 300);
+    }
+
+    #[test]
+    fn test_get_amount_in_dynamic_covers_output() {
+        let (reserve_in, reserve_out) = (1_000_000u64, 1_000_000u64);
+        for amount_out in [1u64, 1_000, 100_000, 300_000] {
+            let amount_in = get_amount_in_dynamic(amount_out, reserve_in, reserve_out, 30, 300).unwrap();
+            let fee_bps = effective_fee_bps(amount_in, reserve_in, 30, 300);
+            assert!(get_amount_out(amount_in, reserve_in, reserve_out, fee_bps).unwrap() >= amount_out);
+        }
+        assert_eq!(
+            get_amount_in_dynamic(1_000, reserve_in, reserve_out, 30, 0).unwrap(),
+            get_amount_in(1_000, reserve_in, reserve_out, 30).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_check_pool_empty() {
+        assert!(check_pool_empty(0, 0).is_ok());
+        assert!(check_pool_empty(MINIMUM_LIQUIDITY, MINIMUM_LIQUIDITY).is_ok());
+        assert!(check_pool_empty(MINIMUM_LIQUIDITY + 1, MINIMUM_LIQUIDITY).is_err());
+    }
+
+    #[test]
+    fn test_single_sided_deposit_matches_swap_then_add() {
+        let (reserve_in, reserve_out, lp_supply) = (10_000_000_000u64, 20_000_000_000u64, 14_142_135_623u64);
+        let amount_in = 1_000_000_000u64;
+
+        let swap_amount = single_sided_swap_amount(amount_in, reserve_in, FEE_BPS).unwrap();
+        // under half: the swap moves the price against the rest of the deposit
+        assert!(swap_amount > amount_in * 45 / 100 && swap_amount < amount_in / 2);
+        let (swap_out, shares) =
+            single_sided_shares(amount_in, swap_amount, reserve_in, reserve_out, lp_supply, FEE_BPS).unwrap();
+
+        // manual: a plain swap followed by a plain two-sided add
+        let manual_out = get_amount_out(swap_amount, reserve_in, reserve_out, FEE_BPS).unwrap();
+        assert_eq!(manual_out, swap_out);
+        let manual_shares = shares_to_mint(
+            amount_in - swap_amount,
+            manual_out,
+            reserve_in + swap_amount,
+            reserve_out - manual_out,
+            lp_supply,
+        )
+        .unwrap();
+        assert_eq!(shares, manual_shares);
+
+        // both sides of the add are worth the same share count, within 0.01%
+        let from_in = (amount_in - swap_amount) as u128 * lp_supply as u128 / (reserve_in + swap_amount) as u128;
+        let from_out = swap_out as u128 * lp_supply as u128 / (reserve_out - swap_out) as u128;
+        assert!(from_in.abs_diff(from_out) * 10_000 <= from_in.max(from_out));
+
+        // and the fee-aware split beats swapping exactly half
+        let half = amount_in / 2;
+        let (_, half_shares) = single_sided_shares(amount_in, half, reserve_in, reserve_out, lp_supply, FEE_BPS).unwrap();
+        assert!(shares >= half_shares);
+    }
+
+    #[test]
+    fn test_single_sided_swap_amount_extremes() {
+        let big = u64::MAX / 2;
+        let swap = single_sided_swap_amount(big, big, MAX_FEE_BPS).unwrap();
+        assert!(swap < big / 2 + big / 100 && swap > big / 2 - big / 10);
+        assert_eq!(single_sided_swap_amount(2, 10_000, 0).unwrap(), 0);
+        assert!(single_sided_swap_amount(0, 10_000, FEE_BPS).is_err());
+        assert!(single_sided_swap_amount(1, 0, FEE_BPS).is_err());
+    }
+
+    #[test]
+    fn test_lp_position() {
+        let position = lp_position(250, 1_000, (4_000, 8_000), (40, 81)).unwrap();
+        assert_eq!(position, LpPosition { amount_a: 1_000, amount_b: 2_000, fees_a: 10, fees_b: 20 });
+        assert_eq!(shares_to_withdraw(250, 4_000, 8_000, 1_000).unwrap(), (position.amount_a, position.amount_b));
+
+        let whole = lp_position(1_000, 1_000, (4_000, 8_000), (40, 81)).unwrap();
+        assert_eq!((whole.fees_a, whole.fees_b), (40, 81));
+        assert_eq!(lp_position(0, 0, (0, 0), (0, 0)).unwrap().amount_a, 0);
+        assert!(lp_position(1_001, 1_000, (4_000, 8_000), (40, 81)).is_err());
+        assert!(lp_position(u64::MAX, u64::MAX, (u64::MAX, u64::MAX), (1, 1)).is_ok());
+    }
+
+    #[test]
+    fn test_check_price_impact() {
+        let reserve = 10_000_000_000u64;
+        let amount_in = 100_000_000u64;
+        let amount_out = get_amount_out(amount_in, reserve, reserve, FEE_BPS).unwrap();
+        // 1% of the pool at 0.3% fee moves the price by about 1.3%
+        let impact = price_impact_bps(amount_in, amount_out, reserve, reserve).unwrap();
+        assert!(impact > 120 && impact < 140);
+
+        assert!(check_price_impact(amount_in, amount_out, reserve, reserve, None).is_ok());
+        assert!(check_price_impact(amount_in, amount_out, reserve, reserve, Some(0)).is_err());
+        assert!(check_price_impact(1_000, 997, reserve, reserve, Some(0)).is_err());
+        assert!(check_price_impact(amount_in, amount_out, reserve, reserve, Some(100)).is_err());
+        assert!(check_price_impact(amount_in, amount_out, reserve, reserve, Some(impact - 1)).is_err());
+        assert!(check_price_impact(amount_in, amount_out, reserve, reserve, Some(impact)).is_ok());
+        assert!(check_price_impact(amount_in, amount_out, reserve, reserve, Some(500)).is_ok());
+    }
+
+    #[test]
+    fn test_check_swap_size() {
+        let reserve = 1_000_000u64;
+        // 10% cap
+        assert!(check_swap_size(100_000, reserve, 1000).is_ok());
+        assert!(check_swap_size(100_001, reserve, 1000).is_err());
+        // the cap rounds down
+        assert!(check_swap_size(1, 9_999, 1).is_err());
+        assert!(check_swap_size(1, 10_000, 1).is_ok());
+        // 0 turns the cap off
+        assert!(check_swap_size(u64::MAX, reserve, 0).is_ok());
+        assert!(check_swap_size(u64::MAX, u64::MAX, 10000).is_ok());
+    }
+
+    #[test]
+    fn test_verify_zk_proof_rejects_empty_input_before_cpi() {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = Vec::new();
+        let verifier = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, true, 0);
+
+        let empty = ErrorCode::EmptyProof.into();
+        assert_eq!(verify_zk_proof(&verifier, None, &[], &[1u8; 32]).unwrap_err(), empty);
+        assert_eq!(verify_zk_proof(&verifier, None, &[1u8; 256], &[]).unwrap_err(), empty);
+    }
+
+    #[test]
+    fn test_slippage_floor() {
+        let reserve = 10_000_000_000u64;
+        let expected = get_amount_out(100_000_000, reserve, reserve, FEE_BPS).unwrap();
+        // 1% under the quote, rounded down
+        assert_eq!(slippage_floor(expected, 100).unwrap(), expected * 99 / 100);
+        assert_eq!(slippage_floor(12_345, 100).unwrap(), 12_221);
+        assert_eq!(slippage_floor(expected, 0).unwrap(), expected);
+        assert_eq!(slippage_floor(u64::MAX, MAX_SLIPPAGE_BPS).unwrap(), u64::MAX / 2);
+        assert!(slippage_floor(expected, MAX_SLIPPAGE_BPS + 1).is_err());
+    }
+
+    #[test]
+    fn test_removing_all_liquidity_leaves_the_locked_floor() {
+        let (reserve_a, reserve_b) = (1_000_000u64, 4_000_000u64);
+        let shares = isqrt(reserve_a as u128 * reserve_b as u128) as u64;
+        let (user_shares, locked) = split_initial_shares(shares, true).unwrap();
+        let lp_supply = user_shares + locked;
+
+        // the depositor burns every share they hold
+        let (out_a, out_b) = shares_to_withdraw(user_shares, reserve_a, reserve_b, lp_supply).unwrap();
+        let (left_a, left_b, left_supply) = (reserve_a - out_a, reserve_b - out_b, lp_supply - user_shares);
+        assert_eq!(left_supply, MINIMUM_LIQUIDITY);
+        assert!(left_a > 0 && left_b > 0);
+        assert!(check_liquidity_invariant(left_a, left_b, left_supply).is_ok());
+        // the floor backs exactly its share of the seed, rounded in the pool's favour
+        assert_eq!(left_a, reserve_a - user_shares * reserve_a / lp_supply);
+
+        // draining either side past the floor breaks the invariant
+        assert!(check_liquidity_invariant(0, left_b, left_supply).is_err());
+        assert!(check_liquidity_invariant(left_a, 0, left_supply).is_err());
+        assert!(shares_to_withdraw(left_supply + 1, left_a, left_b, left_supply).is_err());
+    }
+
+    #[test]
+    fn test_drained_pool_reseeds_and_relocks() {
+        // only the locked floor is outstanding and both reserves are gone
+        let lp_supply = MINIMUM_LIQUIDITY;
+        assert!(is_seeding(0, 0, lp_supply));
+        assert!(!is_seeding(1_000, 4_000, lp_supply));
+
+        let minted = shares_to_mint(1_000_000, 4_000_000, 0, 0, lp_supply).unwrap();
+        assert_eq!(minted, 2_000_000);
+        let (shares, locked) = split_initial_shares(minted, is_seeding(0, 0, lp_supply)).unwrap();
+        assert_eq!((shares, locked), (2_000_000 - MINIMUM_LIQUIDITY, MINIMUM_LIQUIDITY));
+    }
+
+    #[test]
+    fn test_quote_liquidity_matches_the_deposit() {
+        let (reserve_a, reserve_b, lp_supply) = (10_000_000, 25_000_000, 15_000_000);
+        let (amount_b, shares) = quote_liquidity(400_000, reserve_a, reserve_b, lp_supply).unwrap();
+        assert_eq!(amount_b, 1_000_000);
+        // what add_liquidity mints for the quoted pair
+        assert_eq!(deposit_shares(400_000, amount_b, reserve_a, reserve_b, lp_supply).unwrap(), (shares, 0));
+        assert_eq!(shares, 600_000);
+
+        // no ratio to follow, the depositor seeds it
+        assert_eq!(quote_liquidity(400_000, 0, 0, 0).unwrap(), (0, 0));
+        assert_eq!(quote_liquidity(400_000, reserve_a, 0, lp_supply).unwrap(), (0, 0));
+        assert!(quote_liquidity(0, reserve_a, reserve_b, lp_supply).is_err());
+        // a seeding deposit locks MINIMUM_LIQUIDITY of its shares
+        assert_eq!(deposit_shares(4_000, 4_000, 0, 0, 0).unwrap(), (4_000 - MINIMUM_LIQUIDITY, MINIMUM_LIQUIDITY));
+    }
 }
@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use crate::errors::ErrorCode;
 
 pub mod roots;
 pub mod shielded;
@@ -14,10 +15,267 @@ pub struct Pool {
     pub authority: Pubkey,
     pub total_fees_a: u64,
     pub total_fees_b: u64,
+    pub created_at: i64,
+    pub last_swap_at: i64,
+    pub verifier_requires_state: bool,
+    pub test_mode: bool,
+    pub lp_mint: Pubkey,
+    pub token_a_decimals: u8,
+    pub token_b_decimals: u8,
+    pub fee_bps: u16,
+    pub paused: bool,
+    /// private swaps paying out less than this are rejected as dust, 0 disables
+    pub min_output: u64,
+    /// sum of token a priced in token b (PRICE_PRECISION scale) times seconds
+    /// held, sampled twice and differenced for a twap. wraps on overflow
+    pub price_cumulative_a: u128,
+    /// token b priced in token a, accumulated the same way
+    pub price_cumulative_b: u128,
+    pub last_update_ts: i64,
+    /// fees ever accrued per side. unlike total_fees_* these never drop on
+    /// collection, so two samples difference to the fee income in between
+    pub lifetime_fees_a: u128,
+    pub lifetime_fees_b: u128,
+    /// ceiling of the size-dependent fee, fee_bps is its base. at or below
+    /// fee_bps the pool charges a fixed fee_bps
+    pub max_fee_bps: u16,
+    /// set while a swap is in flight, see swap::lock_pool
+    pub locked: bool,
+    /// the only verifier program swaps on this pool may cpi into
+    pub expected_verifier: Pubkey,
+    /// largest share of the input reserve one swap may take in, in bps.
+    /// 0 means uncapped
+    pub max_swap_bps: u16,
+    /// lifetime swap input per side, public and private swaps alike
+    pub volume_a: u128,
+    pub volume_b: u128,
+    /// program notified after every swap, see swap::call_swap_hook
+    pub hook_program: Option<Pubkey>,
+    /// when set, a swap without the hook accounts fails instead of skipping it
+    pub hook_required: bool,
+    /// authority proposed by propose_authority, takes over once it signs
+    /// accept_authority. default when no transfer is pending
+    pub pending_authority: Pubkey,
 }
 
 impl Pool {
-    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 16 + 1 + 32 + 8 + 8;
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 16 + 1 + 32 + 8 + 8 + 8 + 8 + 1 + 1 + 32 + 1 + 1 + 2 + 1 + 8 + 16 + 16 + 8 + 16 + 16 + 2 + 1 + 32 + 2 + 16 + 16 + 33 + 1 + 32;
+
+    /// a pair has one canonical pool: the mints must differ and token_a_mint
+    /// must sort below token_b_mint byte-wise, so `["pool", a, b]` is unique
+    pub fn validate_mint_order(token_a_mint: &Pubkey, token_b_mint: &Pubkey) -> Result<()> {
+        require_keys_neq!(*token_a_mint, *token_b_mint, ErrorCode::IdenticalMints);
+        require!(token_a_mint.to_bytes() < token_b_mint.to_bytes(), ErrorCode::MintsNotSorted);
+        Ok(())
+    }
+
+    /// folds the pre-swap price into the twap accumulators for the time since
+    /// the last update, must run before the swap moves the reserves
+    pub fn accumulate_price(&mut self, now: i64) {
+        let elapsed = now.saturating_sub(self.last_update_ts);
+        if elapsed > 0 && self.token_a_reserve > 0 && self.token_b_reserve > 0 {
+            let reserve_a = self.token_a_reserve as u128;
+            let reserve_b = self.token_b_reserve as u128;
+            let price_a = reserve_b * crate::math::PRICE_PRECISION / reserve_a;
+            let price_b = reserve_a * crate::math::PRICE_PRECISION / reserve_b;
+            self.price_cumulative_a = self.price_cumulative_a.wrapping_add(price_a.wrapping_mul(elapsed as u128));
+            self.price_cumulative_b = self.price_cumulative_b.wrapping_add(price_b.wrapping_mul(elapsed as u128));
+        }
+        self.last_update_ts = now;
+    }
+
+    /// fees collect_fees would pay out right now. accrued fees sit inside the
+    /// tracked reserves rather than on top of them, so neither side can claim
+    /// more than its reserve holds
+    pub fn claimable_fees(&self) -> (u64, u64) {
+        (
+            self.total_fees_a.min(self.token_a_reserve),
+            self.total_fees_b.min(self.token_b_reserve),
+        )
+    }
+
+    /// fee charged on `amount_in` against `reserve_in`, see math::effective_fee_bps
+    pub fn swap_fee_bps(&self, amount_in: u64, reserve_in: u64) -> u16 {
+        crate::math::effective_fee_bps(amount_in, reserve_in, self.fee_bps, self.max_fee_bps)
+    }
+
+    /// the verifier is caller-supplied, so any program other than the one
+    /// stored on the pool could simply report success
+    pub fn check_verifier(&self, verifier_program: &Pubkey) -> Result<()> {
+        require_keys_eq!(*verifier_program, self.expected_verifier, ErrorCode::InvalidVerifier);
+        Ok(())
+    }
+
+    /// second half of a two-step authority transfer, only the proposed key
+    /// can take the pool over
+    pub fn accept_authority(&mut self, signer: &Pubkey) -> Result<()> {
+        require!(self.pending_authority != Pubkey::default(), ErrorCode::NotPendingAuthority);
+        require_keys_eq!(*signer, self.pending_authority, ErrorCode::NotPendingAuthority);
+        self.authority = self.pending_authority;
+        self.pending_authority = Pubkey::default();
+        Ok(())
+    }
+
+    /// books a swap fee on its input side, in the collectable and the
+    /// lifetime counters
+    pub fn accrue_fee(&mut self, is_a_in: bool, fee: u64) -> Result<()> {
+        if is_a_in {
+            self.total_fees_a = self.total_fees_a.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+            self.lifetime_fees_a = self.lifetime_fees_a.wrapping_add(fee as u128);
+        } else {
+            self.total_fees_b = self.total_fees_b.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+            self.lifetime_fees_b = self.lifetime_fees_b.wrapping_add(fee as u128);
+        }
+        Ok(())
+    }
+
+    /// moves the reserves by one swap and books its fee and volume on the
+    /// input side, returning the fee. shared by the public and the private
+    /// swap paths
+    pub fn book_swap(&mut self, is_a_in: bool, amount_in: u64, amount_out: u64, fee_bps: u16) -> Result<u64> {
+        let (reserve_in, reserve_out) = if is_a_in {
+            (&mut self.token_a_reserve, &mut self.token_b_reserve)
+        } else {
+            (&mut self.token_b_reserve, &mut self.token_a_reserve)
+        };
+        *reserve_in = reserve_in.checked_add(amount_in).ok_or(ErrorCode::MathOverflow)?;
+        *reserve_out = reserve_out.checked_sub(amount_out).ok_or(ErrorCode::MathOverflow)?;
+        let fee = crate::math::calculate_fee(amount_in, fee_bps)?;
+        self.accrue_fee(is_a_in, fee)?;
+        self.record_volume(is_a_in, amount_in)?;
+        Ok(fee)
+    }
+
+    /// adds a swap's `amount_in` to the volume of its input side
+    pub fn record_volume(&mut self, is_a_in: bool, amount_in: u64) -> Result<()> {
+        let volume = if is_a_in { &mut self.volume_a } else { &mut self.volume_b };
+        *volume = volume.checked_add(amount_in as u128).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    /// rejects a swap output under the pool's dust floor
+    pub fn check_min_output(&self, amount_out: u64) -> Result<()> {
+        require!(amount_out >= self.min_output, ErrorCode::OutputBelowMinimum);
+        Ok(())
+    }
+
+    /// re-derives the pool pda from the stored mints and bump, so a corrupted
+    /// bump fails here rather than as a cpi signer mismatch
+    pub fn validate_pda(&self, key: &Pubkey) -> Result<()> {
+        let derived = Pubkey::create_program_address(
+            &[
+                b"pool".as_ref(),
+                self.token_a_mint.as_ref(),
+                self.token_b_mint.as_ref(),
+                &[self.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| ErrorCode::InvalidPoolPda)?;
+        require_keys_eq!(derived, *key, ErrorCode::InvalidPoolPda);
+        Ok(())
+    }
+
+    /// reserves as (in, out) for a swap of `mint_in`, none if the pool doesn't trade it
+    pub fn reserves_for(&self, mint_in: &Pubkey) -> Option<(u64, u64)> {
+        if *mint_in == self.token_a_mint {
+            Some((self.token_a_reserve, self.token_b_reserve))
+        } else if *mint_in == self.token_b_mint {
+            Some((self.token_b_reserve, self.token_a_reserve))
+        } else {
+            None
+        }
+    }
+
+    /// the stored test_mode flag only takes effect in builds with the
+    /// `test-mode` feature, release builds can never skip verification
+    pub fn verifier_bypass_enabled(&self) -> bool {
+        cfg!(feature = "test-mode") && self.test_mode
+    }
+}
+
+pub const SWAP_DIRECTION_A_TO_B: u8 = 0;
+pub const SWAP_DIRECTION_B_TO_A: u8 = 1;
+/// first 8 bytes of sha256("global:on_swap"), leads the swap hook payload
+pub const SWAP_HOOK_DISCRIMINATOR: [u8; 8] = [201, 131, 123, 189, 167, 226, 124, 81];
+
+/// emitted on every swap, k is the live reserve product at the swap boundaries
+/// and the reserves are post-swap. private swaps report a default user
+#[event]
+pub struct SwapEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub direction: u8,
+    pub fee: u64,
+    pub new_reserve_a: u64,
+    pub new_reserve_b: u64,
+    pub k_before: u128,
+    pub k_after: u128,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReserveUpdateReason {
+    Swap,
+    PrivateSwap,
+    AddLiquidity,
+    FeeDistribution,
+    RemoveLiquidity,
+    FeeCollection,
+}
+
+/// emitted by every instruction that changes the tracked reserves
+#[event]
+pub struct ReserveUpdateEvent {
+    pub pool: Pubkey,
+    pub reserve_a_before: u64,
+    pub reserve_a_after: u64,
+    pub reserve_b_before: u64,
+    pub reserve_b_after: u64,
+    pub reason: ReserveUpdateReason,
+}
+
+pub const MAX_FEE_RECIPIENTS: usize = 4;
+pub const FEE_WEIGHT_DENOMINATOR: u16 = 10_000;
+
+/// splits collected pool fees between several recipients by weight,
+/// weights must sum to FEE_WEIGHT_DENOMINATOR
+#[account]
+pub struct FeeDistribution {
+    pub pool: Pubkey,
+    pub recipients: [Pubkey; MAX_FEE_RECIPIENTS],
+    pub weights: [u16; MAX_FEE_RECIPIENTS],
+    pub count: u8,
+    pub bump: u8,
+}
+
+impl FeeDistribution {
+    pub const LEN: usize = 8 + 32 + 32 * MAX_FEE_RECIPIENTS + 2 * MAX_FEE_RECIPIENTS + 1 + 1;
+}
+
+/// slots a committed proof stays settleable, roughly a minute
+pub const PROOF_COMMITMENT_TTL_SLOTS: u64 = 150;
+
+/// a verified proof and the swap terms it was committed with, consumed
+/// (closed) by settle_swap. one outstanding commitment per user per pool
+#[account]
+pub struct ProofCommitment {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_in: u64,
+    pub min_out: u64,
+    pub is_a_to_b: bool,
+    pub committed_slot: u64,
+    pub bump: u8,
+}
+
+impl ProofCommitment {
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 1 + 8 + 1;
+
+    pub fn is_expired(&self, slot: u64) -> bool {
+        slot > self.committed_slot.saturating_add(PROOF_COMMITMENT_TTL_SLOTS)
+    }
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -29,4 +287,197 @@ pub struct PoolInfo {
     pub k: u128,
     pub total_fees_a: u64,
     pub total_fees_b: u64,
+    pub volume_a: u128,
+    pub volume_b: u128,
+}
+
+/// lifetime fee counters stamped with when they were read
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct FeeSnapshot {
+    pub lifetime_fees_a: u128,
+    pub lifetime_fees_b: u128,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+/// what `lp_balance` shares redeem for right now. accrued fees sit inside
+/// the reserves, so `fees_a`/`fees_b` are the part of `amount_a`/`amount_b`
+/// that came from fees, not an amount on top
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, PartialEq)]
+pub struct LpPosition {
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub fees_a: u64,
+    pub fees_b: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct PoolActivity {
+    pub created_at: i64,
+    pub last_swap_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BestPoolQuote {
+    pub pool: Pubkey,
+    pub amount_out: u64,
+}
+
+/// a quote split into its price and fee parts. `fee_amount` is charged in
+/// the input token, `mid_price` is the spot price scaled by PRICE_PRECISION
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, PartialEq)]
+pub struct QuoteDetail {
+    pub amount_out: u64,
+    pub amount_out_no_fee: u64,
+    pub fee_amount: u64,
+    pub mid_price: u128,
+}
+
+/// return data of quote_add_liquidity. both are 0 on a pool with no ratio
+/// to follow, the depositor sets the initial ratio there
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, PartialEq)]
+pub struct LiquidityQuote {
+    pub amount_b: u64,
+    pub shares: u64,
+}
+
+/// return data of the exact-in swaps. the post-swap reserves let a bot
+/// chain its next trade without re-reading the pool account
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, PartialEq)]
+pub struct SwapResult {
+    pub amount_out: u64,
+    pub reserve_in: u64,
+    pub reserve_out: u64,
+}
+
+/// output floor, price impact bound and deadline of a zk_swap, serialized
+/// in the order zk_swap took them as separate arguments
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct SwapBounds {
+    pub min_out: u64,
+    pub accept_any_output: bool,
+    pub max_impact_bps: Option<u16>,
+    pub deadline: i64,
+}
+
+/// per-leg floors and price impact bound of swap_route_two, serialized in
+/// the order it took them as separate arguments
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RouteBounds {
+    pub min_mid_out: u64,
+    pub min_out: u64,
+    pub accept_any_output: bool,
+    pub max_impact_bps: Option<u16>,
+}
+
+/// addresses a zk_swap needs, derived from seeds only. the reserves are the
+/// canonical pool ATAs, a pool whose reserve was moved by migrate_reserve
+/// has to be read from chain instead. the history account and verifier
+/// program are deployment config and not derivable
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SwapAccounts {
+    pub pool: Pubkey,
+    pub lp_mint: Pubkey,
+    pub token_a_reserve: Pubkey,
+    pub token_b_reserve: Pubkey,
+    pub user_token_a: Pubkey,
+    pub user_token_b: Pubkey,
+    pub token_program: Pubkey,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::get_amount_out;
+
+    fn pool(token_a_reserve: u64, token_b_reserve: u64) -> Pool {
+        Pool {
+            token_a_mint: Pubkey::new_unique(),
+            token_b_mint: Pubkey::new_unique(),
+            token_a_reserve,
+            token_b_reserve,
+            k: token_a_reserve as u128 * token_b_reserve as u128,
+            bump: 0,
+            authority: Pubkey::default(),
+            total_fees_a: 0,
+            total_fees_b: 0,
+            created_at: 0,
+            last_swap_at: 0,
+            verifier_requires_state: false,
+            test_mode: false,
+            lp_mint: Pubkey::default(),
+            token_a_decimals: 0,
+            token_b_decimals: 0,
+            fee_bps: 30,
+            paused: false,
+            min_output: 0,
+            price_cumulative_a: 0,
+            price_cumulative_b: 0,
+            last_update_ts: 0,
+            lifetime_fees_a: 0,
+            lifetime_fees_b: 0,
+            max_fee_bps: 0,
+            locked: false,
+            expected_verifier: Pubkey::default(),
+            max_swap_bps: 0,
+            volume_a: 0,
+            volume_b: 0,
+            hook_program: None,
+            hook_required: false,
+            pending_authority: Pubkey::default(),
+        }
+    }
+
+    #[test]
+    fn test_accrue_fee_books_the_input_side() {
+        let mut p = pool(0, 0);
+        p.accrue_fee(true, 3_000).unwrap();
+        assert_eq!((p.total_fees_a, p.total_fees_b), (3_000, 0));
+        assert_eq!((p.lifetime_fees_a, p.lifetime_fees_b), (3_000, 0));
+
+        p.accrue_fee(false, 7).unwrap();
+        assert_eq!((p.total_fees_a, p.total_fees_b), (3_000, 7));
+        p.total_fees_b = u64::MAX;
+        assert!(p.accrue_fee(false, 1).is_err());
+    }
+
+    #[test]
+    fn test_private_swap_books_its_fee() {
+        // the amounts swap_private computes for a 1m a -> b swap
+        let mut p = pool(100_000_000, 100_000_000);
+        let amount_in = 1_000_000;
+        let fee_bps = p.swap_fee_bps(amount_in, p.token_a_reserve);
+        let amount_out = get_amount_out(amount_in, p.token_a_reserve, p.token_b_reserve, fee_bps).unwrap();
+
+        assert_eq!(p.book_swap(true, amount_in, amount_out, fee_bps).unwrap(), 3_000);
+        // 30 bps of the input, on the input side only
+        assert_eq!((p.total_fees_a, p.total_fees_b), (3_000, 0));
+        assert_eq!((p.token_a_reserve, p.token_b_reserve), (101_000_000, 100_000_000 - amount_out));
+        assert_eq!((p.volume_a, p.volume_b), (amount_in as u128, 0));
+
+        p.book_swap(false, amount_in, amount_out, fee_bps).unwrap();
+        assert_eq!((p.total_fees_a, p.total_fees_b), (3_000, 3_000));
+        assert!(p.book_swap(true, 1, u64::MAX, fee_bps).is_err());
+    }
+
+    #[test]
+    fn test_accept_authority() {
+        let mut p = pool(0, 0);
+        let old_authority = p.authority;
+        let new_authority = Pubkey::new_unique();
+
+        // nothing proposed yet
+        assert!(p.accept_authority(&new_authority).is_err());
+
+        p.pending_authority = new_authority;
+        assert_eq!(p.accept_authority(&Pubkey::new_unique()).unwrap_err(), ErrorCode::NotPendingAuthority.into());
+        assert!(p.accept_authority(&old_authority).is_err());
+        assert_eq!(p.authority, old_authority);
+
+        p.accept_authority(&new_authority).unwrap();
+        assert_eq!(p.authority, new_authority);
+        assert_eq!(p.pending_authority, Pubkey::default());
+        // the slot is cleared, a second accept can't replay it
+        assert!(p.accept_authority(&new_authority).is_err());
+    }
 }
@@ -1,32 +1,262 @@
 use anchor_lang::prelude::*;
 
+use crate::errors::ErrorCode;
+
+/// capacity a fresh history starts with
+pub const DEFAULT_ROOT_HISTORY_LEN: usize = 100;
+/// ceiling for resize_root_history, the whole ring is deserialized onto the
+/// 32k heap and a resize briefly holds two copies
+pub const MAX_ROOT_HISTORY_LEN: usize = 256;
+/// size of the fixed-array layout deployed before the ring could grow:
+/// discriminator, roots: [u8; 3200], current_index, authority
+pub const LEGACY_ROOT_HISTORY_LEN: usize = 8 + 32 * DEFAULT_ROOT_HISTORY_LEN + 8 + 32;
+
 #[account]
 pub struct StateRootHistory {
-    pub roots: [u8; 3200], // stores last 100 roots (32 bytes each)
     pub current_index: u64,
     pub authority: Pubkey,
+    /// ring buffer, the `i`-th appended root sits at `i % roots.len()`
+    pub roots: Vec<[u8; 32]>,
+    /// per slot, current_index right after the slot's root was appended.
+    /// 0 for a slot never written, so its zeroed root never matches
+    pub written_at: Vec<u64>,
 }
 
 impl StateRootHistory {
-    pub const LEN: usize = 8 + 3200 + 8 + 32;
+    pub const LEN: usize = Self::space(DEFAULT_ROOT_HISTORY_LEN);
+
+    pub const fn space(capacity: usize) -> usize {
+        8 + 8 + 32 + 4 + 32 * capacity + 4 + 8 * capacity
+    }
+
+    /// rebuilds a history from the bytes of a legacy account after its
+    /// discriminator. the legacy ring kept no write markers, so every slot
+    /// among the last 100 appends is marked as written by its append
+    pub fn from_legacy(body: &[u8]) -> Result<Self> {
+        let roots_len = 32 * DEFAULT_ROOT_HISTORY_LEN;
+        require!(body.len() == LEGACY_ROOT_HISTORY_LEN - 8, ErrorCode::InvalidMigration);
+        let (roots_bytes, rest) = body.split_at(roots_len);
+        let current_index = u64::from_le_bytes(rest[..8].try_into().unwrap());
+        let authority = Pubkey::new_from_array(rest[8..40].try_into().unwrap());
+
+        let roots = roots_bytes.chunks_exact(32).map(|root| root.try_into().unwrap()).collect();
+        let mut written_at = vec![0u64; DEFAULT_ROOT_HISTORY_LEN];
+        for index in current_index.saturating_sub(DEFAULT_ROOT_HISTORY_LEN as u64)..current_index {
+            written_at[(index % DEFAULT_ROOT_HISTORY_LEN as u64) as usize] = index + 1;
+        }
+        Ok(Self { current_index, authority, roots, written_at })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.roots.len()
+    }
 
     pub fn append(&mut self, new_root: [u8; 32]) {
-        let idx = (self.current_index as usize) % 100;
-        let start = idx * 32;
-        let end = start + 32;
-        
-        self.roots[start..end].copy_from_slice(&new_root);
+        let idx = (self.current_index as usize) % self.capacity();
+        self.roots[idx] = new_root;
         self.current_index += 1;
+        self.written_at[idx] = self.current_index;
+    }
+
+    /// whether `slot` holds one of the last `capacity` appended roots
+    fn slot_is_live(&self, slot: usize) -> bool {
+        let written_at = self.written_at[slot];
+        written_at > 0 && self.current_index - written_at < self.capacity() as u64
+    }
+
+    /// single-slot lookup for a root the caller knows was appended as the
+    /// `root_index`-th root, only valid while it's within the last `capacity` appends
+    pub fn contains_at(&self, root: &[u8; 32], root_index: u64) -> bool {
+        let capacity = self.capacity() as u64;
+        if root_index >= self.current_index || self.current_index - root_index > capacity {
+            return false;
+        }
+        let slot = (root_index % capacity) as usize;
+        self.written_at[slot] == root_index + 1 && &self.roots[slot] == root
+    }
+
+    /// checks the state root a swap proof commits to as its first public
+    /// input. inputs too short to hold one fail with InvalidProof
+    pub fn check_proof_root(&self, public_inputs: &[u8]) -> Result<()> {
+        let claimed_root: [u8; 32] = public_inputs
+            .get(0..32)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(ErrorCode::InvalidProof)?;
+        require!(self.contains(&claimed_root), ErrorCode::InvalidStateRoot);
+        Ok(())
     }
 
     pub fn contains(&self, root: &[u8; 32]) -> bool {
-        for i in 0..100 {
-            let start = i * 32;
-            let end = start + 32;
-            if &self.roots[start..end] == root {
-                return true;
-            }
-        }
-        false
+        self.roots
+            .iter()
+            .enumerate()
+            .any(|(slot, stored)| stored == root && self.slot_is_live(slot))
+    }
+
+    /// grows the ring to `new_len` slots. live roots move from
+    /// `i % old_len` to `i % new_len` so `contains_at` and the next append
+    /// keep working off the unchanged `current_index`
+    pub fn resize(&mut self, new_len: usize) -> Result<()> {
+        let old_len = self.capacity();
+        require!(new_len > old_len, ErrorCode::InvalidHistoryLength);
+        require!(new_len <= MAX_ROOT_HISTORY_LEN, ErrorCode::InvalidHistoryLength);
+
+        let mut roots = vec![[0u8; 32]; new_len];
+        let mut written_at = vec![0u64; new_len];
+        let first_live = self.current_index.saturating_sub(old_len as u64);
+        for index in first_live..self.current_index {
+            let (old_slot, new_slot) = ((index % old_len as u64) as usize, (index % new_len as u64) as usize);
+            roots[new_slot] = self.roots[old_slot];
+            written_at[new_slot] = self.written_at[old_slot];
+        }
+        self.roots = roots;
+        self.written_at = written_at;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(capacity: usize) -> StateRootHistory {
+        StateRootHistory {
+            current_index: 0,
+            authority: Pubkey::default(),
+            roots: vec![[0u8; 32]; capacity],
+            written_at: vec![0; capacity],
+        }
+    }
+
+    fn root(i: u64) -> [u8; 32] {
+        let mut root = [0u8; 32];
+        root[..8].copy_from_slice(&(i + 1).to_le_bytes());
+        root
+    }
+
+    #[test]
+    fn test_resize_keeps_wrapped_roots() {
+        let mut history = history(DEFAULT_ROOT_HISTORY_LEN);
+        // wrap the ring so the live window straddles the end of the buffer
+        for i in 0..250 {
+            history.append(root(i));
+        }
+        history.resize(160).unwrap();
+        assert_eq!(history.capacity(), 160);
+        assert_eq!(history.current_index, 250);
+
+        for i in 150..250 {
+            assert!(history.contains(&root(i)));
+            assert!(history.contains_at(&root(i), i));
+        }
+        assert!(!history.contains(&root(149)));
+
+        // the next 60 appends fill the new slots without evicting a live root
+        for i in 250..310 {
+            history.append(root(i));
+        }
+        for i in 150..310 {
+            assert!(history.contains_at(&root(i), i));
+        }
+        history.append(root(310));
+        assert!(!history.contains(&root(150)));
+    }
+
+    #[test]
+    fn test_resize_before_wrapping() {
+        let mut history = history(DEFAULT_ROOT_HISTORY_LEN);
+        for i in 0..40 {
+            history.append(root(i));
+        }
+        history.resize(MAX_ROOT_HISTORY_LEN).unwrap();
+        for i in 0..40 {
+            assert!(history.contains_at(&root(i), i));
+        }
+    }
+
+    #[test]
+    fn test_resize_rejects_shrinking() {
+        let mut history = history(DEFAULT_ROOT_HISTORY_LEN);
+        assert!(history.resize(DEFAULT_ROOT_HISTORY_LEN).is_err());
+        assert!(history.resize(50).is_err());
+        assert!(history.resize(MAX_ROOT_HISTORY_LEN + 1).is_err());
+    }
+
+    #[test]
+    fn test_len_matches_serialized_size() {
+        let history = history(DEFAULT_ROOT_HISTORY_LEN);
+        assert_eq!(StateRootHistory::LEN, 8 + history.try_to_vec().unwrap().len());
+    }
+
+    #[test]
+    fn test_contains_only_matches_live_slots() {
+        let mut history = history(DEFAULT_ROOT_HISTORY_LEN);
+        assert!(!history.contains(&[0; 32]));
+        for i in 0..40 {
+            history.append(root(i));
+        }
+        // the slots a resize adds are zeroed but were never written
+        history.resize(160).unwrap();
+        assert!(!history.contains(&[0; 32]));
+
+        // wrap the whole ring, the first root is evicted
+        for i in 40..200 {
+            history.append(root(i));
+        }
+        assert!(!history.contains(&root(0)));
+        assert!(!history.contains_at(&root(0), 0));
+        assert!(history.contains(&root(40)));
+
+        // a root left behind in a slot whose write fell out of the window is expired
+        // root 40 sits in slot 40 of the 160-slot ring
+        let slot = 40;
+        history.roots[slot] = root(0);
+        history.written_at[slot] = 1;
+        assert!(!history.contains(&root(0)));
+    }
+
+    #[test]
+    fn test_check_proof_root() {
+        let mut history = history(DEFAULT_ROOT_HISTORY_LEN);
+        history.append(root(0));
+        let mut inputs = root(0).to_vec();
+        inputs.extend_from_slice(&[9; 64]);
+        assert!(history.check_proof_root(&inputs).is_ok());
+        assert_eq!(history.check_proof_root(&root(1)).unwrap_err(), ErrorCode::InvalidStateRoot.into());
+        // too short to hold a root, rejected rather than panicking
+        assert_eq!(history.check_proof_root(&inputs[..31]).unwrap_err(), ErrorCode::InvalidProof.into());
+        assert_eq!(history.check_proof_root(&[]).unwrap_err(), ErrorCode::InvalidProof.into());
+    }
+
+    fn legacy_body(appends: u64, authority: Pubkey) -> Vec<u8> {
+        let mut roots = vec![0u8; 32 * DEFAULT_ROOT_HISTORY_LEN];
+        for i in 0..appends {
+            let start = (i as usize % DEFAULT_ROOT_HISTORY_LEN) * 32;
+            roots[start..start + 32].copy_from_slice(&root(i));
+        }
+        let mut body = roots;
+        body.extend_from_slice(&appends.to_le_bytes());
+        body.extend_from_slice(authority.as_ref());
+        body
+    }
+
+    #[test]
+    fn test_from_legacy_keeps_the_live_window() {
+        let authority = Pubkey::new_unique();
+        let history = StateRootHistory::from_legacy(&legacy_body(250, authority)).unwrap();
+        assert_eq!(history.current_index, 250);
+        assert_eq!(history.authority, authority);
+        assert_eq!(history.capacity(), DEFAULT_ROOT_HISTORY_LEN);
+        for i in 150..250 {
+            assert!(history.contains_at(&root(i), i));
+        }
+        assert!(!history.contains(&root(149)));
+
+        // a barely used legacy ring must not turn its zeroed slots live
+        let history = StateRootHistory::from_legacy(&legacy_body(3, authority)).unwrap();
+        assert!(history.contains(&root(2)));
+        assert!(!history.contains(&[0u8; 32]));
+
+        assert!(StateRootHistory::from_legacy(&legacy_body(3, authority)[1..]).is_err());
     }
 }
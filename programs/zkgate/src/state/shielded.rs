@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token::spl_token::native_mint;
+use crate::errors::ErrorCode;
 
 /// shielded pool state for a single spl token mint
 /// root updates are managed by an off-chain sequencer or relayer authority
@@ -11,10 +13,103 @@ pub struct ShieldedPool {
     pub root_history: Pubkey,
     pub next_index: u64,
     pub bump: u8,
+    pub last_rooted_index: u64,
+    pub paused: bool,
+    /// seconds between request_emergency and the first emergency_withdraw
+    pub emergency_delay: i64,
+    /// when request_emergency was called, 0 while no request is pending
+    pub emergency_requested_at: i64,
+    /// verifier program spend proofs against this pool must go through
+    pub expected_verifier: Pubkey,
+    /// only accept proofs against the newest root instead of any root in history
+    pub strict_root: bool,
+    /// set by initialize_shielded_tree. from then on every leaf goes through
+    /// the on-chain tree, so instructions that add one must pass it
+    pub has_tree: bool,
 }
 
 impl ShieldedPool {
-    pub const LEN: usize = 8 + 32 + 32 + 32 + 32 + 32 + 8 + 1;
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 32 + 32 + 8 + 1 + 8 + 1 + 8 + 8 + 32 + 1 + 1;
+    /// size of the original layout, mint through bump, what
+    /// migrate_shielded_pool grows from
+    pub const LEGACY_LEN: usize = 8 + 32 + 32 + 32 + 32 + 32 + 8 + 1;
+
+    /// a leaf taken without the tree would desync next_index from it and
+    /// every later insert would fail with InvalidLeafIndex
+    pub fn check_tree_passed(&self, passed: bool) -> Result<()> {
+        require!(!self.has_tree || passed, ErrorCode::MerkleTreeRequired);
+        Ok(())
+    }
+
+    /// withdraw_shielded_sol unwraps to lamports, which only a wsol pool holds
+    pub fn check_native_mint(&self) -> Result<()> {
+        require_keys_eq!(self.mint, native_mint::ID, ErrorCode::NotNativeMint);
+        Ok(())
+    }
+
+    pub fn check_verifier(&self, verifier_program: &Pubkey) -> Result<()> {
+        require_keys_eq!(*verifier_program, self.expected_verifier, ErrorCode::InvalidVerifier);
+        Ok(())
+    }
+
+    /// the authority can only sweep the vault once a recorded emergency
+    /// request is older than the delay, depositors get that long to exit
+    pub fn check_emergency_unlocked(&self, now: i64) -> Result<()> {
+        require!(self.emergency_requested_at > 0, ErrorCode::TimelockActive);
+        let unlocks_at = self
+            .emergency_requested_at
+            .checked_add(self.emergency_delay)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(now >= unlocks_at, ErrorCode::TimelockActive);
+        Ok(())
+    }
+
+    /// a request unlocks a single emergency_withdraw, further sweeps need a
+    /// new request and a new delay
+    pub fn consume_emergency(&mut self, now: i64) -> Result<()> {
+        self.check_emergency_unlocked(now)?;
+        self.emergency_requested_at = 0;
+        Ok(())
+    }
+
+    pub fn cancel_emergency(&mut self) -> Result<()> {
+        require!(self.emergency_requested_at > 0, ErrorCode::NoEmergencyRequest);
+        self.emergency_requested_at = 0;
+        Ok(())
+    }
+
+    /// re-derives the shielded pool pda from the stored mint and bump
+    pub fn validate_pda(&self, key: &Pubkey) -> Result<()> {
+        let derived = Pubkey::create_program_address(
+            &[b"shielded_pool".as_ref(), self.mint.as_ref(), &[self.bump]],
+            &crate::ID,
+        )
+        .map_err(|_| ErrorCode::InvalidPoolPda)?;
+        require_keys_eq!(derived, *key, ErrorCode::InvalidPoolPda);
+        Ok(())
+    }
+}
+
+pub const MAX_ALLOWED_MINTS: usize = 32;
+/// emergency delay a new shielded pool starts with, and the floor for
+/// set_emergency_delay
+pub const DEFAULT_EMERGENCY_DELAY: i64 = 7 * 24 * 60 * 60;
+pub const MIN_EMERGENCY_DELAY: i64 = 24 * 60 * 60;
+/// commitments per deposit_shielded_batch, bounded by the compute budget of
+/// the on-chain tree inserts
+pub const MAX_DEPOSIT_BATCH: usize = 16;
+
+/// global allow-list of mints that may back a shielded pool. created once
+/// by the program's upgrade authority, every shielded pool must be on it
+#[account]
+pub struct ShieldedMintAllowlist {
+    pub authority: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl ShieldedMintAllowlist {
+    pub const LEN: usize = 8 + 32 + 4 + 32 * MAX_ALLOWED_MINTS + 1;
 }
 
 /// marks a nullifier as spent
@@ -27,6 +122,63 @@ impl Nullifier {
     pub const LEN: usize = 8 + 1;
 }
 
+/// preimage of a plain commitment, see crypto::compute_commitment
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CommitmentOpening {
+    pub secret: [u8; 32],
+    pub recipient: Pubkey,
+}
+
+/// return value of get_shielded_pool_info
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ShieldedPoolInfo {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub current_root: [u8; 32],
+    pub next_index: u64,
+    pub root_history: Pubkey,
+    /// deposits and spends have to pass the merkle_tree account when set
+    pub has_tree: bool,
+}
+
+/// one note spent by withdraw_shielded, or by withdraw_shielded_batch with
+/// the same public inputs per note
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ShieldedSpend {
+    pub amount: u64,
+    pub nullifier_hash: [u8; 32],
+    pub change_commitment: [u8; 32],
+    pub proof: Vec<u8>,
+    pub public_inputs: Vec<u8>,
+}
+
+/// the arguments of swap_private. the fields keep the order they had as
+/// separate instruction arguments, so the encoding is unchanged
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SwapPrivateArgs {
+    pub proof: Vec<u8>,
+    pub public_inputs: Vec<u8>,
+    pub amount_in: u64,
+    pub min_out: u64,
+    pub accept_any_output: bool,
+    pub deadline: i64,
+    pub is_a_to_b: bool,
+    pub nullifier_hash: [u8; 32],
+    pub change_commitment: [u8; 32],
+    /// hint into the root history, which is scanned in full without one
+    pub root_index: Option<u64>,
+}
+
+/// inclusion proof of a leaf in the on-chain tree, see crypto::verify_merkle_path.
+/// the leaf isn't bound to the spend proof, it only pins the root
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MerkleProof {
+    pub leaf: [u8; 32],
+    pub path_elements: Vec<[u8; 32]>,
+    pub path_indices: u64,
+}
+
 #[event]
 pub struct DepositEvent {
     pub pool: Pubkey,
@@ -35,8 +187,91 @@ pub struct DepositEvent {
     pub amount: u64,
 }
 
+/// emitted by swap_private. deliberately carries no nullifier, root or
+/// recipient so the swap itself can't be linked back to the spent note,
+/// NullifierSpent only repeats what the proof's public inputs already show
+#[event]
+pub struct PrivateSwapEvent {
+    pub pool: Pubkey,
+    pub is_a_to_b: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub reserve_a_after: u64,
+    pub reserve_b_after: u64,
+}
+
+/// emitted by withdraw_shielded, withdraw_shielded_sol and swap_private once
+/// the nullifier flips to spent, so indexers can keep a spent set without
+/// scanning nullifier pdas. `recipient` is the account the proof binds
+#[event]
+pub struct NullifierSpent {
+    pub pool: Pubkey,
+    pub nullifier_hash: [u8; 32],
+    pub amount: u64,
+    pub recipient: Pubkey,
+}
+
+/// emitted when the authority resyncs current_root from the root history
+#[event]
+pub struct CurrentRootSynced {
+    pub pool: Pubkey,
+    pub old_root: [u8; 32],
+    pub new_root: [u8; 32],
+}
+
+/// emitted by request_emergency, depositors should withdraw before `unlocks_at`
+#[event]
+pub struct EmergencyRequested {
+    pub pool: Pubkey,
+    pub requested_at: i64,
+    pub unlocks_at: i64,
+}
+
+/// emitted by cancel_emergency, the vault can no longer be swept on the old request
+#[event]
+pub struct EmergencyCancelled {
+    pub pool: Pubkey,
+    pub requested_at: i64,
+}
+
+/// emitted when the authority overrides next_index during recovery
+#[event]
+pub struct NextIndexReset {
+    pub pool: Pubkey,
+    pub old_index: u64,
+    pub new_index: u64,
+}
+
+/// slots a cached proof verification stays usable by swap_private
+pub const VERIFIED_PROOF_TTL_SLOTS: u64 = 150;
+
+/// marks that a proof for exactly these public inputs already passed the
+/// verifier, so a retried swap_private can skip the cpi. the root and
+/// nullifier checks still run on every attempt, the cache only saves the
+/// verification cost and can't be used to spend a note twice
+#[account]
+pub struct VerifiedProof {
+    pub verifier_program: Pubkey,
+    pub inputs_hash: [u8; 32],
+    pub verified_slot: u64,
+    pub bump: u8,
+}
+
+impl VerifiedProof {
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 1;
+
+    pub fn is_usable(&self, verifier_program: &Pubkey, inputs_hash: &[u8; 32], slot: u64) -> bool {
+        self.verifier_program == *verifier_program
+            && self.inputs_hash == *inputs_hash
+            && slot <= self.verified_slot.saturating_add(VERIFIED_PROOF_TTL_SLOTS)
+    }
+}
+
 pub const ROOT_HISTORY_SIZE: usize = 32;
 pub const ROOT_HISTORY_BYTES: usize = ROOT_HISTORY_SIZE * 32;
+/// upper bound on roots a batch spend may reference, each costs a history
+/// scan and a verifier cpi
+pub const MAX_PROOF_ROOTS: usize = 4;
 
 #[account(zero_copy)]
 #[repr(C)]
@@ -44,10 +279,26 @@ pub struct ShieldedRootHistory {
     pub current_index: u64,
     pub pool: Pubkey,
     pub roots: [u8; ROOT_HISTORY_BYTES],
+    /// per slot, current_index right after the slot's root was appended.
+    /// 0 for a slot never written, so its zeroed bytes never match
+    pub written_at: [u64; ROOT_HISTORY_SIZE],
 }
 
 impl ShieldedRootHistory {
-    pub const LEN: usize = 8 + 8 + 32 + ROOT_HISTORY_BYTES;
+    pub const LEN: usize = 8 + 8 + 32 + ROOT_HISTORY_BYTES + 8 * ROOT_HISTORY_SIZE;
+    /// size before written_at was appended, what
+    /// migrate_shielded_root_history grows from
+    pub const LEGACY_LEN: usize = Self::LEN - 8 * ROOT_HISTORY_SIZE;
+
+    /// marks every slot among the last ROOT_HISTORY_SIZE appends as written
+    /// by its append. a legacy history kept no markers, so its live window
+    /// is rebuilt from current_index alone
+    pub fn mark_live_window(&mut self) {
+        let size = ROOT_HISTORY_SIZE as u64;
+        for index in self.current_index.saturating_sub(size)..self.current_index {
+            self.written_at[(index % size) as usize] = index + 1;
+        }
+    }
 
     pub fn append_root(&mut self, new_root: [u8; 32]) {
         let idx = (self.current_index as usize) % ROOT_HISTORY_SIZE;
@@ -55,16 +306,369 @@ impl ShieldedRootHistory {
         let end = start + 32;
         self.roots[start..end].copy_from_slice(&new_root);
         self.current_index += 1;
+        self.written_at[idx] = self.current_index;
+    }
+
+    /// whether `slot` holds one of the last ROOT_HISTORY_SIZE appended roots
+    fn slot_is_live(&self, slot: usize) -> bool {
+        let written_at = self.written_at[slot];
+        written_at > 0 && self.current_index - written_at < ROOT_HISTORY_SIZE as u64
+    }
+
+    /// most recently appended root, none before the first append
+    pub fn latest_root(&self) -> Option<[u8; 32]> {
+        if self.current_index == 0 {
+            return None;
+        }
+        let idx = ((self.current_index - 1) as usize) % ROOT_HISTORY_SIZE;
+        let mut root = [0u8; 32];
+        root.copy_from_slice(&self.roots[idx * 32..idx * 32 + 32]);
+        Some(root)
     }
 
     pub fn contains_root(&self, root: &[u8; 32]) -> bool {
         for i in 0..ROOT_HISTORY_SIZE {
             let start = i * 32;
             let end = start + 32;
-            if &self.roots[start..end] == root {
+            if self.slot_is_live(i) && &self.roots[start..end] == root {
                 return true;
             }
         }
         false
     }
+
+    /// single-slot lookup for a root the caller knows was appended as the
+    /// `root_index`-th root, only valid while it's within the last ROOT_HISTORY_SIZE appends
+    pub fn contains_root_at(&self, root: &[u8; 32], root_index: u64) -> bool {
+        if root_index >= self.current_index || self.current_index - root_index > ROOT_HISTORY_SIZE as u64 {
+            return false;
+        }
+        let slot = root_index as usize % ROOT_HISTORY_SIZE;
+        if self.written_at[slot] != root_index + 1 {
+            return false;
+        }
+        let start = slot * 32;
+        &self.roots[start..start + 32] == root
+    }
+
+    /// checks a proof's root, trying the hinted slot before scanning the whole history
+    pub fn check_root(&self, root: &[u8; 32], root_index: Option<u64>) -> Result<()> {
+        if root_index.is_some_and(|index| self.contains_root_at(root, index)) {
+            return Ok(());
+        }
+        self.contains_roots(&[*root])
+    }
+
+    /// strict pools trade liveness for freshness, a proof built before the
+    /// latest append has to be regenerated
+    pub fn check_pool_root(&self, root: &[u8; 32], root_index: Option<u64>, strict_root: bool) -> Result<()> {
+        if strict_root {
+            require!(self.latest_root() == Some(*root), ErrorCode::InvalidStateRoot);
+            return Ok(());
+        }
+        self.check_root(root, root_index)
+    }
+
+    /// checks every root referenced by a (possibly batched) proof against the history
+    pub fn contains_roots(&self, roots: &[[u8; 32]]) -> Result<()> {
+        require!(!roots.is_empty(), ErrorCode::InvalidStateRoot);
+        require!(roots.len() <= MAX_PROOF_ROOTS, ErrorCode::TooManyRoots);
+        for root in roots {
+            require!(self.contains_root(root), ErrorCode::InvalidStateRoot);
+        }
+        Ok(())
+    }
+}
+
+pub const SHIELDED_TREE_DEPTH: usize = 20;
+
+/// keccak256(left || right), the node hash of ShieldedMerkleTree
+pub fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    solana_keccak_hasher::hashv(&[left, right]).to_bytes()
+}
+
+/// append-only incremental merkle tree over deposit commitments, so roots
+/// are derived on-chain instead of trusted from update_shielded_root.
+/// only the right-most filled node per level is kept, an insert is
+/// SHIELDED_TREE_DEPTH hashes.
+/// the shielded_spend circuit currently hashes with poseidon at depth 32,
+/// proofs against these keccak roots need a circuit built for this tree
+#[account(zero_copy)]
+#[repr(C)]
+pub struct ShieldedMerkleTree {
+    pub pool: Pubkey,
+    pub filled_subtrees: [[u8; 32]; SHIELDED_TREE_DEPTH],
+    pub zeros: [[u8; 32]; SHIELDED_TREE_DEPTH],
+    pub root: [u8; 32],
+    pub next_index: u64,
+}
+
+impl ShieldedMerkleTree {
+    pub const LEN: usize = 8 + 32 + 32 * SHIELDED_TREE_DEPTH * 2 + 32 + 8;
+    pub const CAPACITY: u64 = 1 << SHIELDED_TREE_DEPTH;
+
+    pub fn init(&mut self, pool: Pubkey) {
+        self.pool = pool;
+        let mut zero = [0u8; 32];
+        for level in 0..SHIELDED_TREE_DEPTH {
+            self.zeros[level] = zero;
+            self.filled_subtrees[level] = zero;
+            zero = hash_pair(&zero, &zero);
+        }
+        self.root = zero;
+        self.next_index = 0;
+    }
+
+    /// inserts the next leaf and returns the new root
+    pub fn insert(&mut self, leaf: [u8; 32]) -> Result<[u8; 32]> {
+        require!(self.next_index < Self::CAPACITY, ErrorCode::TreeFull);
+
+        let mut index = self.next_index;
+        let mut node = leaf;
+        for level in 0..SHIELDED_TREE_DEPTH {
+            node = if index.is_multiple_of(2) {
+                self.filled_subtrees[level] = node;
+                hash_pair(&node, &self.zeros[level])
+            } else {
+                hash_pair(&self.filled_subtrees[level], &node)
+            };
+            index /= 2;
+        }
+
+        self.root = node;
+        self.next_index += 1;
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_root() {
+        let mut history: ShieldedRootHistory = bytemuck::Zeroable::zeroed();
+        assert_eq!(history.latest_root(), None);
+        for i in 0..(ROOT_HISTORY_SIZE as u8 + 3) {
+            history.append_root([i + 1; 32]);
+            assert_eq!(history.latest_root(), Some([i + 1; 32]));
+        }
+    }
+
+    #[test]
+    fn test_contains_roots() {
+        let mut history: ShieldedRootHistory = bytemuck::Zeroable::zeroed();
+        history.append_root([1u8; 32]);
+        history.append_root([2u8; 32]);
+
+        assert!(history.contains_roots(&[[1u8; 32], [2u8; 32]]).is_ok());
+        assert!(history.contains_roots(&[[1u8; 32], [3u8; 32]]).is_err());
+        assert!(history.contains_roots(&[]).is_err());
+        assert!(history.contains_roots(&[[1u8; 32]; MAX_PROOF_ROOTS + 1]).is_err());
+    }
+
+    // reference root hashing level by level, the empty right side of each
+    // level is the all-zero subtree of that height
+    fn full_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+        let mut level: Vec<[u8; 32]> = leaves.to_vec();
+        let mut zero = [0u8; 32];
+        for _ in 0..SHIELDED_TREE_DEPTH {
+            if level.is_empty() {
+                level.push(zero);
+            }
+            if level.len() % 2 == 1 {
+                level.push(zero);
+            }
+            level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+            zero = hash_pair(&zero, &zero);
+        }
+        level[0]
+    }
+
+    #[test]
+    fn test_merkle_tree_matches_full_tree() {
+        let mut tree: ShieldedMerkleTree = bytemuck::Zeroable::zeroed();
+        tree.init(Pubkey::default());
+        assert_eq!(tree.root, full_root(&[]));
+
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        for (i, leaf) in leaves.iter().enumerate() {
+            let root = tree.insert(*leaf).unwrap();
+            assert_eq!(root, full_root(&leaves[..=i]));
+        }
+        assert_eq!(tree.next_index, 3);
+    }
+
+    #[test]
+    fn test_merkle_tree_full() {
+        let mut tree: ShieldedMerkleTree = bytemuck::Zeroable::zeroed();
+        tree.init(Pubkey::default());
+        tree.next_index = ShieldedMerkleTree::CAPACITY;
+        assert!(tree.insert([1u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_contains_root_at() {
+        let mut history: ShieldedRootHistory = bytemuck::Zeroable::zeroed();
+        assert!(!history.contains_root_at(&[0; 32], 0));
+        for i in 0..(ROOT_HISTORY_SIZE as u64 + 5) {
+            history.append_root([i as u8 + 1; 32]);
+        }
+        let last = ROOT_HISTORY_SIZE as u64 + 4;
+        assert!(history.contains_root_at(&[last as u8 + 1; 32], last));
+        assert!(history.contains_root_at(&[6; 32], 5));
+        // overwritten by a later append
+        assert!(!history.contains_root_at(&[5; 32], 4));
+        assert!(!history.contains_root_at(&[6; 32], 6));
+        assert!(!history.contains_root_at(&[6; 32], last + 1));
+
+        assert!(history.check_root(&[6; 32], Some(5)).is_ok());
+        // a wrong hint falls back to the scan
+        assert!(history.check_root(&[6; 32], Some(9)).is_ok());
+        assert!(history.check_root(&[6; 32], None).is_ok());
+        assert!(history.check_root(&[5; 32], Some(4)).is_err());
+    }
+
+    #[test]
+    fn test_emergency_timelock() {
+        let mut pool = ShieldedPool {
+            mint: Pubkey::default(),
+            vault: Pubkey::default(),
+            authority: Pubkey::default(),
+            current_root: [0; 32],
+            root_history: Pubkey::default(),
+            next_index: 0,
+            bump: 0,
+            last_rooted_index: 0,
+            paused: false,
+            emergency_delay: DEFAULT_EMERGENCY_DELAY,
+            emergency_requested_at: 0,
+            expected_verifier: Pubkey::default(),
+            strict_root: false,
+            has_tree: false,
+        };
+        assert!(pool.check_emergency_unlocked(i64::MAX).is_err());
+
+        pool.emergency_requested_at = 1_000;
+        assert!(pool.check_emergency_unlocked(1_000).is_err());
+        assert!(pool.check_emergency_unlocked(1_000 + DEFAULT_EMERGENCY_DELAY - 1).is_err());
+        assert!(pool.check_emergency_unlocked(1_000 + DEFAULT_EMERGENCY_DELAY).is_ok());
+    }
+
+    #[test]
+    fn test_check_pool_root_strict() {
+        let mut history: ShieldedRootHistory = bytemuck::Zeroable::zeroed();
+        assert!(history.check_pool_root(&[0; 32], None, true).is_err());
+        // the zeroed slots of an empty history hold no root
+        assert!(history.check_pool_root(&[0; 32], None, false).is_err());
+
+        history.append_root([1; 32]);
+        history.append_root([2; 32]);
+        assert!(history.check_pool_root(&[2; 32], None, true).is_ok());
+        assert!(history.check_pool_root(&[1; 32], Some(0), true).is_err());
+        assert!(history.check_pool_root(&[1; 32], Some(0), false).is_ok());
+        assert!(history.check_pool_root(&[3; 32], None, false).is_err());
+    }
+
+    #[test]
+    fn test_contains_root_only_matches_live_slots() {
+        let mut history: ShieldedRootHistory = bytemuck::Zeroable::zeroed();
+        // unwritten slots are zeroed, a zero root must not match them
+        assert!(!history.contains_root(&[0; 32]));
+        history.append_root([1; 32]);
+        assert!(!history.contains_root(&[0; 32]));
+        assert!(history.contains_root(&[1; 32]));
+
+        // wrap the whole ring, every slot now holds a later root
+        for i in 0..ROOT_HISTORY_SIZE as u8 {
+            history.append_root([i + 2; 32]);
+        }
+        assert!(!history.contains_root(&[1; 32]));
+        assert!(!history.contains_root_at(&[1; 32], 0));
+        assert!(history.contains_root(&[2; 32]));
+        assert!(history.check_root(&[1; 32], Some(0)).is_err());
+
+        // a root that lingers in a slot whose write left the window is expired
+        history.roots[..32].copy_from_slice(&[1; 32]);
+        history.written_at[0] = 1;
+        assert!(!history.contains_root(&[1; 32]));
+        assert!(!history.contains_root_at(&[1; 32], 0));
+        assert_eq!(ShieldedRootHistory::LEN, 8 + std::mem::size_of::<ShieldedRootHistory>());
+    }
+
+    #[test]
+    fn test_check_tree_passed() {
+        let mut pool = shielded_pool();
+        assert!(pool.check_tree_passed(false).is_ok());
+
+        pool.has_tree = true;
+        assert!(pool.check_tree_passed(false).is_err());
+        assert!(pool.check_tree_passed(true).is_ok());
+    }
+
+    fn shielded_pool() -> ShieldedPool {
+        ShieldedPool {
+            mint: Pubkey::default(),
+            vault: Pubkey::default(),
+            authority: Pubkey::default(),
+            current_root: [0; 32],
+            root_history: Pubkey::default(),
+            next_index: 0,
+            bump: 0,
+            last_rooted_index: 0,
+            paused: false,
+            emergency_delay: DEFAULT_EMERGENCY_DELAY,
+            emergency_requested_at: 0,
+            expected_verifier: Pubkey::default(),
+            strict_root: false,
+            has_tree: false,
+        }
+    }
+
+    #[test]
+    fn test_emergency_withdraw_consumes_the_request() {
+        let mut pool = shielded_pool();
+        pool.emergency_requested_at = 1_000;
+        let unlocked = 1_000 + DEFAULT_EMERGENCY_DELAY;
+        assert!(pool.consume_emergency(unlocked - 1).is_err());
+        assert_eq!(pool.emergency_requested_at, 1_000);
+
+        pool.consume_emergency(unlocked).unwrap();
+        assert_eq!(pool.emergency_requested_at, 0);
+        // a second sweep needs a fresh request
+        assert!(pool.consume_emergency(unlocked).is_err());
+    }
+
+    #[test]
+    fn test_cancel_emergency() {
+        let mut pool = shielded_pool();
+        assert!(pool.cancel_emergency().is_err());
+
+        pool.emergency_requested_at = 1_000;
+        pool.cancel_emergency().unwrap();
+        assert_eq!(pool.emergency_requested_at, 0);
+        assert!(pool.check_emergency_unlocked(i64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_mark_live_window() {
+        let mut history: ShieldedRootHistory = bytemuck::Zeroable::zeroed();
+        // a legacy history three appends in, its other slots still zeroed
+        history.current_index = 3;
+        for i in 0..3 {
+            history.roots[i * 32..i * 32 + 32].copy_from_slice(&[i as u8 + 1; 32]);
+        }
+        assert!(!history.contains_root(&[1u8; 32]));
+
+        history.mark_live_window();
+        assert_eq!(history.written_at[..4], [1, 2, 3, 0]);
+        assert!(history.contains_root(&[1u8; 32]) && history.contains_root(&[3u8; 32]));
+        assert!(!history.contains_root(&[0u8; 32]));
+
+        // past a full ring every slot is live and holds its latest append
+        history.current_index = ROOT_HISTORY_SIZE as u64 + 5;
+        history.mark_live_window();
+        assert_eq!(history.written_at[4], ROOT_HISTORY_SIZE as u64 + 5);
+        assert_eq!(history.written_at[5], 6);
+    }
 }
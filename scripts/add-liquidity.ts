@@ -115,6 +115,8 @@ async function main() {
                 userTokenB: deployerTokenB,
                 tokenAReserve: tokenAReserve,
                 tokenBReserve: tokenBReserve,
+                tokenAMint: tokenAMint,
+                tokenBMint: tokenBMint,
                 user: deployerKeypair.publicKey,
                 tokenProgram: new PublicKey('TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA'),
             })
@@ -29,6 +29,13 @@ import * as path from 'path';
 const DEVNET_URL = process.env.RPC_URL || 'https://api.devnet.solana.com';
 const INITIAL_LIQUIDITY_A = 10_000_000_000; // 10,000 tokens (9 decimals)
 const INITIAL_LIQUIDITY_B = 10_000_000_000; // 10,000 tokens (9 decimals)
+// pools only accept proofs routed through these verifiers; if they are not
+// deployed yet, point the pools at them later with setExpectedVerifier /
+// setShieldedVerifier
+const VERIFIER_PROGRAM_ID = new PublicKey(process.env.VERIFIER_PROGRAM_ID || SystemProgram.programId);
+const SHIELDED_VERIFIER_PROGRAM_ID = new PublicKey(
+  process.env.SHIELDED_VERIFIER_PROGRAM_ID || SystemProgram.programId
+);
 
 async function main() {
   console.log('========================================');
@@ -60,7 +67,7 @@ async function main() {
 
   // Step 1: Create Token A
   console.log('Step 1: Creating Token A...');
-  const tokenAMint = await createMint(
+  let tokenAMint = await createMint(
     connection,
     deployer,
     deployer.publicKey,
@@ -71,7 +78,7 @@ async function main() {
 
   // Step 2: Create Token B
   console.log('\nStep 2: Creating Token B...');
-  const tokenBMint = await createMint(
+  let tokenBMint = await createMint(
     connection,
     deployer,
     deployer.publicKey,
@@ -80,6 +87,11 @@ async function main() {
   );
   console.log(`Token B Mint: ${tokenBMint.toBase58()}`);
 
+  // pools only accept the canonical order, token_a_mint < token_b_mint
+  if (Buffer.compare(tokenAMint.toBuffer(), tokenBMint.toBuffer()) > 0) {
+    [tokenAMint, tokenBMint] = [tokenBMint, tokenAMint];
+  }
+
   // Step 3: Create ATAs for deployer
   console.log('\nStep 3: Creating token accounts...');
   const deployerTokenA = await getOrCreateAssociatedTokenAccount(
@@ -211,7 +223,11 @@ async function main() {
     await programConnected.methods
       .createPool(
         new BN(INITIAL_LIQUIDITY_A),
-        new BN(INITIAL_LIQUIDITY_B)
+        new BN(INITIAL_LIQUIDITY_B),
+        false,
+        30,
+        VERIFIER_PROGRAM_ID,
+        0
       )
       .accounts({
         pool: poolPda,
@@ -228,8 +244,39 @@ async function main() {
     console.log('Pool initialized!');
 
     console.log('\nStep 7b: Initializing shielded pools...');
+    // shielded pools can only be created for mints on the global allow-list,
+    // which the program's upgrade authority creates once
+    const [mintAllowlist] = PublicKey.findProgramAddressSync(
+      [Buffer.from('shielded_mint_allowlist')],
+      programId
+    );
+    if (!(await connection.getAccountInfo(mintAllowlist))) {
+      const [programData] = PublicKey.findProgramAddressSync(
+        [programId.toBuffer()],
+        new PublicKey('BPFLoaderUpgradeab1e11111111111111111111111')
+      );
+      await programConnected.methods
+        .initializeShieldedMintAllowlist()
+        .accounts({
+          mintAllowlist,
+          authority: deployer.publicKey,
+          program: programId,
+          programData,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([deployer])
+        .rpc();
+    }
+    for (const mint of [tokenAMint, tokenBMint]) {
+      await programConnected.methods
+        .addShieldedMint(mint)
+        .accounts({ mintAllowlist, authority: deployer.publicKey })
+        .signers([deployer])
+        .rpc();
+    }
+
     await programConnected.methods
-      .initializeShieldedPool()
+      .initializeShieldedPool(SHIELDED_VERIFIER_PROGRAM_ID)
       .accounts({
         shieldedPool: shieldedPoolA,
         mint: tokenAMint,
@@ -238,6 +285,7 @@ async function main() {
         systemProgram: SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
         associatedTokenProgram: new PublicKey('ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL'),
+        mintAllowlist,
       })
       .signers([deployer])
       .rpc();
@@ -254,7 +302,7 @@ async function main() {
       .rpc();
 
     await programConnected.methods
-      .initializeShieldedPool()
+      .initializeShieldedPool(SHIELDED_VERIFIER_PROGRAM_ID)
       .accounts({
         shieldedPool: shieldedPoolB,
         mint: tokenBMint,
@@ -263,6 +311,7 @@ async function main() {
         systemProgram: SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
         associatedTokenProgram: new PublicKey('ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL'),
+        mintAllowlist,
       })
       .signers([deployer])
       .rpc();
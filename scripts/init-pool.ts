@@ -44,6 +44,9 @@ const IDL = {
             args: [
                 { name: 'initA', type: 'u64' },
                 { name: 'initB', type: 'u64' },
+                { name: 'rejectFreezableMints', type: 'bool' },
+                { name: 'feeBps', type: 'u16' },
+                { name: 'expectedVerifier', type: 'pubkey' },
             ],
         },
     ],
@@ -81,6 +84,10 @@ async function main() {
     // Initial liquidity amounts (10 tokens each)
     const initA = new BN(10 * 1e9);
     const initB = new BN(10 * 1e9);
+    // swaps are rejected unless they cpi into this verifier
+    const verifierProgram = new PublicKey(
+        process.env.VERIFIER_PROGRAM_ID || '11111111111111111111111111111111'
+    );
 
     console.log('Creating pool with:');
     console.log('  Token A Mint:', tokenAMint.toBase58());
@@ -90,7 +97,7 @@ async function main() {
 
     try {
         const tx = await (program.methods as any)
-            .createPool(initA, initB)
+            .createPool(initA, initB, false, 30, verifierProgram, 0)
             .accounts({
                 pool: poolPda,
                 tokenAMint: tokenAMint,
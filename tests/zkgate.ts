@@ -8,9 +8,13 @@ import {
   Connection,
 } from "@solana/web3.js";
 import {
+  createAccount,
   createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
   getOrCreateAssociatedTokenAccount,
   mintTo,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
   TOKEN_PROGRAM_ID,
 } from "@solana/spl-token";
 import { expect } from "chai";
@@ -49,6 +53,11 @@ function getProvider(): anchor.AnchorProvider {
   }
 }
 
+/** unix-time swap deadline a couple of minutes out */
+function deadline(): anchor.BN {
+  return new anchor.BN(Math.floor(Date.now() / 1000) + 120);
+}
+
 describe("zkgate", () => {
   // Configure the client to use the local cluster or devnet
   const provider = getProvider();
@@ -66,11 +75,16 @@ describe("zkgate", () => {
   let userTokenB: PublicKey;
   let poolTokenAReserve: PublicKey;
   let poolTokenBReserve: PublicKey;
+  let lpMint: PublicKey;
+  let lpLock: PublicKey;
+  let userLp: PublicKey;
   let historyKp = Keypair.generate(); // New: History Account
 
   // Initial liquidity
   const INIT_A = new anchor.BN(10_000_000_000); // 10,000 tokens
   const INIT_B = new anchor.BN(10_000_000_000);
+  // the placeholder verifier the swap tests pass, pinned on the pool
+  const EXPECTED_VERIFIER = SystemProgram.programId;
 
   before(async () => {
     console.log("Setting up test environment...");
@@ -95,6 +109,11 @@ describe("zkgate", () => {
     );
     console.log("Token B Mint:", tokenBMint.toBase58());
 
+    // create_pool only accepts the canonical order, token_a_mint < token_b_mint
+    if (Buffer.compare(tokenAMint.toBuffer(), tokenBMint.toBuffer()) > 0) {
+      [tokenAMint, tokenBMint] = [tokenBMint, tokenAMint];
+    }
+
     // Compute Pool PDA
     [poolPda, poolBump] = PublicKey.findProgramAddressSync(
       [Buffer.from("pool"), tokenAMint.toBuffer(), tokenBMint.toBuffer()],
@@ -102,6 +121,15 @@ describe("zkgate", () => {
     );
     console.log("Pool PDA:", poolPda.toBase58());
 
+    [lpMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_mint"), poolPda.toBuffer()],
+      program.programId
+    );
+    [lpLock] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_lock"), poolPda.toBuffer()],
+      program.programId
+    );
+
     // Create user token accounts
     const userTokenAAccount = await getOrCreateAssociatedTokenAccount(
       provider.connection,
@@ -173,15 +201,64 @@ describe("zkgate", () => {
     console.log("History initialized");
   });
 
+  const poolAccountsFor = (mintA: PublicKey, mintB: PublicKey) => {
+    const [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer()],
+      program.programId
+    );
+    const [poolLpMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_mint"), pool.toBuffer()],
+      program.programId
+    );
+    const [poolLpLock] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_lock"), pool.toBuffer()],
+      program.programId
+    );
+    return {
+      pool,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      lpMint: poolLpMint,
+      lpLock: poolLpLock,
+      user: wallet.publicKey,
+      systemProgram: SystemProgram.programId,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+  };
+
+  it("Rejects a pool over identical mints", async () => {
+    try {
+      await program.methods
+        .createPool(INIT_A, INIT_B, false, 30, EXPECTED_VERIFIER, 0)
+        .accounts(poolAccountsFor(tokenAMint, tokenAMint))
+        .rpc();
+      expect.fail("Should have failed with IdenticalMints");
+    } catch (error) {
+      expect(error.message).to.include("IdenticalMints");
+    }
+  });
+
+  it("Rejects unsorted pool mints", async () => {
+    try {
+      await program.methods
+        .createPool(INIT_A, INIT_B, false, 30, EXPECTED_VERIFIER, 0)
+        .accounts(poolAccountsFor(tokenBMint, tokenAMint))
+        .rpc();
+      expect.fail("Should have failed with MintsNotSorted");
+    } catch (error) {
+      expect(error.message).to.include("MintsNotSorted");
+    }
+  });
+
   it("Creates a pool", async () => {
     const tx = await program.methods
-      .createPool(INIT_A, INIT_B)
+      .createPool(INIT_A, INIT_B, false, 30, EXPECTED_VERIFIER, 0)
       .accounts({
         pool: poolPda,
         tokenAMint: tokenAMint,
         tokenBMint: tokenBMint,
-        tokenAReserve: poolTokenAReserve,
-        tokenBReserve: poolTokenBReserve,
+        lpMint: lpMint,
+        lpLock: lpLock,
         user: wallet.publicKey,
         systemProgram: SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
@@ -197,10 +274,76 @@ describe("zkgate", () => {
     expect(pool.tokenAReserve.toNumber()).to.equal(INIT_A.toNumber());
     expect(pool.tokenBReserve.toNumber()).to.equal(INIT_B.toNumber());
     expect(pool.bump).to.equal(poolBump);
+    expect(pool.lpMint.toBase58()).to.equal(lpMint.toBase58());
+    expect(pool.expectedVerifier.toBase58()).to.equal(EXPECTED_VERIFIER.toBase58());
+
+    userLp = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        wallet.payer,
+        lpMint,
+        wallet.publicKey
+      )
+    ).address;
 
     console.log("Pool created successfully");
   });
 
+  it("Creates a pool together with its reserve accounts", async () => {
+    let [mintA, mintB] = [
+      await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6),
+      await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6),
+    ];
+    if (Buffer.compare(mintA.toBuffer(), mintB.toBuffer()) > 0) {
+      [mintA, mintB] = [mintB, mintA];
+    }
+    const [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer()],
+      program.programId
+    );
+    const [poolLpMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_mint"), pool.toBuffer()],
+      program.programId
+    );
+    const [poolLpLock] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_lock"), pool.toBuffer()],
+      program.programId
+    );
+    const reserveA = getAssociatedTokenAddressSync(mintA, pool, true);
+    const reserveB = getAssociatedTokenAddressSync(mintB, pool, true);
+
+    await program.methods
+      .createPoolWithReserves(true, 30, EXPECTED_VERIFIER, 0)
+      .accounts({
+        pool,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        lpMint: poolLpMint,
+        lpLock: poolLpLock,
+        tokenAReserve: reserveA,
+        tokenBReserve: reserveB,
+        user: wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    for (const [reserve, mint] of [
+      [reserveA, mintA],
+      [reserveB, mintB],
+    ]) {
+      const account = await getAccount(provider.connection, reserve);
+      expect(account.owner.toBase58()).to.equal(pool.toBase58());
+      expect(account.mint.toBase58()).to.equal(mint.toBase58());
+      expect(account.amount === BigInt(0)).to.equal(true);
+    }
+    const state = await program.account.pool.fetch(pool);
+    expect(state.tokenAReserve.toNumber()).to.equal(0);
+    expect(state.tokenBReserve.toNumber()).to.equal(0);
+    expect(state.authority.toBase58()).to.equal(wallet.publicKey.toBase58());
+  });
+
   it("Adds liquidity", async () => {
     const addA = new anchor.BN(1_000_000_000); // 1,000 tokens
     const addB = new anchor.BN(1_000_000_000);
@@ -213,6 +356,11 @@ describe("zkgate", () => {
         userTokenB: userTokenB,
         tokenAReserve: poolTokenAReserve,
         tokenBReserve: poolTokenBReserve,
+        tokenAMint: tokenAMint,
+        tokenBMint: tokenBMint,
+        lpMint: lpMint,
+        userLp: userLp,
+        lpLock: lpLock,
         user: wallet.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
@@ -229,9 +377,217 @@ describe("zkgate", () => {
       INIT_B.toNumber() + addB.toNumber()
     );
 
+    const lpBalance = await provider.connection.getTokenAccountBalance(userLp);
+    expect(Number(lpBalance.value.amount)).to.be.greaterThan(0);
+
     console.log("Liquidity added successfully");
   });
 
+  it("Rejects a liquidity deposit with a zero side", async () => {
+    for (const [addA, addB] of [
+      [new anchor.BN(0), new anchor.BN(1_000_000)],
+      [new anchor.BN(1_000_000), new anchor.BN(0)],
+    ]) {
+      try {
+        await program.methods
+          .addLiquidity(addA, addB)
+          .accounts({
+            pool: poolPda,
+            userTokenA: userTokenA,
+            userTokenB: userTokenB,
+            tokenAReserve: poolTokenAReserve,
+            tokenBReserve: poolTokenBReserve,
+            tokenAMint: tokenAMint,
+            tokenBMint: tokenBMint,
+            lpMint: lpMint,
+            userLp: userLp,
+            lpLock: lpLock,
+            user: wallet.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+        expect.fail("Should have failed with ZeroAmount");
+      } catch (error) {
+        expect(error.message).to.include("ZeroAmount");
+      }
+    }
+  });
+
+  it("Rejects a reserve account the pool does not own", async () => {
+    // right mint, but owned by the depositor instead of the pool pda
+    const foreignReserve = await createAccount(
+      provider.connection,
+      wallet.payer,
+      tokenAMint,
+      wallet.publicKey,
+      Keypair.generate()
+    );
+    try {
+      await program.methods
+        .addLiquidity(new anchor.BN(1_000_000), new anchor.BN(1_000_000))
+        .accounts({
+          pool: poolPda,
+          userTokenA: userTokenA,
+          userTokenB: userTokenB,
+          tokenAReserve: foreignReserve,
+          tokenBReserve: poolTokenBReserve,
+          tokenAMint: tokenAMint,
+          tokenBMint: tokenBMint,
+          lpMint: lpMint,
+          userLp: userLp,
+          lpLock: lpLock,
+          user: wallet.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      expect.fail("Should have failed the reserve owner constraint");
+    } catch (error) {
+      expect(error.message).to.include("ConstraintRaw");
+      expect(error.message).to.include("token_a_reserve");
+    }
+  });
+
+  it("Removes liquidity", async () => {
+    const before = await program.account.pool.fetch(poolPda);
+    const lpBalance = await provider.connection.getTokenAccountBalance(userLp);
+    const lpAmount = new anchor.BN(lpBalance.value.amount).divn(10);
+
+    await program.methods
+      .removeLiquidity(lpAmount, new anchor.BN(0), new anchor.BN(0))
+      .accounts({
+        pool: poolPda,
+        userTokenA: userTokenA,
+        userTokenB: userTokenB,
+        tokenAReserve: poolTokenAReserve,
+        tokenBReserve: poolTokenBReserve,
+        tokenAMint: tokenAMint,
+        tokenBMint: tokenBMint,
+        lpMint: lpMint,
+        userLp: userLp,
+        user: wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const after = await program.account.pool.fetch(poolPda);
+    expect(after.tokenAReserve.lt(before.tokenAReserve)).to.equal(true);
+    expect(after.tokenBReserve.lt(before.tokenBReserve)).to.equal(true);
+  });
+
+  it("Closes a pool after its liquidity is removed", async () => {
+    let [mintA, mintB] = [
+      await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6),
+      await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6),
+    ];
+    if (Buffer.compare(mintA.toBuffer(), mintB.toBuffer()) > 0) {
+      [mintA, mintB] = [mintB, mintA];
+    }
+    const [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer()],
+      program.programId
+    );
+    const [poolLpMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_mint"), pool.toBuffer()],
+      program.programId
+    );
+    const [poolLpLock] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_lock"), pool.toBuffer()],
+      program.programId
+    );
+    const reserveA = getAssociatedTokenAddressSync(mintA, pool, true);
+    const reserveB = getAssociatedTokenAddressSync(mintB, pool, true);
+
+    await program.methods
+      .createPoolWithReserves(true, 30, EXPECTED_VERIFIER, 0)
+      .accounts({
+        pool,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        lpMint: poolLpMint,
+        lpLock: poolLpLock,
+        tokenAReserve: reserveA,
+        tokenBReserve: reserveB,
+        user: wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const deposit = 1_000_000;
+    const [ownA, ownB, ownLp] = await Promise.all(
+      [mintA, mintB, poolLpMint].map(async (mint) =>
+        (await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mint, wallet.publicKey)).address
+      )
+    );
+    await mintTo(provider.connection, wallet.payer, mintA, ownA, wallet.publicKey, deposit);
+    await mintTo(provider.connection, wallet.payer, mintB, ownB, wallet.publicKey, deposit);
+
+    await program.methods
+      .addLiquidity(new anchor.BN(deposit), new anchor.BN(deposit))
+      .accounts({
+        pool,
+        userTokenA: ownA,
+        userTokenB: ownB,
+        tokenAReserve: reserveA,
+        tokenBReserve: reserveB,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        lpMint: poolLpMint,
+        userLp: ownLp,
+        lpLock: poolLpLock,
+        user: wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const shares = (await getAccount(provider.connection, ownLp)).amount;
+    await program.methods
+      .removeLiquidity(new anchor.BN(shares.toString()), new anchor.BN(0), new anchor.BN(0))
+      .accounts({
+        pool,
+        userTokenA: ownA,
+        userTokenB: ownB,
+        tokenAReserve: reserveA,
+        tokenBReserve: reserveB,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        lpMint: poolLpMint,
+        userLp: ownLp,
+        user: wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // the locked floor still backs some of both tokens
+    const left = await program.account.pool.fetch(pool);
+    expect(left.tokenAReserve.toNumber()).to.be.greaterThan(0);
+    expect(left.tokenBReserve.toNumber()).to.be.greaterThan(0);
+
+    await program.methods
+      .closePool()
+      .accounts({
+        pool,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        tokenAReserve: reserveA,
+        tokenBReserve: reserveB,
+        lpMint: poolLpMint,
+        lpLock: poolLpLock,
+        authorityTokenA: ownA,
+        authorityTokenB: ownB,
+        authority: wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(pool)).to.equal(null);
+    expect(await provider.connection.getAccountInfo(reserveA)).to.equal(null);
+    // the sweep hands the floor's dust back, so the depositor ends whole
+    expect((await getAccount(provider.connection, ownA)).amount === BigInt(deposit)).to.equal(true);
+    expect((await getAccount(provider.connection, ownB)).amount === BigInt(deposit)).to.equal(true);
+  });
+
   it("Executes ZK swap (Expect Failure due to Invalid Root)", async () => {
     // Note: This test uses a mock verifier
     // In production, you'd deploy the actual Sunspot verifier
@@ -253,13 +609,15 @@ describe("zkgate", () => {
 
     try {
       const tx = await program.methods
-        .zkSwap(amountIn, minOut, mockProof, mockPublicInputs)
+        .zkSwap(amountIn, { minOut, acceptAnyOutput: false, maxImpactBps: null, deadline: deadline() }, mockProof, mockPublicInputs)
         .accounts({
           pool: poolPda,
           userTokenA: userTokenA,
           userTokenB: userTokenB,
           tokenAReserve: poolTokenAReserve,
           tokenBReserve: poolTokenBReserve,
+          tokenAMint: tokenAMint,
+          tokenBMint: tokenBMint,
           user: wallet.publicKey,
           verifierProgram: mockVerifier,
           verifierState: mockVerifierState,
@@ -283,6 +641,170 @@ describe("zkgate", () => {
     }
   });
 
+  it("Lets the pool authority toggle test mode", async () => {
+    // the flag is stored either way, the verifier bypass only exists in
+    // builds with the test-mode feature (`just test-mode`)
+    await program.methods
+      .setTestMode(true)
+      .accounts({
+        pool: poolPda,
+        authority: wallet.publicKey,
+      })
+      .rpc();
+    let pool = await program.account.pool.fetch(poolPda);
+    expect(pool.testMode).to.equal(true);
+
+    await program.methods
+      .setTestMode(false)
+      .accounts({
+        pool: poolPda,
+        authority: wallet.publicKey,
+      })
+      .rpc();
+    pool = await program.account.pool.fetch(poolPda);
+    expect(pool.testMode).to.equal(false);
+  });
+
+  it("Pays a zk swap out to a recipient other than the user", async function () {
+    // the swap needs the verifier bypass, which only `just test-mode` builds
+    const recipient = Keypair.generate();
+    const recipientTokenB = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        wallet.payer,
+        tokenBMint,
+        recipient.publicKey
+      )
+    ).address;
+    const root = Buffer.alloc(32, 7);
+    await program.methods
+      .updateRoots(Array.from(root))
+      .accounts({ history: historyKp.publicKey, authority: wallet.publicKey })
+      .rpc();
+    await program.methods
+      .setTestMode(true)
+      .accounts({ pool: poolPda, authority: wallet.publicKey })
+      .rpc();
+
+    const userBBefore = (await getAccount(provider.connection, userTokenB)).amount;
+    try {
+      await program.methods
+        .zkSwap(
+          new anchor.BN(1_000_000),
+          { minOut: new anchor.BN(1), acceptAnyOutput: false, maxImpactBps: null, deadline: deadline() },
+          Buffer.alloc(256),
+          root
+        )
+        .accounts({
+          pool: poolPda,
+          userTokenA: userTokenA,
+          userTokenB: userTokenB,
+          tokenAReserve: poolTokenAReserve,
+          tokenBReserve: poolTokenBReserve,
+          tokenAMint: tokenAMint,
+          tokenBMint: tokenBMint,
+          user: wallet.publicKey,
+          verifierProgram: SystemProgram.programId,
+          verifierState: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          history: historyKp.publicKey,
+          recipientTokenB: recipientTokenB,
+        })
+        .rpc();
+    } catch (error) {
+      if (error.message.includes("InvalidVerifier")) {
+        this.skip();
+      }
+      throw error;
+    } finally {
+      await program.methods
+        .setTestMode(false)
+        .accounts({ pool: poolPda, authority: wallet.publicKey })
+        .rpc();
+    }
+
+    const received = (await getAccount(provider.connection, recipientTokenB)).amount;
+    expect(received > BigInt(0)).to.equal(true);
+    const userBAfter = (await getAccount(provider.connection, userTokenB)).amount;
+    expect(userBAfter === userBBefore).to.equal(true);
+  });
+
+  it("Rejects swaps on a paused pool but still serves views", async () => {
+    await program.methods
+      .setPause(true)
+      .accounts({ pool: poolPda, authority: wallet.publicKey })
+      .rpc();
+
+    try {
+      await program.methods
+        .zkSwap(
+          new anchor.BN(1_000_000),
+          { minOut: new anchor.BN(0), acceptAnyOutput: true, maxImpactBps: null, deadline: deadline() },
+          Buffer.alloc(256),
+          Buffer.alloc(32)
+        )
+        .accounts({
+          pool: poolPda,
+          userTokenA: userTokenA,
+          userTokenB: userTokenB,
+          tokenAReserve: poolTokenAReserve,
+          tokenBReserve: poolTokenBReserve,
+          tokenAMint: tokenAMint,
+          tokenBMint: tokenBMint,
+          user: wallet.publicKey,
+          verifierProgram: SystemProgram.programId,
+          verifierState: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          history: historyKp.publicKey,
+        })
+        .rpc();
+      expect.fail("Should have failed with PoolPaused");
+    } catch (error) {
+      expect(error.message).to.include("PoolPaused");
+    }
+
+    const info = await program.methods
+      .getPoolInfo()
+      .accounts({ pool: poolPda })
+      .view();
+    expect(info.tokenAMint.toBase58()).to.equal(tokenAMint.toBase58());
+
+    await program.methods
+      .setPause(false)
+      .accounts({ pool: poolPda, authority: wallet.publicKey })
+      .rpc();
+  });
+
+  it("Rejects min_out of zero unless accept_any_output is set", async () => {
+    try {
+      await program.methods
+        .zkSwap(
+          new anchor.BN(1_000_000),
+          { minOut: new anchor.BN(0), acceptAnyOutput: false, maxImpactBps: null, deadline: deadline() },
+          Buffer.alloc(256),
+          Buffer.alloc(32)
+        )
+        .accounts({
+          pool: poolPda,
+          userTokenA: userTokenA,
+          userTokenB: userTokenB,
+          tokenAReserve: poolTokenAReserve,
+          tokenBReserve: poolTokenBReserve,
+          tokenAMint: tokenAMint,
+          tokenBMint: tokenBMint,
+          user: wallet.publicKey,
+          verifierProgram: SystemProgram.programId,
+          verifierState: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          history: historyKp.publicKey,
+        })
+        .rpc();
+      expect.fail("Should have failed with SlippageProtectionRequired");
+    } catch (error) {
+      expect(error.message).to.include("SlippageProtectionRequired");
+    }
+  });
+
   it("Calculates correct swap output", async () => {
     // Test the AMM formula: (amount_in * 997 * reserve_out) / (reserve_in * 1000 + amount_in * 997)
     const pool = await program.account.pool.fetch(poolPda);
@@ -319,13 +841,15 @@ describe("zkgate", () => {
 
     try {
       await program.methods
-        .zkSwap(amountIn, minOut, mockProof, mockPublicInputs)
+        .zkSwap(amountIn, { minOut, acceptAnyOutput: false, maxImpactBps: null, deadline: deadline() }, mockProof, mockPublicInputs)
         .accounts({
           pool: poolPda,
           userTokenA: userTokenA,
           userTokenB: userTokenB,
           tokenAReserve: poolTokenAReserve,
           tokenBReserve: poolTokenBReserve,
+          tokenAMint: tokenAMint,
+          tokenBMint: tokenBMint,
           user: wallet.publicKey,
           verifierProgram: mockVerifier,
           verifierState: mockVerifierState,